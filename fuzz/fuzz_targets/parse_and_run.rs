@@ -0,0 +1,27 @@
+//! Parse, format and run arbitrary scripts, any input must end in a value or a typed error
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mybash::{formatter, Interpreter, Limits, OutputBuffer, Sandbox};
+use std::{io::Cursor, time::Duration};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    let _ = formatter::format(source);
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_sandbox(Sandbox::deny_all());
+    interpreter.set_limits(Limits {
+        max_steps: Some(10_000),
+        timeout: Some(Duration::from_secs(1)),
+        max_memory: Some(1 << 20),
+        max_output: Some(1 << 16),
+        max_depth: Some(32),
+    });
+    interpreter.set_stdout(OutputBuffer::default());
+    interpreter.set_stderr(OutputBuffer::default());
+    interpreter.set_stdin(Cursor::new(Vec::new()));
+    let _ = interpreter.eval_str(source);
+});
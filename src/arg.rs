@@ -0,0 +1,188 @@
+use crate::{call::Call, cmp::CompareExpr, template::Template};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// A comparison operand, classified once when the script is parsed
+///
+/// Example
+/// ```text
+/// age          =>  the variable `age`, or the literal if no variable has that name
+/// $1           =>  a single expansion, looked up like a variable
+/// ${dir}/logs  =>  a template, expanded and then parsed like a literal
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct Operand {
+    /// The operand as written
+    pub template: Template,
+    /// Whether the operand expands variables, other than as a single `$var`
+    pub is_template: bool,
+}
+
+impl Operand {
+    pub fn parse<T: Into<String>>(source: T) -> Self {
+        let template = Template::parse(source);
+        let is_template = template.has_vars()
+            && Template::parse(template.source().trim())
+                .single_var()
+                .is_none();
+
+        Self {
+            template,
+            is_template,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        self.template.source()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.source().is_empty()
+    }
+}
+
+impl From<&str> for Operand {
+    fn from(s: &str) -> Self {
+        Operand::parse(s)
+    }
+}
+
+impl From<String> for Operand {
+    fn from(s: String) -> Self {
+        Operand::parse(s)
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source())
+    }
+}
+
+/// What a call argument is, decided when the script is parsed
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum ArgKind {
+    /// `"Hello $name"` or `'Hello'`, holds the string without its quotes
+    Quoted(Template),
+    /// A nested call `ex: read_file("notes.txt")`
+    Call(Box<Call>),
+    /// A comparison `ex: count > 2`, built-ins taking a condition and `case` guards compare
+    /// it, anywhere else it's an operand
+    Compare(Box<CompareExpr>, Operand),
+    /// A variable, a path into one or a literal `ex: name`, `err.message`, `42`
+    Operand(Operand),
+}
+
+/// A call argument, a `run` argument, the value of a `match` or a `case` guard
+///
+/// Example
+/// ```text
+/// "notes.txt"          =>  Quoted([Lit("notes.txt")])
+/// read_file("a.txt")   =>  Call(read_file, ["\"a.txt\""])
+/// n > 10               =>  Compare(n > 10)
+/// err.message          =>  Operand(err.message)
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Arg {
+    /// The argument as written, quotes included
+    source: String,
+    kind: ArgKind,
+}
+
+impl Arg {
+    pub fn parse<T: Into<String>>(source: T) -> Self {
+        let source = source.into();
+        let arg = source.trim();
+        let is_quoted = arg.len() >= 2
+            && (arg.starts_with('"') && arg.ends_with('"')
+                || arg.starts_with('\'') && arg.ends_with('\''));
+
+        let kind = if is_quoted {
+            ArgKind::Quoted(Template::parse(&arg[1..arg.len() - 1]))
+        } else if let Some(call) = Call::is_call(arg)
+            .then(|| arg.parse::<Call>().ok())
+            .flatten()
+        {
+            ArgKind::Call(Box::new(call))
+        } else if let Ok(cmp) = arg.parse::<CompareExpr>() {
+            ArgKind::Compare(Box::new(cmp), Operand::parse(arg))
+        } else {
+            ArgKind::Operand(Operand::parse(arg))
+        };
+
+        Self { source, kind }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn kind(&self) -> &ArgKind {
+        &self.kind
+    }
+}
+
+impl From<&str> for Arg {
+    fn from(s: &str) -> Self {
+        Arg::parse(s)
+    }
+}
+
+impl From<String> for Arg {
+    fn from(s: String) -> Self {
+        Arg::parse(s)
+    }
+}
+
+impl PartialEq<str> for Arg {
+    fn eq(&self, other: &str) -> bool {
+        self.source == other
+    }
+}
+
+impl PartialEq<&str> for Arg {
+    fn eq(&self, other: &&str) -> bool {
+        self.source == *other
+    }
+}
+
+impl Display for Arg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Arg, ArgKind, Operand};
+    use crate::{call::Call, template::Template};
+
+    #[test]
+    fn classify_operands() {
+        assert!(!Operand::parse("age").is_template);
+        assert!(!Operand::parse("$1").is_template);
+        assert!(!Operand::parse(" ${name} ").is_template);
+        assert!(Operand::parse("${dir}/logs").is_template);
+    }
+
+    #[test]
+    fn classify_args() {
+        assert_eq!(
+            Arg::parse(" \"Hi $name\"").kind(),
+            &ArgKind::Quoted(Template::parse("Hi $name"))
+        );
+        assert_eq!(
+            Arg::parse("read_file('a')").kind(),
+            &ArgKind::Call(Box::new(Call::new("read_file", vec!["'a'".into()])))
+        );
+        assert!(matches!(
+            Arg::parse("count > 2").kind(),
+            ArgKind::Compare(cmp, _) if cmp.left.source() == "count"
+        ));
+        assert_eq!(
+            Arg::parse("err.message").kind(),
+            &ArgKind::Operand(Operand::parse("err.message"))
+        );
+        assert_eq!(Arg::parse(" 'a' ").to_string(), " 'a' ");
+    }
+}
@@ -0,0 +1,349 @@
+use crate::{
+    arg,
+    cmp::{CompareExpr, Operator},
+    conditions::Condition,
+    echo::Echo,
+    executor::parse_operand,
+    lang_parser::{Expression, LangParser},
+    template::{Segment, Template},
+    try_catch::{Throw, TryCatch},
+    variables::{split_path, CallVariable, VarValue, Variable},
+};
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CompileErr {
+    #[error("`{1}` at line {0} is not supported by the VM yet")]
+    Unsupported(usize, String),
+}
+
+/// A variable reference resolved at compile time
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Name {
+    /// A script variable
+    Slot(usize),
+    /// A map field or list item of a script variable `ex: err.message`, `files[0]`
+    Field(usize, Vec<String>),
+    /// `$?`, `$!`, positional arguments and environment variables
+    Special(String),
+}
+
+/// A piece of a string with variable expansions `ex: "Hello, $name"`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Part {
+    Lit(String),
+    /// The variable and its name as written, used when the variable isn't set
+    Var(Name, String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Op {
+    /// Start of a statement at the given source line
+    Line(usize),
+    Push(VarValue),
+    /// Push the variable, or the fallback if it isn't set
+    Load(Name, VarValue),
+    /// Push the expanded string
+    Expand(Vec<Part>),
+    /// Pop a string and push the variable it names, or the string parsed as a value
+    Resolve,
+    /// Pop a string and push it parsed as a comparison operand
+    ParseOperand,
+    Store(usize),
+    Echo,
+    /// Pop the right and left operands and push the result
+    Compare(Operator),
+    JumpIfFalse(usize),
+    Jump(usize),
+    Throw,
+    /// Errors until the matching `TryEnd` jump to `catch` with the error stored in `slot`
+    TryStart {
+        catch: usize,
+        slot: usize,
+    },
+    TryEnd,
+    /// Enter a nested block
+    Enter,
+    Leave,
+}
+
+/// A comparison operand, either known at compile time or the code computing it
+enum Operand {
+    Const(VarValue),
+    Code(Vec<Op>),
+}
+
+/// Compiled script
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Program {
+    pub ops: Vec<Op>,
+    /// Variable name of every slot
+    pub names: Vec<String>,
+}
+
+/// Compiles the AST into bytecode. Variables get slots, strings without expansions and
+/// comparisons between constants are evaluated at compile time
+pub struct Compiler {
+    program: Program,
+    slots: HashMap<String, usize>,
+}
+
+impl Compiler {
+    /// Compile `ast`. `globals` are variables that already exist (`ex: set by the embedder`)
+    pub fn compile<'a, I>(ast: &LangParser, globals: I) -> Result<Program, CompileErr>
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        let mut compiler = Compiler {
+            program: Program::default(),
+            slots: HashMap::new(),
+        };
+        globals.into_iter().for_each(|name| {
+            compiler.slot(name);
+        });
+        compiler.declare(ast);
+        compiler.block(ast)?;
+        Ok(compiler.program)
+    }
+
+    fn slot(&mut self, name: &str) -> usize {
+        if let Some(slot) = self.slots.get(name) {
+            return *slot;
+        }
+
+        let slot = self.program.names.len();
+        self.program.names.push(name.to_string());
+        self.slots.insert(name.to_string(), slot);
+        slot
+    }
+
+    /// Give every variable assigned anywhere in `block` a slot, so lookups can be
+    /// resolved no matter where the variable is assigned
+    fn declare(&mut self, block: &LangParser) {
+        for expr in block.experssions.iter() {
+            self.declare_expr(expr);
+        }
+    }
+
+    fn declare_expr(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Var(Variable { name, .. })
+            | Expression::CallVar(CallVariable { name, .. }) => {
+                self.slot(name);
+            }
+            Expression::TryCatch(try_catch) => {
+                self.slot(&try_catch.err_name);
+                self.declare(&try_catch.body);
+                self.declare(&try_catch.catch_body);
+            }
+            Expression::Condition(con) => {
+                self.declare_expr(&con.if_expr);
+                if let Some(expr) = &con.else_expr {
+                    self.declare_expr(expr);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.program.ops.push(op);
+        self.program.ops.len() - 1
+    }
+
+    /// Point the jump at `idx` to the next op
+    fn patch(&mut self, idx: usize) {
+        let target = self.program.ops.len();
+        match &mut self.program.ops[idx] {
+            Op::Jump(to) | Op::JumpIfFalse(to) | Op::TryStart { catch: to, .. } => *to = target,
+            op => unreachable!("`{:?}` is not a jump", op),
+        }
+    }
+
+    fn block(&mut self, block: &LangParser) -> Result<(), CompileErr> {
+        self.emit(Op::Enter);
+        for (expr, line) in block.experssions.iter().zip(&block.lines) {
+            self.emit(Op::Line(*line));
+            self.expr(expr, *line)?;
+        }
+        self.emit(Op::Leave);
+
+        Ok(())
+    }
+
+    fn expr(&mut self, expr: &Expression, line: usize) -> Result<(), CompileErr> {
+        match expr {
+            Expression::Var(Variable { name, template, .. }) => {
+                self.value(template);
+                let slot = self.slots[name];
+                self.emit(Op::Store(slot));
+            }
+            Expression::Echo(Echo(s)) => {
+                self.value(s);
+                self.emit(Op::Echo);
+            }
+            Expression::Throw(Throw(s)) => {
+                self.value(s);
+                self.emit(Op::Throw);
+            }
+            Expression::Condition(con) => self.condition(con, line)?,
+            Expression::TryCatch(try_catch) => self.try_catch(try_catch)?,
+            Expression::Call(call) | Expression::CallVar(CallVariable { call, .. }) => {
+                return Err(CompileErr::Unsupported(line, format!("{}()", call.name)))
+            }
+            Expression::Trap(_) => return Err(CompileErr::Unsupported(line, "trap".into())),
+            Expression::Defer(_) => return Err(CompileErr::Unsupported(line, "defer".into())),
+            Expression::Run(_) => return Err(CompileErr::Unsupported(line, "run".into())),
+            Expression::Function(_) => return Err(CompileErr::Unsupported(line, "fn".into())),
+            Expression::Match(_) => return Err(CompileErr::Unsupported(line, "match".into())),
+            Expression::Scoped(scoped) => {
+                return Err(CompileErr::Unsupported(line, scoped.kind.to_string()))
+            }
+            Expression::Readonly(readonly) => {
+                return Err(CompileErr::Unsupported(line, readonly.kind.to_string()))
+            }
+            Expression::SetTrace(_) => {
+                return Err(CompileErr::Unsupported(line, "set trace".into()))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn condition(&mut self, con: &Condition, line: usize) -> Result<(), CompileErr> {
+        let Condition {
+            condition,
+            if_expr,
+            else_expr,
+        } = con;
+        let CompareExpr {
+            left,
+            right,
+            operator,
+        } = condition;
+
+        let left = self.operand(left);
+        let right = self.operand(right);
+
+        // Both sides are known, only the taken branch is emitted
+        if let (Operand::Const(left), Operand::Const(right)) = (&left, &right) {
+            if !operator.is_file_test() {
+                if let Ok(is_true) = CompareExpr::cmp(left, right, operator) {
+                    return match (is_true, else_expr) {
+                        (true, _) => self.expr(if_expr, line),
+                        (false, Some(else_expr)) => self.expr(else_expr, line),
+                        (false, None) => Ok(()),
+                    };
+                }
+            }
+        }
+
+        for operand in [left, right] {
+            match operand {
+                Operand::Const(value) => self.program.ops.push(Op::Push(value)),
+                Operand::Code(ops) => self.program.ops.extend(ops),
+            }
+        }
+
+        self.emit(Op::Compare(operator.clone()));
+        let jump_else = self.emit(Op::JumpIfFalse(0));
+        self.expr(if_expr, line)?;
+        match else_expr {
+            Some(else_expr) => {
+                let jump_end = self.emit(Op::Jump(0));
+                self.patch(jump_else);
+                self.expr(else_expr, line)?;
+                self.patch(jump_end);
+            }
+            None => self.patch(jump_else),
+        }
+
+        Ok(())
+    }
+
+    fn try_catch(&mut self, try_catch: &TryCatch) -> Result<(), CompileErr> {
+        let TryCatch {
+            body,
+            err_name,
+            catch_body,
+        } = try_catch;
+
+        let slot = self.slots[err_name];
+        let try_start = self.emit(Op::TryStart { catch: 0, slot });
+        self.block(body)?;
+        self.emit(Op::TryEnd);
+        let jump_end = self.emit(Op::Jump(0));
+        self.patch(try_start);
+        self.block(catch_body)?;
+        self.patch(jump_end);
+
+        Ok(())
+    }
+
+    /// Resolve a variable name, names that are never assigned can only be special variables
+    fn name(&self, s: &str) -> Option<Name> {
+        if let Some(slot) = self.slots.get(s) {
+            return Some(Name::Slot(*slot));
+        }
+
+        let (base, path) = split_path(s).filter(|(_, path)| !path.is_empty())?;
+        let slot = self.slots.get(base)?;
+        Some(Name::Field(*slot, path))
+    }
+
+    fn parts(&self, template: &Template) -> Vec<Part> {
+        template
+            .segments()
+            .iter()
+            .map(|segment| match segment {
+                Segment::Lit(lit) => Part::Lit(lit.clone()),
+                Segment::Var(var) => {
+                    let name = self.name(var).unwrap_or_else(|| Name::Special(var.clone()));
+                    Part::Var(name, var.clone())
+                }
+            })
+            .collect()
+    }
+
+    /// Push the value of `template` the way `Executor::eval_var_expansion` evaluates it
+    fn value(&mut self, template: &Template) {
+        if template.has_vars() {
+            let parts = self.parts(template);
+            self.emit(Op::Expand(parts));
+            self.emit(Op::Resolve);
+            return;
+        }
+
+        let s = template.source();
+        let fallback = VarValue::from_literal(s);
+        match self.name(s) {
+            Some(name) => self.emit(Op::Load(name, fallback)),
+            None => self.emit(Op::Push(fallback)),
+        };
+    }
+
+    /// A comparison operand evaluated the way `Executor::found_var_or_create` does
+    fn operand(&self, operand: &arg::Operand) -> Operand {
+        let (s, template) = (operand.source(), &operand.template);
+        let trimmed = s.trim();
+        let whole_var = template.has_vars().then_some(!operand.is_template);
+
+        match whole_var {
+            // A single `$var` is looked up without its `$`, script variables aren't
+            // considered
+            Some(true) => Operand::Code(vec![Op::Load(
+                Name::Special(trimmed[1..].to_string()),
+                VarValue::Str("".into()),
+            )]),
+            Some(false) => Operand::Code(vec![Op::Expand(self.parts(template)), Op::ParseOperand]),
+            None => {
+                let fallback = parse_operand(s.to_string());
+                match self.name(trimmed) {
+                    Some(name) => Operand::Code(vec![Op::Load(name, fallback)]),
+                    None => Operand::Const(fallback),
+                }
+            }
+        }
+    }
+}
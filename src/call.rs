@@ -0,0 +1,128 @@
+use crate::{
+    arg::Arg,
+    regex::{cached_regex, RE_CALL, RE_COMMAND},
+    utils::split_args,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CallErr {
+    #[error("`{0}` is not a valid function call")]
+    InvalidCall(String),
+}
+
+/// A call to a built-in function
+///
+/// Example
+/// ```text
+/// write_file("notes.txt", content)
+/// mkdir -p "logs/today"
+/// ```
+/// Arguments are parsed once and keep their source as written (quotes included),
+/// command style calls (`mkdir`, `rm`, `cp`, `mv`, `wait`, `jobs`, `kill`) keep their flags
+/// as leading arguments
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Call {
+    pub name: String,
+    pub args: Vec<Arg>,
+}
+
+impl Call {
+    pub fn new<T: Into<String>>(name: T, args: Vec<String>) -> Self {
+        Self {
+            name: name.into(),
+            args: args.into_iter().map(Arg::parse).collect(),
+        }
+    }
+
+    pub fn is_call(s: &str) -> bool {
+        let s = s.trim();
+        cached_regex!(RE_CALL).is_match(s) || cached_regex!(RE_COMMAND).is_match(s)
+    }
+}
+
+impl FromStr for Call {
+    type Err = CallErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Some(caps) = cached_regex!(RE_CALL).captures(s) {
+            return Ok(Self::new(
+                &caps["name"],
+                split_args(&caps["args"], Some(',')),
+            ));
+        }
+
+        if let Some(caps) = cached_regex!(RE_COMMAND).captures(s) {
+            let args = caps
+                .name("args")
+                .map_or(vec![], |args| split_args(args.as_str(), None));
+            return Ok(Self::new(&caps["name"], args));
+        }
+
+        Err(CallErr::InvalidCall(s.into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Call, CallErr};
+    use crate::arg::Arg;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn sources(args: &[Arg]) -> Vec<&str> {
+        args.iter().map(Arg::source).collect()
+    }
+
+    #[test]
+    fn parse_function_call() {
+        let call = "write_file(\"notes.txt\", content)"
+            .parse::<Call>()
+            .unwrap();
+        assert_eq!(
+            call,
+            Call::new("write_file", args(&["\"notes.txt\"", "content"]))
+        );
+
+        let call = "input()".parse::<Call>().unwrap();
+        assert_eq!(call, Call::new("input", vec![]));
+    }
+
+    #[test]
+    fn parse_call_with_commas_in_args() {
+        let call = "write_file('out.txt', \"a, b\", read_file(\"x, y\"))"
+            .parse::<Call>()
+            .unwrap();
+        assert_eq!(
+            sources(&call.args),
+            ["'out.txt'", "\"a, b\"", "read_file(\"x, y\")"]
+        );
+    }
+
+    #[test]
+    fn parse_command_style_call() {
+        let call = "mkdir -p \"my logs/today\"".parse::<Call>().unwrap();
+        assert_eq!(call, Call::new("mkdir", args(&["-p", "\"my logs/today\""])));
+
+        let call = "cp a.txt  backup/".parse::<Call>().unwrap();
+        assert_eq!(call, Call::new("cp", args(&["a.txt", "backup/"])));
+
+        let call = "wait".parse::<Call>().unwrap();
+        assert_eq!(call, Call::new("wait", vec![]));
+    }
+
+    #[test]
+    fn parse_invalid_call() {
+        assert!(!Call::is_call("echo \"read_file(x)\""));
+        assert_eq!(
+            "not a call".parse::<Call>().err().unwrap(),
+            CallErr::InvalidCall("not a call".into())
+        );
+    }
+}
@@ -0,0 +1,503 @@
+use mybash::Limits;
+use std::{path::Path, time::Duration};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CliErr {
+    #[error("Missing file path: `{0}`")]
+    MissingFilePath(String),
+    #[error("`{0}` expects a value")]
+    MissingValue(String),
+    #[error("`{1}` is not a valid value for `{0}`")]
+    InvalidValue(String, String),
+    #[error("Unknown option `{0}`")]
+    UnknownOption(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    /// Parse the script and write the AST to `output`
+    Compile {
+        output: String,
+    },
+    /// Print the AST as JSON instead of running the script
+    DumpAst,
+    /// Format the scripts in place, or only report unformatted ones with `check`
+    Fmt {
+        check: bool,
+        paths: Vec<String>,
+    },
+    /// Report problems in the scripts, as JSON with `json`
+    Lint {
+        json: bool,
+        paths: Vec<String>,
+    },
+    /// Run the `test_*` functions of the `*_test.mb` files in `paths`
+    Test {
+        paths: Vec<String>,
+    },
+    /// Serve the language server protocol over stdio
+    Lsp,
+    /// Run the script under the console debugger, or serve the debug adapter protocol
+    /// over stdio with `dap`
+    Debug {
+        dap: bool,
+    },
+}
+
+/// Command line options
+///
+/// Example
+/// ```text
+/// mybash [--vm] [--trace] [--dump-ast=json] [--max-steps N] [--timeout 10s] [--max-memory 64M] [--max-output 1M] [--max-depth N] script.mb [args...]
+/// mybash compile script.mb [-o script.mbc]
+/// mybash fmt [--check] scripts...
+/// mybash lint [--json] scripts...
+/// mybash test [paths...]
+/// mybash lsp
+/// mybash debug script.mb [args...]
+/// mybash debug --dap
+/// ```
+/// Options must come before the script path, everything after it is passed to the script.
+/// Option values can also be given as `--option=value`
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cli {
+    pub command: Command,
+    pub file_path: String,
+    pub args: Vec<String>,
+    pub limits: Limits,
+    /// Run on the bytecode VM
+    pub vm: bool,
+    /// Print every statement to stderr before it runs
+    pub trace: bool,
+}
+
+impl Cli {
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, CliErr> {
+        let mut args = args.into_iter().peekable();
+        match args.peek().map(String::as_str) {
+            Some("compile") => {
+                args.next();
+                return Cli::parse_compile(args);
+            }
+            Some("fmt") => {
+                args.next();
+                return Cli::parse_fmt(args);
+            }
+            Some("lint") => {
+                args.next();
+                return Cli::parse_lint(args);
+            }
+            Some("test") => {
+                args.next();
+                return Cli::parse_test(args);
+            }
+            Some("debug") => {
+                args.next();
+                return Cli::parse_debug(args);
+            }
+            Some("lsp") => {
+                args.next();
+                if let Some(arg) = args.next() {
+                    return Err(CliErr::UnknownOption(arg));
+                }
+                return Ok(Self {
+                    command: Command::Lsp,
+                    file_path: String::new(),
+                    args: Vec::new(),
+                    limits: Limits::default(),
+                    vm: false,
+                    trace: false,
+                });
+            }
+            _ => {}
+        }
+
+        let mut command = Command::Run;
+        let mut limits = Limits::default();
+        let mut vm = false;
+        let mut trace = false;
+
+        let file_path = loop {
+            let Some(arg) = args.next() else {
+                return Err(CliErr::MissingFilePath(
+                    "example: mybash ./src/main.mb".into(),
+                ));
+            };
+            if !arg.starts_with("--") {
+                break arg;
+            }
+            if arg == "--vm" {
+                vm = true;
+                continue;
+            }
+            if arg == "--trace" {
+                trace = true;
+                continue;
+            }
+
+            let (arg, value) = match arg.split_once('=') {
+                Some((arg, value)) => (arg.to_string(), value.to_string()),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| CliErr::MissingValue(arg.clone()))?;
+                    (arg, value)
+                }
+            };
+            let invalid = || CliErr::InvalidValue(arg.clone(), value.clone());
+            match arg.as_str() {
+                "--dump-ast" if value == "json" => command = Command::DumpAst,
+                "--dump-ast" => return Err(invalid()),
+                "--max-steps" => limits.max_steps = Some(value.parse().map_err(|_| invalid())?),
+                "--timeout" => limits.timeout = Some(parse_duration(&value).ok_or_else(invalid)?),
+                "--max-memory" => limits.max_memory = Some(parse_size(&value).ok_or_else(invalid)?),
+                "--max-output" => limits.max_output = Some(parse_size(&value).ok_or_else(invalid)?),
+                "--max-depth" => limits.max_depth = Some(value.parse().map_err(|_| invalid())?),
+                _ => return Err(CliErr::UnknownOption(arg)),
+            }
+        };
+
+        Ok(Self {
+            command,
+            file_path,
+            args: args.collect(),
+            limits,
+            vm,
+            trace,
+        })
+    }
+
+    /// `compile script.mb [-o script.mbc]`, the output defaults to the script path with
+    /// the `.mbc` extension
+    fn parse_compile<I: Iterator<Item = String>>(mut args: I) -> Result<Self, CliErr> {
+        let mut file_path = None;
+        let mut output = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => {
+                    output = Some(args.next().ok_or(CliErr::MissingValue(arg))?);
+                }
+                _ if arg.starts_with('-') => return Err(CliErr::UnknownOption(arg)),
+                _ => file_path = Some(arg),
+            }
+        }
+
+        let file_path = file_path.ok_or_else(|| {
+            CliErr::MissingFilePath("example: mybash compile script.mb -o script.mbc".into())
+        })?;
+        let output = output.unwrap_or_else(|| {
+            Path::new(&file_path)
+                .with_extension("mbc")
+                .display()
+                .to_string()
+        });
+
+        Ok(Self {
+            command: Command::Compile { output },
+            file_path,
+            args: Vec::new(),
+            limits: Limits::default(),
+            vm: false,
+            trace: false,
+        })
+    }
+
+    /// `fmt [--check] scripts...`
+    fn parse_fmt<I: Iterator<Item = String>>(args: I) -> Result<Self, CliErr> {
+        let mut check = false;
+        let mut paths = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "--check" => check = true,
+                _ if arg.starts_with('-') => return Err(CliErr::UnknownOption(arg)),
+                _ => paths.push(arg),
+            }
+        }
+        if paths.is_empty() {
+            return Err(CliErr::MissingFilePath(
+                "example: mybash fmt --check scripts/*.mb".into(),
+            ));
+        }
+
+        Ok(Self {
+            command: Command::Fmt { check, paths },
+            file_path: String::new(),
+            args: Vec::new(),
+            limits: Limits::default(),
+            vm: false,
+            trace: false,
+        })
+    }
+
+    /// `lint [--json] scripts...`
+    fn parse_lint<I: Iterator<Item = String>>(args: I) -> Result<Self, CliErr> {
+        let mut json = false;
+        let mut paths = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "--json" => json = true,
+                _ if arg.starts_with('-') => return Err(CliErr::UnknownOption(arg)),
+                _ => paths.push(arg),
+            }
+        }
+        if paths.is_empty() {
+            return Err(CliErr::MissingFilePath(
+                "example: mybash lint --json scripts/*.mb".into(),
+            ));
+        }
+
+        Ok(Self {
+            command: Command::Lint { json, paths },
+            file_path: String::new(),
+            args: Vec::new(),
+            limits: Limits::default(),
+            vm: false,
+            trace: false,
+        })
+    }
+
+    /// `test [paths...]`, the current directory by default
+    fn parse_test<I: Iterator<Item = String>>(args: I) -> Result<Self, CliErr> {
+        let mut paths = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                _ if arg.starts_with('-') => return Err(CliErr::UnknownOption(arg)),
+                _ => paths.push(arg),
+            }
+        }
+        if paths.is_empty() {
+            paths.push(".".into());
+        }
+
+        Ok(Self {
+            command: Command::Test { paths },
+            file_path: String::new(),
+            args: Vec::new(),
+            limits: Limits::default(),
+            vm: false,
+            trace: false,
+        })
+    }
+
+    /// `debug script.mb [args...]` or `debug --dap`, the script of the adapter comes from
+    /// the client's `launch` request
+    fn parse_debug<I: Iterator<Item = String>>(mut args: I) -> Result<Self, CliErr> {
+        let (dap, file_path) = match args.next() {
+            Some(arg) if arg == "--dap" => match args.next() {
+                Some(arg) => return Err(CliErr::UnknownOption(arg)),
+                None => (true, String::new()),
+            },
+            Some(arg) if arg.starts_with('-') => return Err(CliErr::UnknownOption(arg)),
+            Some(arg) => (false, arg),
+            None => {
+                return Err(CliErr::MissingFilePath(
+                    "example: mybash debug script.mb".into(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            command: Command::Debug { dap },
+            file_path,
+            args: args.collect(),
+            limits: Limits::default(),
+            vm: false,
+            trace: false,
+        })
+    }
+}
+
+/// Parse a duration `ex: 500ms`, `10s`, `2m`. Plain numbers are seconds
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (num, unit) = split_unit(s);
+    let num = num.parse::<u64>().ok()?;
+    match unit {
+        "ms" => Some(Duration::from_millis(num)),
+        "" | "s" => Some(Duration::from_secs(num)),
+        "m" => num.checked_mul(60).map(Duration::from_secs),
+        _ => None,
+    }
+}
+
+/// Parse a size in bytes `ex: 512`, `64K`, `1M`, `2G`
+fn parse_size(s: &str) -> Option<usize> {
+    let (num, unit) = split_unit(s);
+    let num = num.parse::<usize>().ok()?;
+    let multiplier = match unit.to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return None,
+    };
+    num.checked_mul(multiplier)
+}
+
+fn split_unit(s: &str) -> (&str, &str) {
+    let idx = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(idx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_duration, parse_size, Cli, CliErr, Command};
+    use mybash::Limits;
+    use std::time::Duration;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_limits_and_script_args() {
+        let cli = Cli::parse(args(
+            "--max-steps 100 --vm --trace --timeout 2s --max-output 1K run.mb --max-steps x",
+        ))
+        .unwrap();
+        assert_eq!(
+            cli,
+            Cli {
+                command: Command::Run,
+                file_path: "run.mb".into(),
+                args: args("--max-steps x"),
+                limits: Limits {
+                    max_steps: Some(100),
+                    timeout: Some(Duration::from_secs(2)),
+                    max_output: Some(1024),
+                    ..Limits::default()
+                },
+                vm: true,
+                trace: true
+            }
+        );
+    }
+
+    #[test]
+    fn parse_compile_and_dump_ast() {
+        let cli = Cli::parse(args("compile scripts/deploy.mb")).unwrap();
+        assert_eq!(cli.file_path, "scripts/deploy.mb");
+        assert_eq!(
+            cli.command,
+            Command::Compile {
+                output: "scripts/deploy.mbc".into()
+            }
+        );
+        let cli = Cli::parse(args("compile -o out.mbc deploy.mb")).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Compile {
+                output: "out.mbc".into()
+            }
+        );
+
+        let cli = Cli::parse(args("--dump-ast=json --timeout=1s deploy.mbc")).unwrap();
+        assert_eq!(cli.command, Command::DumpAst);
+        assert_eq!(cli.limits.timeout, Some(Duration::from_secs(1)));
+        assert_eq!(
+            Cli::parse(args("--dump-ast=yaml deploy.mb")),
+            Err(CliErr::InvalidValue("--dump-ast".into(), "yaml".into()))
+        );
+    }
+
+    #[test]
+    fn parse_fmt() {
+        let cli = Cli::parse(args("fmt --check a.mb b.mb")).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Fmt {
+                check: true,
+                paths: args("a.mb b.mb")
+            }
+        );
+        assert!(matches!(
+            Cli::parse(args("fmt --check")),
+            Err(CliErr::MissingFilePath(_))
+        ));
+    }
+
+    #[test]
+    fn parse_lint() {
+        let cli = Cli::parse(args("lint a.mb --json")).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Lint {
+                json: true,
+                paths: args("a.mb")
+            }
+        );
+        assert_eq!(
+            Cli::parse(args("lint --fix a.mb")),
+            Err(CliErr::UnknownOption("--fix".into()))
+        );
+        assert_eq!(Cli::parse(args("lsp")).unwrap().command, Command::Lsp);
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(
+            Cli::parse(args("test")).unwrap().command,
+            Command::Test { paths: args(".") }
+        );
+        assert_eq!(
+            Cli::parse(args("test lib tests/strings_test.mb"))
+                .unwrap()
+                .command,
+            Command::Test {
+                paths: args("lib tests/strings_test.mb")
+            }
+        );
+        assert_eq!(
+            Cli::parse(args("test --filter lib")),
+            Err(CliErr::UnknownOption("--filter".into()))
+        );
+    }
+
+    #[test]
+    fn parse_debug() {
+        let cli = Cli::parse(args("debug script.mb --verbose")).unwrap();
+        assert_eq!(cli.command, Command::Debug { dap: false });
+        assert_eq!(cli.file_path, "script.mb");
+        assert_eq!(cli.args, args("--verbose"));
+        assert_eq!(
+            Cli::parse(args("debug --dap")).unwrap().command,
+            Command::Debug { dap: true }
+        );
+        assert_eq!(
+            Cli::parse(args("debug --dap script.mb")),
+            Err(CliErr::UnknownOption("script.mb".into()))
+        );
+    }
+
+    #[test]
+    fn reject_invalid_options() {
+        assert!(matches!(
+            Cli::parse(args("")),
+            Err(CliErr::MissingFilePath(_))
+        ));
+        assert_eq!(
+            Cli::parse(args("--timeout")),
+            Err(CliErr::MissingValue("--timeout".into()))
+        );
+        assert_eq!(
+            Cli::parse(args("--max-depth deep run.mb")),
+            Err(CliErr::InvalidValue("--max-depth".into(), "deep".into()))
+        );
+        assert_eq!(
+            Cli::parse(args("--fast run.mb")),
+            Err(CliErr::UnknownOption("--fast".into()))
+        );
+    }
+
+    #[test]
+    fn parse_units() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("3"), Some(Duration::from_secs(3)));
+        assert_eq!(parse_duration("1h"), None);
+        assert_eq!(parse_duration("999999999999999999m"), None);
+        assert_eq!(parse_size("64M"), Some(64 << 20));
+        assert_eq!(parse_size("12"), Some(12));
+        assert_eq!(parse_size("1T"), None);
+    }
+}
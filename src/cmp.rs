@@ -1,9 +1,12 @@
 use crate::{
-    regex::RE_CMP,
-    variables::{VarValue, Variable},
+    arg::Operand,
+    filesystem::file_test,
+    glob::{glob_match, is_glob},
+    regex::{cached_regex, RE_CMP, RE_FILE_TEST, RE_QUOTE_REMOVAL, RE_VAR_PATH},
+    variables::VarValue,
 };
-use regex::Regex;
-use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
 use thiserror::Error;
 
 #[derive(Debug, Error, PartialEq, Eq)]
@@ -11,14 +14,34 @@ pub enum OperatorErr {
     #[error("`{0}` is not a valid operator")]
     InvalidOperator(String),
 }
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum Operator {
     Eq,
     NotEq,
+    /// `~=` case-insensitive equality
+    EqIgnoreCase,
     Gt,
     GtEq,
     Lt,
     LtEq,
+    /// `-e` path exists
+    Exists,
+    /// `-f` path is a regular file
+    IsFile,
+    /// `-d` path is a directory
+    IsDir,
+    /// `-r` path is readable
+    IsReadable,
+    /// `-w` path is writable
+    IsWritable,
+    /// `-x` path is executable
+    IsExecutable,
+    /// `-s` path exists and is not empty
+    IsNotEmpty,
+    /// `-nt` left path is newer than the right one
+    NewerThan,
+    /// `-ot` left path is older than the right one
+    OlderThan,
 }
 
 impl FromStr for Operator {
@@ -27,10 +50,20 @@ impl FromStr for Operator {
         let op = match s.trim() {
             "==" => Self::Eq,
             "!=" => Self::NotEq,
+            "~=" => Self::EqIgnoreCase,
             ">" => Self::Gt,
             ">=" => Self::GtEq,
             "<" => Self::Lt,
             "<=" => Self::LtEq,
+            "-e" => Self::Exists,
+            "-f" => Self::IsFile,
+            "-d" => Self::IsDir,
+            "-r" => Self::IsReadable,
+            "-w" => Self::IsWritable,
+            "-x" => Self::IsExecutable,
+            "-s" => Self::IsNotEmpty,
+            "-nt" => Self::NewerThan,
+            "-ot" => Self::OlderThan,
             _ => return Err(OperatorErr::InvalidOperator(s.into())),
         };
 
@@ -38,6 +71,51 @@ impl FromStr for Operator {
     }
 }
 
+impl Display for Operator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            Self::Eq => "==",
+            Self::NotEq => "!=",
+            Self::EqIgnoreCase => "~=",
+            Self::Gt => ">",
+            Self::GtEq => ">=",
+            Self::Lt => "<",
+            Self::LtEq => "<=",
+            Self::Exists => "-e",
+            Self::IsFile => "-f",
+            Self::IsDir => "-d",
+            Self::IsReadable => "-r",
+            Self::IsWritable => "-w",
+            Self::IsExecutable => "-x",
+            Self::IsNotEmpty => "-s",
+            Self::NewerThan => "-nt",
+            Self::OlderThan => "-ot",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl Operator {
+    pub fn is_ordering(&self) -> bool {
+        matches!(self, Self::Gt | Self::GtEq | Self::Lt | Self::LtEq)
+    }
+
+    pub fn is_file_test(&self) -> bool {
+        matches!(
+            self,
+            Self::Exists
+                | Self::IsFile
+                | Self::IsDir
+                | Self::IsReadable
+                | Self::IsWritable
+                | Self::IsExecutable
+                | Self::IsNotEmpty
+                | Self::NewerThan
+                | Self::OlderThan
+        )
+    }
+}
+
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum CompareExprErr {
     #[error("Operator error: {0}")]
@@ -46,63 +124,150 @@ pub enum CompareExprErr {
     InvalidComparson(String),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+/// A comparison `<left> <op> <right>` or a unary file test `<op> <right>`.
+/// Unary operators leave `left` empty
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct CompareExpr {
-    pub left: String,
-    pub right: String,
+    pub left: Operand,
+    pub right: Operand,
     pub operator: Operator,
 }
 
 impl CompareExpr {
+    /// Compare two values with the given operator
+    ///
+    /// Type rules
+    /// ```text
+    /// int  <op> int   all operators, numeric ordering (`~=` behaves like `==`)
+    /// str  <op> str   all operators, lexicographic ordering. `==`, `!=` and `~=`
+    ///                 do glob matching when the right hand side is a pattern
+    ///                 (`*`, `?`, `[...]`). `~=` ignores case
+    /// bool <op> bool  `==`, `!=` and `~=` only
+    /// other pairs     `==`, `!=` and `~=` compare the textual representations,
+    ///                 ordering operators are an error
+    /// file tests      operands are treated as paths
+    /// ```
     pub fn cmp(left: &VarValue, right: &VarValue, op: &Operator) -> Result<bool, CompareExprErr> {
-        let res = match op {
-            Operator::Eq => left == right,
-            Operator::NotEq => left != right,
-            other => {
-                let (left_val, right_val) = CompareExpr::is_valid_int_cmp(left, right)?;
-                match other {
-                    Operator::Gt => left_val > right_val,
-                    Operator::GtEq => left_val >= right_val,
-                    Operator::Lt => left_val < right_val,
-                    Operator::LtEq => left_val <= right_val,
-                    _ => unreachable!(),
-                }
-            }
-        };
-
-        Ok(res)
-    }
+        if op.is_file_test() {
+            return Ok(file_test(&left.to_string(), &right.to_string(), op));
+        }
 
-    fn is_valid_int_cmp(left: &VarValue, right: &VarValue) -> Result<(i32, i32), CompareExprErr> {
-        match (&left, &right) {
+        match (left, right) {
             (VarValue::Int(left_val), VarValue::Int(right_val)) => {
-                Ok((left_val.clone(), right_val.clone()))
+                Ok(CompareExpr::cmp_ord(left_val, right_val, op))
             }
-            (VarValue::Int(_), VarValue::Str(v)) => Err(CompareExprErr::InvalidComparson(format!(
-                "`{}` is not a valid right hand side",
-                v
-            ))),
-            (VarValue::Str(v), VarValue::Int(_)) => Err(CompareExprErr::InvalidComparson(format!(
-                "`{}` is not a valid left hand side",
-                v
-            ))),
-            (_, _) => Err(CompareExprErr::InvalidComparson(format!(
+            (VarValue::Str(left_val), VarValue::Str(right_val)) => {
+                Ok(CompareExpr::cmp_str(left_val, right_val, op))
+            }
+            (VarValue::Bool(left_val), VarValue::Bool(right_val)) => match op {
+                Operator::Eq | Operator::EqIgnoreCase => Ok(left_val == right_val),
+                Operator::NotEq => Ok(left_val != right_val),
+                _ => Err(CompareExprErr::InvalidComparson(format!(
+                    "`{}` & `{}` booleans can't be ordered",
+                    left, right
+                ))),
+            },
+            (VarValue::Int(_), VarValue::Str(v)) | (VarValue::Bool(_), VarValue::Str(v))
+                if op.is_ordering() =>
+            {
+                Err(CompareExprErr::InvalidComparson(format!(
+                    "`{}` is not a valid right hand side",
+                    v
+                )))
+            }
+            (VarValue::Str(v), VarValue::Int(_)) | (VarValue::Str(v), VarValue::Bool(_))
+                if op.is_ordering() =>
+            {
+                Err(CompareExprErr::InvalidComparson(format!(
+                    "`{}` is not a valid left hand side",
+                    v
+                )))
+            }
+            (_, _) if op.is_ordering() => Err(CompareExprErr::InvalidComparson(format!(
                 "`{}` & `{}` Invalid right and left hand side",
                 left, right
             ))),
+            (_, _) => Ok(CompareExpr::cmp_str(
+                &left.to_string(),
+                &right.to_string(),
+                op,
+            )),
+        }
+    }
+
+    fn cmp_ord<T: Ord>(left: &T, right: &T, op: &Operator) -> bool {
+        match op {
+            Operator::Eq | Operator::EqIgnoreCase => left == right,
+            Operator::NotEq => left != right,
+            Operator::Gt => left > right,
+            Operator::GtEq => left >= right,
+            Operator::Lt => left < right,
+            Operator::LtEq => left <= right,
+            _ => unreachable!("`{:?}` is not a comparison operator", op),
+        }
+    }
+
+    fn cmp_str(left: &str, right: &str, op: &Operator) -> bool {
+        match op {
+            Operator::Eq if is_glob(right) => glob_match(right, left),
+            Operator::NotEq if is_glob(right) => !glob_match(right, left),
+            Operator::EqIgnoreCase => {
+                let (left, right) = (left.to_lowercase(), right.to_lowercase());
+                if is_glob(&right) {
+                    glob_match(&right, &left)
+                } else {
+                    left == right
+                }
+            }
+            _ => CompareExpr::cmp_ord(&left, &right, op),
         }
     }
+
+    /// Split a comparison with a variable path operand `ex: cfg["port"] > 1000`, the
+    /// quotes of the path are kept while the other operand loses its quotes as usual
+    fn parse_paths(s: &str) -> Option<Self> {
+        let (left, rest) = s.trim().split_once(char::is_whitespace)?;
+        let (op, right) = rest.trim().split_once(char::is_whitespace)?;
+        let re_path = cached_regex!(RE_VAR_PATH);
+        let (left, right) = (left.trim(), right.trim());
+        if !re_path.is_match(left) && !re_path.is_match(right) {
+            return None;
+        }
+
+        let unquote = |operand: &str| match cached_regex!(RE_QUOTE_REMOVAL).captures(operand) {
+            Some(caps) if !re_path.is_match(operand) => caps["value"].trim().to_string(),
+            _ => operand.to_string(),
+        };
+        Some(Self {
+            left: unquote(left).into(),
+            right: unquote(right).into(),
+            operator: op.parse::<Operator>().ok()?,
+        })
+    }
 }
 
 impl FromStr for CompareExpr {
     type Err = CompareExprErr;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let re = Regex::new(RE_CMP).unwrap();
+        let re = cached_regex!(RE_FILE_TEST);
+        if let Some(caps) = re.captures(s.trim()) {
+            return Ok(Self {
+                left: Operand::default(),
+                right: caps["right"].trim().into(),
+                operator: caps["op"].parse::<Operator>()?,
+            });
+        }
+
+        if let Some(cmp) = Self::parse_paths(s) {
+            return Ok(cmp);
+        }
+
+        let re = cached_regex!(RE_CMP);
 
         if let Some(caps) = re.captures(s) {
             Ok(Self {
-                left: caps["left"].trim().to_string(),
-                right: caps["right"].trim().to_string(),
+                left: caps["left"].trim().into(),
+                right: caps["right"].trim().into(),
                 operator: caps["op"].parse::<Operator>()?,
             })
         } else {
@@ -114,6 +279,11 @@ impl FromStr for CompareExpr {
 #[cfg(test)]
 mod test {
     use super::{CompareExpr, CompareExprErr, Operator, OperatorErr};
+    use crate::variables::VarValue;
+
+    fn str_val(s: &str) -> VarValue {
+        VarValue::Str(s.into())
+    }
 
     #[test]
     fn new_operator() {
@@ -123,6 +293,7 @@ mod test {
         assert_eq!(">=".parse::<Operator>().unwrap(), Operator::GtEq);
         assert_eq!("<".parse::<Operator>().unwrap(), Operator::Lt);
         assert_eq!("<=".parse::<Operator>().unwrap(), Operator::LtEq);
+        assert_eq!("~=".parse::<Operator>().unwrap(), Operator::EqIgnoreCase);
         assert!("..".parse::<Operator>().is_err());
     }
 
@@ -138,7 +309,24 @@ mod test {
                 right: "20".into(),
                 operator: Operator::Gt
             }
-        )
+        );
+        let cmp = "name != \"\"".parse::<CompareExpr>().unwrap();
+        assert_eq!((cmp.left.source(), cmp.right.source()), ("name", ""));
+    }
+
+    #[test]
+    fn parse_path_operands() {
+        let cmp = r#"cfg["servers"][0]["port"] >= 1000"#.parse::<CompareExpr>().unwrap();
+        assert_eq!(cmp.left.source(), r#"cfg["servers"][0]["port"]"#);
+        assert_eq!(cmp.operator, Operator::GtEq);
+
+        let cmp = r#"name == cfg['owner']"#.parse::<CompareExpr>().unwrap();
+        assert_eq!(
+            (cmp.left.source(), cmp.right.source()),
+            ("name", "cfg['owner']")
+        );
+        let cmp = r#"cfg["name"] == "Jone""#.parse::<CompareExpr>().unwrap();
+        assert_eq!(cmp.right.source(), "Jone");
     }
 
     #[test]
@@ -151,4 +339,64 @@ mod test {
             CompareExprErr::OperatorErr(OperatorErr::InvalidOperator("!!".into()))
         )
     }
+
+    #[test]
+    fn cmp_strings_lexicographically() {
+        let (apple, banana) = (str_val("apple"), str_val("banana"));
+        assert!(CompareExpr::cmp(&apple, &banana, &Operator::Lt).unwrap());
+        assert!(CompareExpr::cmp(&banana, &apple, &Operator::GtEq).unwrap());
+        assert!(!CompareExpr::cmp(&apple, &banana, &Operator::Gt).unwrap());
+    }
+
+    #[test]
+    fn cmp_strings_with_glob_pattern() {
+        let file = str_val("server.log");
+        assert!(CompareExpr::cmp(&file, &str_val("*.log"), &Operator::Eq).unwrap());
+        assert!(!CompareExpr::cmp(&file, &str_val("*.txt"), &Operator::Eq).unwrap());
+        assert!(CompareExpr::cmp(&file, &str_val("*.txt"), &Operator::NotEq).unwrap());
+    }
+
+    #[test]
+    fn cmp_strings_ignoring_case() {
+        let name = str_val("Jone");
+        assert!(CompareExpr::cmp(&name, &str_val("JONE"), &Operator::EqIgnoreCase).unwrap());
+        assert!(CompareExpr::cmp(&name, &str_val("j*"), &Operator::EqIgnoreCase).unwrap());
+        assert!(!CompareExpr::cmp(&name, &str_val("JONE"), &Operator::Eq).unwrap());
+    }
+
+    #[test]
+    fn cmp_mixed_types() {
+        let (num, text) = (VarValue::Int(5), str_val("5"));
+        assert!(CompareExpr::cmp(&num, &text, &Operator::Eq).unwrap());
+        assert!(CompareExpr::cmp(&VarValue::Bool(true), &str_val("true"), &Operator::Eq).unwrap());
+        assert_eq!(
+            CompareExpr::cmp(&num, &str_val("five"), &Operator::Gt)
+                .err()
+                .unwrap(),
+            CompareExprErr::InvalidComparson("`five` is not a valid right hand side".into())
+        );
+        assert!(
+            CompareExpr::cmp(&VarValue::Bool(true), &VarValue::Bool(false), &Operator::Lt).is_err()
+        );
+    }
+
+    #[test]
+    fn parse_file_test_expr() {
+        assert_eq!(
+            "-f \"notes.txt\"".parse::<CompareExpr>().unwrap(),
+            CompareExpr {
+                left: "".into(),
+                right: "notes.txt".into(),
+                operator: Operator::IsFile
+            }
+        );
+        assert_eq!(
+            "build.log -nt src".parse::<CompareExpr>().unwrap(),
+            CompareExpr {
+                left: "build.log".into(),
+                right: "src".into(),
+                operator: Operator::NewerThan
+            }
+        );
+    }
 }
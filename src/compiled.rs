@@ -0,0 +1,217 @@
+use crate::{interpreter::InterpreterErr, lang_parser::LangParser};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// First bytes of every compiled script
+pub const MAGIC: &[u8; 4] = b"MBC\0";
+/// Bumped whenever the AST changes shape, older files are rebuilt from their source
+pub const FORMAT_VERSION: u32 = 8;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum CompiledErr {
+    #[error("`{0}` was compiled by an incompatible version (format {1}, expected {FORMAT_VERSION}) and its source is missing")]
+    IncompatibleVersion(String, u32),
+    #[error("`{0}` is corrupted")]
+    Corrupted(String),
+}
+
+/// A parsed script and the checksum of the source it was parsed from
+///
+/// Layout
+/// ```text
+/// "MBC\0" | version: u32 | checksum: u64 | source path length: u32 | source path | AST (bincode)
+/// ```
+/// Integers are little endian. The header is readable by every version so stale files
+/// can always find their source
+#[derive(Debug, PartialEq, Eq)]
+pub struct CompiledScript {
+    pub source: PathBuf,
+    pub checksum: u64,
+    pub ast: LangParser,
+}
+
+impl CompiledScript {
+    /// Parse the script at `source`
+    pub fn compile<P: AsRef<Path>>(source: P) -> Result<Self, InterpreterErr> {
+        let source = source.as_ref();
+        if !source.exists() {
+            return Err(InterpreterErr::FileNotFound(source.display().to_string()));
+        }
+
+        let content = fs::read_to_string(source)?;
+        Ok(Self {
+            source: source.canonicalize()?,
+            checksum: checksum(content.as_bytes()),
+            ast: content.parse::<LangParser>()?,
+        })
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), InterpreterErr> {
+        let source = self.source.to_string_lossy();
+        let ast = bincode::serialize(&self.ast).expect("the AST is always serializable");
+
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 16 + source.len() + ast.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.checksum.to_le_bytes());
+        bytes.extend_from_slice(&(source.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(source.as_bytes());
+        bytes.extend_from_slice(&ast);
+        fs::write(path, bytes)?;
+
+        Ok(())
+    }
+}
+
+/// Load the AST of the script at `path`, which is either a source file or a compiled
+/// script. A compiled script whose source changed, or that was written by another
+/// version, is rebuilt from the source and written back if its location is writable
+pub fn load<P: AsRef<Path>>(path: P) -> Result<LangParser, InterpreterErr> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Err(InterpreterErr::FileNotFound(path.display().to_string()));
+    }
+
+    let bytes = fs::read(path)?;
+    let Some(rest) = bytes.strip_prefix(MAGIC) else {
+        let source = String::from_utf8(bytes)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        return Ok(source.parse::<LangParser>()?);
+    };
+
+    let corrupted = || CompiledErr::Corrupted(path.display().to_string());
+    let mut reader = Reader(rest);
+    let version = reader.u32().ok_or_else(corrupted)?;
+    let expected_checksum = reader.u64().ok_or_else(corrupted)?;
+    let source_len = reader.u32().ok_or_else(corrupted)? as usize;
+    let source = reader.take(source_len).ok_or_else(corrupted)?;
+    let source = PathBuf::from(String::from_utf8_lossy(source).into_owned());
+
+    match fs::read(&source) {
+        Ok(content) if version != FORMAT_VERSION || checksum(&content) != expected_checksum => {
+            let compiled = CompiledScript::compile(&source)?;
+            // The rebuilt AST runs even if the stale file can't be replaced
+            let _ = compiled.write(path);
+            Ok(compiled.ast)
+        }
+        Err(_) if version != FORMAT_VERSION => Err(CompiledErr::IncompatibleVersion(
+            path.display().to_string(),
+            version,
+        ))?,
+        _ => Ok(bincode::deserialize(reader.0).map_err(|_| corrupted())?),
+    }
+}
+
+/// FNV-1a hash of the source, only used to notice changes
+pub fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load, CompiledErr, CompiledScript, FORMAT_VERSION, MAGIC};
+    use crate::{interpreter::InterpreterErr, lang_parser::LangParser};
+    use std::{env, fs, path::PathBuf};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("mybash_compiled_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn load_compiled_script() {
+        let dir = temp_dir("load");
+        let source = dir.join("script.mb");
+        let compiled = dir.join("script.mbc");
+        fs::write(
+            &source,
+            "name: str = Jone\necho \"Hello, $name\"\nmatch name\ncase /^J/\necho 'J'\nend",
+        )
+        .unwrap();
+
+        CompiledScript::compile(&source)
+            .unwrap()
+            .write(&compiled)
+            .unwrap();
+        assert!(fs::read(&compiled).unwrap().starts_with(MAGIC));
+        assert_eq!(load(&compiled).unwrap(), load(&source).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rebuild_when_the_source_changes() {
+        let dir = temp_dir("rebuild");
+        let source = dir.join("script.mb");
+        let compiled = dir.join("script.mbc");
+        fs::write(&source, "echo 'old'").unwrap();
+        CompiledScript::compile(&source)
+            .unwrap()
+            .write(&compiled)
+            .unwrap();
+
+        fs::write(&source, "echo 'new'").unwrap();
+        let expected = "echo 'new'".parse::<LangParser>().unwrap();
+        assert_eq!(load(&compiled).unwrap(), expected);
+
+        // The rebuilt file no longer depends on the source
+        fs::remove_file(&source).unwrap();
+        assert_eq!(load(&compiled).unwrap(), expected);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reject_incompatible_and_corrupted_files() {
+        let dir = temp_dir("reject");
+        let compiled = dir.join("script.mbc");
+        let path = compiled.display().to_string();
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+        bytes.extend_from_slice(b"missing");
+        fs::write(&compiled, &bytes).unwrap();
+        assert!(matches!(
+            load(&compiled),
+            Err(InterpreterErr::CompiledErr(CompiledErr::IncompatibleVersion(p, v)))
+                if p == path && v == FORMAT_VERSION + 1
+        ));
+
+        fs::write(&compiled, &bytes[..10]).unwrap();
+        assert!(matches!(
+            load(&compiled),
+            Err(InterpreterErr::CompiledErr(CompiledErr::Corrupted(p))) if p == path
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
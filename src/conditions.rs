@@ -1,16 +1,16 @@
 use crate::{
     cmp::{CompareExpr, CompareExprErr},
     lang_parser::{Expression, LangParser, ParseErr},
-    regex::RE_IF_ELSE,
+    regex::{cached_regex, RE_IF_ELSE},
 };
-use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use thiserror::Error;
 
 /// A representation of `if else` statments
 ///
 /// Example
-/// ```
+/// ```text
 /// if <condition>
 /// do <expr1>
 /// else
@@ -18,12 +18,12 @@ use thiserror::Error;
 /// endif
 /// ```
 /// Or
-/// ```
+/// ```text
 /// if <condition>
 /// do <expr>
 /// endif
 /// ```
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Condition {
     pub condition: CompareExpr,
     pub if_expr: Expression,
@@ -39,10 +39,7 @@ impl Condition {
         s.trim() == "endif"
     }
 
-    pub fn from_lines(
-        lines: &Vec<String>,
-        start_idx: usize,
-    ) -> Result<(String, usize), ConditionErr> {
+    pub fn from_lines(lines: &[String], start_idx: usize) -> Result<(String, usize), ConditionErr> {
         let num_of_lines = lines.len();
 
         if start_idx >= num_of_lines {
@@ -72,7 +69,7 @@ impl Condition {
                 )));
             }
 
-            if Condition::is_endif(&line) {
+            if Condition::is_endif(line) {
                 break;
             }
         }
@@ -108,7 +105,7 @@ impl FromStr for Condition {
     type Err = ConditionErr;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let re = Regex::new(RE_IF_ELSE).unwrap();
+        let re = cached_regex!(RE_IF_ELSE);
         if let Some(caps) = re.captures(s.trim()) {
             let condition = caps["con"].parse::<CompareExpr>()?;
 
@@ -169,7 +166,7 @@ mod test {
                 operator: crate::cmp::Operator::GtEq
             }
         );
-        assert_eq!(if_expr, Expression::Echo(Echo("Hello, World".to_string())));
+        assert_eq!(if_expr, Expression::Echo(Echo("Hello, World".into())));
         assert!(else_expr.is_none());
     }
 
@@ -0,0 +1,444 @@
+use crate::{
+    debugger::{Debugger, Frontend, Paused, Resume},
+    executor::ExeError,
+    interpreter::{Interpreter, InterpreterErr},
+    lsp::{read_message, write_message},
+};
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, Write},
+    path::Path,
+    rc::Rc,
+};
+
+/// Scripts run on a single thread
+const THREAD_ID: u64 = 1;
+
+/// Serve the debug adapter protocol over `input` and `output` until the client disconnects
+///
+/// Example
+/// ```text
+/// initialize, launch { "program": "deploy.mb", "args": [], "stopOnEntry": false },
+/// setBreakpoints { "breakpoints": [{ "line": 3 }] }, configurationDone
+/// ```
+/// The script runs after `configurationDone`. Requests are answered while the script is
+/// stopped, its output is sent as `output` events and `input()` reads nothing since stdin
+/// carries the protocol
+pub fn serve<R: BufRead + 'static, W: Write + 'static>(input: R, output: W) -> io::Result<()> {
+    let conn = Rc::new(RefCell::new(Connection {
+        input,
+        output,
+        seq: 0,
+    }));
+
+    let mut launch = Value::Null;
+    let mut breakpoints = Vec::new();
+    loop {
+        let Some(request) = conn.borrow_mut().read()? else {
+            return Ok(());
+        };
+        let args = &request["arguments"];
+        let body = match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsEvaluateForHovers": true,
+                });
+                let mut conn = conn.borrow_mut();
+                conn.respond(&request, Ok(capabilities))?;
+                conn.event("initialized", json!({}))?;
+                continue;
+            }
+            "launch" => {
+                launch = args.clone();
+                Ok(json!({}))
+            }
+            "setBreakpoints" => {
+                breakpoints = lines(args);
+                Ok(verified(&breakpoints))
+            }
+            "threads" => Ok(threads()),
+            "configurationDone" => {
+                conn.borrow_mut().respond(&request, Ok(json!({})))?;
+                break;
+            }
+            "disconnect" => return conn.borrow_mut().respond(&request, Ok(json!({}))),
+            command => Err(format!("Unsupported request `{}`", command)),
+        };
+        conn.borrow_mut().respond(&request, body)?;
+    }
+
+    let program = launch["program"].as_str().unwrap_or_default().to_string();
+    let args = launch["args"].as_array().cloned().unwrap_or_default();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_args(args.iter().map(|arg| arg.as_str().unwrap_or_default()));
+    interpreter.set_stdout(OutputEvents {
+        conn: Rc::clone(&conn),
+        category: "stdout",
+    });
+    interpreter.set_stderr(OutputEvents {
+        conn: Rc::clone(&conn),
+        category: "stderr",
+    });
+    interpreter.set_stdin(io::empty());
+    let mut debugger = Debugger::new(Adapter {
+        conn: Rc::clone(&conn),
+        program: program.clone(),
+    })
+    .stop_on_entry(launch["stopOnEntry"].as_bool().unwrap_or(false));
+    for line in breakpoints {
+        debugger = debugger.breakpoint(line);
+    }
+    interpreter.set_debugger(debugger);
+
+    let exit_code = match interpreter.eval_file(&program) {
+        Ok(()) => 0,
+        Err(InterpreterErr::ExeError {
+            err: ExeError::Signal(event),
+            ..
+        }) => event.exit_status(),
+        Err(e) => {
+            let output = json!({ "category": "stderr", "output": format!("{}\n", e) });
+            conn.borrow_mut().event("output", output)?;
+            1
+        }
+    };
+    let mut conn = conn.borrow_mut();
+    conn.event("exited", json!({ "exitCode": exit_code }))?;
+    conn.event("terminated", json!({}))?;
+
+    while let Some(request) = conn.read()? {
+        let body = match request["command"].as_str().unwrap_or_default() {
+            "threads" => Ok(threads()),
+            "disconnect" => return conn.respond(&request, Ok(json!({}))),
+            _ => Err("The script ended".to_string()),
+        };
+        conn.respond(&request, body)?;
+    }
+
+    Ok(())
+}
+
+struct Connection<R, W> {
+    input: R,
+    output: W,
+    seq: u64,
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    fn read(&mut self) -> io::Result<Option<Value>> {
+        read_message(&mut self.input)
+    }
+
+    fn send(&mut self, mut msg: Value) -> io::Result<()> {
+        self.seq += 1;
+        msg["seq"] = json!(self.seq);
+        write_message(&mut self.output, &msg)
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+}
+
+/// The script's output as `output` events
+struct OutputEvents<R, W> {
+    conn: Rc<RefCell<Connection<R, W>>>,
+    category: &'static str,
+}
+
+impl<R: BufRead, W: Write> Write for OutputEvents<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let output = json!({
+            "category": self.category,
+            "output": String::from_utf8_lossy(buf),
+        });
+        self.conn.borrow_mut().event("output", output)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Answers the client's requests while the script is stopped
+struct Adapter<R, W> {
+    conn: Rc<RefCell<Connection<R, W>>>,
+    program: String,
+}
+
+impl<R: BufRead, W: Write> Adapter<R, W> {
+    fn stopped(&mut self, paused: &mut Paused) -> io::Result<Resume> {
+        let stopped = json!({
+            "reason": paused.reason.to_string(),
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        self.conn.borrow_mut().event("stopped", stopped)?;
+
+        loop {
+            // Not borrowed while evaluating, the expression may write output
+            let Some(request) = self.conn.borrow_mut().read()? else {
+                return Ok(Resume::Quit);
+            };
+            let args = &request["arguments"];
+            let mut resume = None;
+            let body = match request["command"].as_str().unwrap_or_default() {
+                "threads" => Ok(threads()),
+                "stackTrace" => Ok(self.stack_trace(paused)),
+                "scopes" => Ok(scopes(paused)),
+                "variables" => Ok(variables(paused, args)),
+                "evaluate" => {
+                    let expr = args["expression"].as_str().unwrap_or_default();
+                    paused
+                        .eval(expr)
+                        .map(|value| {
+                            json!({
+                                "result": value.to_string(),
+                                "type": value.datatype().to_string(),
+                                "variablesReference": 0,
+                            })
+                        })
+                        .map_err(|e| e.to_string())
+                }
+                "setBreakpoints" => {
+                    let lines = lines(args);
+                    paused.set_breakpoints(lines.iter().copied());
+                    Ok(verified(&lines))
+                }
+                "continue" => {
+                    resume = Some(Resume::Continue);
+                    Ok(json!({ "allThreadsContinued": true }))
+                }
+                "next" => {
+                    resume = Some(Resume::Next);
+                    Ok(json!({}))
+                }
+                "stepIn" => {
+                    resume = Some(Resume::Step);
+                    Ok(json!({}))
+                }
+                "pause" => Ok(json!({})),
+                "disconnect" => {
+                    resume = Some(Resume::Quit);
+                    Ok(json!({}))
+                }
+                command => Err(format!("Unsupported request `{}`", command)),
+            };
+            self.conn.borrow_mut().respond(&request, body)?;
+            if let Some(resume) = resume {
+                return Ok(resume);
+            }
+        }
+    }
+
+    fn stack_trace(&self, paused: &Paused) -> Value {
+        let name = Path::new(&self.program)
+            .file_name()
+            .map_or(self.program.clone(), |name| {
+                name.to_string_lossy().to_string()
+            });
+        let frames = paused
+            .stack()
+            .into_iter()
+            .enumerate()
+            .map(|(id, (frame, line))| {
+                json!({
+                    "id": id,
+                    "name": frame,
+                    "line": line,
+                    "column": 1,
+                    "source": { "name": name, "path": self.program },
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+}
+
+impl<R: BufRead, W: Write> Frontend for Adapter<R, W> {
+    /// The script ends if the client is gone
+    fn pause(&mut self, paused: &mut Paused) -> Resume {
+        self.stopped(paused).unwrap_or(Resume::Quit)
+    }
+}
+
+fn threads() -> Value {
+    json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })
+}
+
+/// The scope chain innermost first, the `variablesReference` of a scope is its position
+/// starting at 1
+fn scopes(paused: &Paused) -> Value {
+    let scopes = paused
+        .scopes()
+        .iter()
+        .enumerate()
+        .map(|(idx, (name, _))| {
+            json!({
+                "name": name,
+                "variablesReference": idx + 1,
+                "expensive": false,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({ "scopes": scopes })
+}
+
+fn variables(paused: &Paused, args: &Value) -> Value {
+    let scopes = paused.scopes();
+    let scope = args["variablesReference"]
+        .as_u64()
+        .and_then(|reference| scopes.get((reference as usize).checked_sub(1)?));
+    let mut vars = scope.map_or(vec![], |(_, vars)| vars.iter().collect::<Vec<_>>());
+    vars.sort_by_key(|(name, _)| *name);
+    let vars = vars
+        .into_iter()
+        .map(|(name, value)| {
+            json!({
+                "name": name,
+                "value": value.to_string(),
+                "type": value.datatype().to_string(),
+                "variablesReference": 0,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({ "variables": vars })
+}
+
+/// The lines of a `setBreakpoints` request
+fn lines(args: &Value) -> Vec<usize> {
+    args["breakpoints"]
+        .as_array()
+        .map(|breakpoints| {
+            breakpoints
+                .iter()
+                .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                .map(|line| line as usize)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn verified(lines: &[usize]) -> Value {
+    let breakpoints = lines
+        .iter()
+        .map(|line| json!({ "verified": true, "line": line }))
+        .collect::<Vec<_>>();
+
+    json!({ "breakpoints": breakpoints })
+}
+
+#[cfg(test)]
+mod test {
+    use super::serve;
+    use crate::streams::OutputBuffer;
+    use serde_json::{json, Value};
+    use std::io::Cursor;
+
+    fn request(seq: u64, command: &str, arguments: Value) -> Value {
+        json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments })
+    }
+
+    #[test]
+    fn debug_a_script_over_the_protocol() {
+        let path = std::env::temp_dir().join(format!("mybash-dap-{}.mb", std::process::id()));
+        std::fs::write(
+            &path,
+            "name: str = Jone\nage: int = 30\necho \"$name is $age\"",
+        )
+        .unwrap();
+        let program = path.to_str().unwrap();
+
+        let mut input = Vec::new();
+        for request in [
+            request(1, "initialize", json!({ "adapterID": "mybash" })),
+            request(2, "launch", json!({ "program": program })),
+            request(
+                3,
+                "setBreakpoints",
+                json!({ "breakpoints": [{ "line": 2 }] }),
+            ),
+            request(4, "configurationDone", json!({})),
+            request(5, "stackTrace", json!({ "threadId": 1 })),
+            request(6, "variables", json!({ "variablesReference": 1 })),
+            request(7, "evaluate", json!({ "expression": "name == \"Jone\"" })),
+            request(8, "next", json!({ "threadId": 1 })),
+            request(9, "continue", json!({ "threadId": 1 })),
+            request(10, "disconnect", json!({})),
+        ] {
+            let body = request.to_string();
+            input.extend(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).bytes());
+        }
+        let output = OutputBuffer::default();
+        serve(Cursor::new(input), output.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let messages = output
+            .contents()
+            .split("Content-Length: ")
+            .skip(1)
+            .map(|msg| serde_json::from_str(msg.split_once("\r\n\r\n").unwrap().1).unwrap())
+            .collect::<Vec<Value>>();
+        let summary = messages
+            .iter()
+            .map(|msg| match msg["type"].as_str().unwrap() {
+                "event" => format!("event {}", msg["event"].as_str().unwrap()),
+                _ => format!("{} {}", msg["command"].as_str().unwrap(), msg["success"]),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                "initialize true",
+                "event initialized",
+                "launch true",
+                "setBreakpoints true",
+                "configurationDone true",
+                "event stopped",
+                "stackTrace true",
+                "variables true",
+                "evaluate true",
+                "next true",
+                "event stopped",
+                "continue true",
+                "event output",
+                "event exited",
+                "event terminated",
+                "disconnect true",
+            ]
+        );
+
+        let frame = &messages[6]["body"]["stackFrames"][0];
+        assert_eq!(
+            (&frame["name"], &frame["line"]),
+            (&json!("script"), &json!(2))
+        );
+        assert_eq!(
+            messages[7]["body"]["variables"][0],
+            json!({ "name": "name", "value": "Jone", "type": "str", "variablesReference": 0 })
+        );
+        assert_eq!(messages[8]["body"]["result"], "true");
+        assert_eq!(messages[12]["body"]["output"], "Jone is 30\n");
+        assert_eq!(messages[13]["body"]["exitCode"], 0);
+    }
+}
@@ -0,0 +1,477 @@
+use crate::{
+    executor::{ExeError, Executor},
+    variables::VarValue,
+};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    io::{self, BufRead, Read, Write},
+};
+
+/// Why the script stopped
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StopReason {
+    /// Before the first statement
+    Entry,
+    Breakpoint,
+    /// After `step` or `next`
+    Step,
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            StopReason::Entry => "entry",
+            StopReason::Breakpoint => "breakpoint",
+            StopReason::Step => "step",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// How to resume a stopped script
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Resume {
+    /// Run until the next breakpoint
+    Continue,
+    /// Stop before the next statement, entering blocks
+    Step,
+    /// Stop before the next statement of the current block or a block around it
+    Next,
+    /// End the script, its traps don't run
+    Quit,
+}
+
+/// The user interface of the debugger, it decides how to resume when the script stops
+pub trait Frontend {
+    fn pause(&mut self, paused: &mut Paused) -> Resume;
+}
+
+/// Stops the executor before statements at breakpoints or while stepping
+///
+/// Example
+/// ```
+/// use mybash::{debugger::{Debugger, Frontend, Paused, Resume}, Interpreter, VarValue};
+///
+/// struct Dump;
+///
+/// impl Frontend for Dump {
+///     fn pause(&mut self, paused: &mut Paused) -> Resume {
+///         assert_eq!(paused.line(), 2);
+///         assert_eq!(paused.vars()["name"], VarValue::Str("Jone".into()));
+///         Resume::Continue
+///     }
+/// }
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_debugger(Debugger::new(Dump).breakpoint(2));
+/// interpreter.eval_str("name: str = Jone\necho name").unwrap();
+/// ```
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    resume: Resume,
+    /// Number of frames when `next` was requested
+    next_depth: usize,
+    started: bool,
+    stop_on_entry: bool,
+    frontend: Box<dyn Frontend>,
+}
+
+impl Debugger {
+    pub fn new<F: Frontend + 'static>(frontend: F) -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            resume: Resume::Continue,
+            next_depth: 0,
+            started: false,
+            stop_on_entry: false,
+            frontend: Box::new(frontend),
+        }
+    }
+
+    /// Stop before the statement at `line` (1-based)
+    pub fn breakpoint(mut self, line: usize) -> Self {
+        self.breakpoints.insert(line);
+        self
+    }
+
+    /// Stop before the first statement
+    pub fn stop_on_entry(mut self, stop: bool) -> Self {
+        self.stop_on_entry = stop;
+        self
+    }
+
+    /// Called by the executor before every statement
+    pub(crate) fn statement(&mut self, exe: &mut Executor) -> Result<(), ExeError> {
+        let depth = exe.frames.len();
+        let reason = match self.resume {
+            Resume::Quit => return Err(ExeError::DebuggerQuit),
+            _ if !self.started && self.stop_on_entry => Some(StopReason::Entry),
+            _ if self.breakpoints.contains(&exe.line) => Some(StopReason::Breakpoint),
+            Resume::Step => Some(StopReason::Step),
+            Resume::Next if depth <= self.next_depth => Some(StopReason::Step),
+            _ => None,
+        };
+        self.started = true;
+        let Some(reason) = reason else {
+            return Ok(());
+        };
+
+        let mut paused = Paused {
+            exe,
+            breakpoints: &mut self.breakpoints,
+            reason,
+        };
+        self.resume = self.frontend.pause(&mut paused);
+        self.next_depth = depth;
+        match self.resume {
+            Resume::Quit => Err(ExeError::DebuggerQuit),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The state of a stopped script
+pub struct Paused<'a> {
+    exe: &'a mut Executor,
+    breakpoints: &'a mut BTreeSet<usize>,
+    pub reason: StopReason,
+}
+
+impl Paused<'_> {
+    /// Line of the statement about to run
+    pub fn line(&self) -> usize {
+        self.exe.line
+    }
+
+    /// The script's variables
+    pub fn vars(&self) -> &HashMap<String, VarValue> {
+        &self.exe.vars
+    }
+
+    /// The scopes the current statement sees and their variables, innermost first. Blocks
+    /// hold their `let` variables, the script's scope is last
+    pub fn scopes(&self) -> Vec<(&str, &HashMap<String, VarValue>)> {
+        self.exe.scopes()
+    }
+
+    /// Evaluate a comparison `ex: age > 20` or a value `ex: $name`, `err.message`
+    pub fn eval(&mut self, expr: &str) -> Result<VarValue, ExeError> {
+        self.exe.eval_watch(expr.trim())
+    }
+
+    /// The blocks being executed, innermost first, with the line each one is at. A called
+    /// function is named after it
+    pub fn stack(&self) -> Vec<(&str, usize)> {
+        let mut line = self.exe.line;
+        let mut stack = Vec::new();
+        for frame in self.exe.frames.iter().rev() {
+            stack.push((frame.name.as_str(), line));
+            line = frame.line;
+        }
+
+        stack
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<usize> {
+        self.breakpoints
+    }
+
+    pub fn set_breakpoints<I: IntoIterator<Item = usize>>(&mut self, lines: I) {
+        *self.breakpoints = lines.into_iter().collect();
+    }
+
+    pub fn add_breakpoint(&mut self, line: usize) {
+        self.breakpoints.insert(line);
+    }
+
+    pub fn remove_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.remove(&line)
+    }
+}
+
+const HELP: &str = "Commands:
+  c, continue      run to the next breakpoint
+  s, step          run the next statement, entering blocks
+  n, next          run the next statement of the current block
+  b, break <line>  set a breakpoint
+  d, delete <line> remove a breakpoint
+  vars             print the variables, innermost scope first
+  p, print <expr>  evaluate a value or a comparison
+  bt, backtrace    print the blocks being executed
+  l, list          print the source around the current line
+  q, quit          end the script
+";
+
+/// A command line frontend
+///
+/// Example
+/// ```text
+/// Stopped at line 3 (breakpoint)
+///    3 | if age > 40
+/// (mbdb) p age
+/// 30
+/// (mbdb) n
+/// ```
+pub struct Console<R, W> {
+    lines: Vec<String>,
+    input: R,
+    output: W,
+}
+
+impl Console<StdinLines, io::Stdout> {
+    /// A console on the terminal, the script's `input()` can still read stdin
+    pub fn stdio(source: &str) -> Self {
+        Console::new(source, StdinLines::default(), io::stdout())
+    }
+}
+
+impl<R: BufRead, W: Write> Console<R, W> {
+    pub fn new(source: &str, input: R, output: W) -> Self {
+        Self {
+            lines: source.lines().map(String::from).collect(),
+            input,
+            output,
+        }
+    }
+
+    fn list(&mut self, line: usize, context: usize) -> io::Result<()> {
+        let first = line.saturating_sub(context).max(1);
+        let last = (line + context).min(self.lines.len());
+        for idx in first..=last {
+            let marker = if idx == line { ">" } else { " " };
+            writeln!(
+                self.output,
+                "{}{:>4} | {}",
+                marker,
+                idx,
+                self.lines[idx - 1]
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Run a command, returns how to resume if it resumes the script
+    fn command(&mut self, paused: &mut Paused, cmd: &str) -> io::Result<Option<Resume>> {
+        let (cmd, arg) = cmd.split_once(' ').unwrap_or((cmd, ""));
+        let arg = arg.trim();
+        let line_arg = arg.parse::<usize>().ok().filter(|line| *line > 0);
+        let out = &mut self.output;
+        match cmd {
+            "c" | "continue" => return Ok(Some(Resume::Continue)),
+            "s" | "step" => return Ok(Some(Resume::Step)),
+            "n" | "next" => return Ok(Some(Resume::Next)),
+            "q" | "quit" => return Ok(Some(Resume::Quit)),
+            "b" | "break" => match line_arg {
+                Some(line) => {
+                    paused.add_breakpoint(line);
+                    writeln!(out, "Breakpoint at line {}", line)?;
+                }
+                None => {
+                    let lines = paused.breakpoints().iter().map(|l| l.to_string());
+                    writeln!(out, "Breakpoints: {}", lines.collect::<Vec<_>>().join(", "))?;
+                }
+            },
+            "d" | "delete" => match line_arg {
+                Some(line) if paused.remove_breakpoint(line) => {
+                    writeln!(out, "Removed the breakpoint at line {}", line)?
+                }
+                _ => writeln!(out, "No breakpoint at line `{}`", arg)?,
+            },
+            "vars" => {
+                let mut seen = HashSet::new();
+                for (scope, vars) in paused.scopes() {
+                    let mut vars = vars.iter().collect::<Vec<_>>();
+                    vars.sort_by_key(|(name, _)| *name);
+                    for (name, value) in vars {
+                        write!(out, "{}: {} = {}", name, value.datatype(), value)?;
+                        if scope != "script" {
+                            write!(out, " (let in {})", scope)?;
+                        }
+                        if !seen.insert(name) {
+                            write!(out, " (shadowed)")?;
+                        }
+                        writeln!(out)?;
+                    }
+                }
+            }
+            "p" | "print" => match paused.eval(arg) {
+                Ok(value) => writeln!(out, "{}", value)?,
+                Err(e) => writeln!(out, "Error: {}", e)?,
+            },
+            "bt" | "backtrace" => {
+                for (idx, (name, line)) in paused.stack().into_iter().enumerate() {
+                    writeln!(out, "#{} {} at line {}", idx, name, line)?;
+                }
+            }
+            "l" | "list" => self.list(paused.line(), 5)?,
+            "h" | "help" => write!(out, "{}", HELP)?,
+            "" => {}
+            _ => writeln!(out, "Unknown command `{}`, `help` lists the commands", cmd)?,
+        }
+
+        Ok(None)
+    }
+
+    fn prompt(&mut self, paused: &mut Paused) -> io::Result<Resume> {
+        writeln!(
+            self.output,
+            "Stopped at line {} ({})",
+            paused.line(),
+            paused.reason
+        )?;
+        self.list(paused.line(), 0)?;
+        loop {
+            write!(self.output, "(mbdb) ")?;
+            self.output.flush()?;
+            let mut cmd = String::new();
+            if self.input.read_line(&mut cmd)? == 0 {
+                return Ok(Resume::Quit);
+            }
+            if let Some(resume) = self.command(paused, cmd.trim())? {
+                return Ok(resume);
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Frontend for Console<R, W> {
+    /// The script ends when the input does or can't be read
+    fn pause(&mut self, paused: &mut Paused) -> Resume {
+        self.prompt(paused).unwrap_or(Resume::Quit)
+    }
+}
+
+/// Reads stdin a line at a time without holding its lock, nothing past the line is buffered
+#[derive(Default)]
+pub struct StdinLines {
+    line: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for StdinLines {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for StdinLines {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.line.len() {
+            let mut line = String::new();
+            io::stdin().read_line(&mut line)?;
+            self.line = line.into_bytes();
+            self.pos = 0;
+        }
+        Ok(&self.line[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.line.len());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Console, Debugger};
+    use crate::{
+        executor::ExeError,
+        interpreter::{Interpreter, InterpreterErr},
+        streams::OutputBuffer,
+        variables::VarValue,
+    };
+    use std::io::Cursor;
+
+    fn debug(
+        source: &str,
+        commands: &str,
+        debugger: fn(Debugger) -> Debugger,
+    ) -> (String, Interpreter, Result<(), InterpreterErr>) {
+        let out = OutputBuffer::default();
+        let console = Console::new(source, Cursor::new(commands.to_string()), out.clone());
+        let mut interpreter = Interpreter::new();
+        interpreter.set_stdout(OutputBuffer::default());
+        interpreter.set_debugger(debugger(Debugger::new(console)));
+        let result = interpreter.eval_str(source);
+        (out.contents(), interpreter, result)
+    }
+
+    #[test]
+    fn stop_at_breakpoints_and_inspect() {
+        let source = "name: str = Jone\nage: int = 30\nif age > 20\ndo echo name\nendif";
+        let (out, _, result) = debug(source, "vars\np age > 20\np \"Hi $name\"\nc\n", |d| {
+            d.breakpoint(3)
+        });
+        result.unwrap();
+        assert_eq!(
+            out,
+            "Stopped at line 3 (breakpoint)\n>   3 | if age > 20\n(mbdb) age: int = 30\nname: str = Jone\n(mbdb) true\n(mbdb) Hi Jone\n(mbdb) "
+        );
+    }
+
+    #[test]
+    fn list_vars_by_scope() {
+        let source =
+            "count: int = 1\ntry\nlet count: int = 2\nlet step: int = 1\necho count\ncatch\nend";
+        let (out, _, result) = debug(source, "vars\nc\n", |d| d.breakpoint(5));
+        result.unwrap();
+        assert_eq!(
+            out,
+            "Stopped at line 5 (breakpoint)\n>   5 | echo count\n(mbdb) count: int = 2 (let in try)\nstep: int = 1 (let in try)\ncount: int = 1 (shadowed)\n(mbdb) "
+        );
+    }
+
+    #[test]
+    fn step_into_and_over_blocks() {
+        let source = "try\nthrow 'x'\ncatch\necho err.message\nend\ndone: bool = true";
+        let (out, interpreter, result) =
+            debug(source, "s\nbt\nn\nn\nn\n", |d| d.stop_on_entry(true));
+        result.unwrap();
+        // The commands aren't echoed, the next output follows the prompt
+        let stops = out
+            .lines()
+            .map(|line| line.trim_start_matches("(mbdb) "))
+            .filter(|line| line.starts_with("Stopped"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stops,
+            [
+                "Stopped at line 1 (entry)",
+                "Stopped at line 2 (step)",
+                "Stopped at line 4 (step)",
+                "Stopped at line 6 (step)"
+            ]
+        );
+        assert!(out.contains("(mbdb) #0 try at line 2\n#1 script at line 1\n"));
+        assert_eq!(interpreter.get_var("done"), Some(&VarValue::Bool(true)));
+    }
+
+    #[test]
+    fn backtrace_names_functions() {
+        let source = "fn greet\ntry\nlet name: str = \"Jone\"\necho name\ncatch\nend\nend\ngreet()";
+        let (out, _, result) = debug(source, "bt\nvars\nc\n", |d| d.breakpoint(4));
+        result.unwrap();
+        assert!(out.contains(
+            "(mbdb) #0 try at line 4\n#1 greet at line 2\n#2 script at line 8\n(mbdb) name: str = Jone (let in try)\n"
+        ));
+    }
+
+    #[test]
+    fn quit_ends_the_script() {
+        let source = "trap EXIT\ncleaned: bool = true\nend\necho 'a'\necho 'b'";
+        let (_, interpreter, result) = debug(source, "q\n", |d| d.breakpoint(4));
+        assert!(matches!(
+            result,
+            Err(InterpreterErr::ExeError {
+                err: ExeError::DebuggerQuit,
+                line: 4
+            })
+        ));
+        assert_eq!(interpreter.get_var("cleaned"), None);
+    }
+}
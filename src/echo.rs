@@ -1,5 +1,8 @@
-use crate::regex::RE_ECHO;
-use regex::Regex;
+use crate::{
+    regex::{cached_regex, RE_ECHO, RE_VAR_PATH},
+    template::Template,
+};
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use thiserror::Error;
 
@@ -9,12 +12,12 @@ pub enum EchoErr {
     NoMatch(String),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub struct Echo(pub String);
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Echo(pub Template);
 
 impl Echo {
     pub fn is_echo(s: &str) -> bool {
-        let re = Regex::new(RE_ECHO).unwrap();
+        let re = cached_regex!(RE_ECHO);
         re.is_match(s)
     }
 }
@@ -22,10 +25,15 @@ impl Echo {
 impl FromStr for Echo {
     type Err = EchoErr;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let re = Regex::new(RE_ECHO).unwrap();
+        let re = cached_regex!(RE_ECHO);
+
+        let path = s.trim().strip_prefix("echo").map(str::trim);
+        if let Some(path) = path.filter(|path| cached_regex!(RE_VAR_PATH).is_match(path)) {
+            return Ok(Self(Template::parse(path)));
+        }
 
         if let Some(caps) = re.captures(s) {
-            Ok(Self(caps["expr"].to_string()))
+            Ok(Self(Template::parse(&caps["expr"])))
         } else {
             Err(EchoErr::NoMatch(s.into()))
         }
@@ -54,6 +62,11 @@ mod tests {
         assert_eq!(expr.parse::<Echo>().unwrap(), Echo("$1".into()));
         let expr = "echo               some_var";
         assert_eq!(expr.parse::<Echo>().unwrap(), Echo("some_var".into()));
+        let expr = "echo cfg[\"servers\"][0]['host']";
+        assert_eq!(
+            expr.parse::<Echo>().unwrap(),
+            Echo("cfg[\"servers\"][0]['host']".into())
+        );
     }
 
     #[test]
@@ -1,74 +1,155 @@
-use regex::Regex;
+use crate::regex::{cached_regex, RE_INVALID_MATH_EXPR};
 
-use crate::regex::RE_INVALID_MATH_EXPR;
+/// Deepest nesting of groups and signs, deeper expressions are rejected instead of
+/// overflowing the stack
+const MAX_NESTING: usize = 64;
 
-enum Op {
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Token {
+    Num(f64),
     Plus,  // '+'
     Mins,  // '-'
-    Multi, // '*' | 'x'
+    Multi, // '*'
     Div,   // '/'
+    Open,  // '(' | '['
+    Close, // ')' | ']'
 }
 
-fn split<'a>(expr: &'a str, by: &'a str) -> Vec<&'a str> {
-    expr.split(by).map(|term| term.trim()).collect()
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
+        let token = match c {
+            '+' => Token::Plus,
+            '-' => Token::Mins,
+            '*' => Token::Multi,
+            '/' => Token::Div,
+            '(' | '[' => Token::Open,
+            ')' | ']' => Token::Close,
+            c if c.is_whitespace() => continue,
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = idx + c.len_utf8();
+                while let Some((next, c)) = chars.peek().copied() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    end = next + c.len_utf8();
+                    chars.next();
+                }
+                let num = &expr[idx..end];
+                Token::Num(
+                    num.parse()
+                        .map_err(|_| format!("`{}` is not a valid number", num))?,
+                )
+            }
+            c => return Err(format!("Unexpected `{}` in `{}`", c, expr)),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
 }
 
-pub fn eval(expr: &str) -> Result<f64, String> {
-    let re_validate = Regex::new(RE_INVALID_MATH_EXPR).unwrap();
+/// A recursive descent parser evaluating while it parses
+///
+/// ```text
+/// expr   = term (('+' | '-') term)*
+/// term   = factor (('*' | '/') factor)*
+/// factor = '-' factor | '+' factor | number | '(' expr ')'
+/// ```
+struct Parser<'a> {
+    expr: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
 
-    if re_validate.is_match(expr) {
-        return Err(format!("`{}` is not a valid math expression", expr));
+impl Parser<'_> {
+    fn peek(&self) -> Option<Token> {
+        self.tokens.get(self.pos).copied()
     }
 
-    let re = Regex::new(r"(\(|\[)(?P<expr>[^\)\(\]\[]+)(\)|\])").unwrap();
-    let mut temp_expr = expr.to_string();
-    for cap in re.captures_iter(expr) {
-        let res = eval(&cap["expr"]);
-        temp_expr = temp_expr.to_string().replace(&cap[0], &res?.to_string());
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek();
+        self.pos += 1;
+        token
     }
 
-    let expr = temp_expr.as_str();
-    let mut terms = split(expr, "+");
-    let mut op = Op::Plus;
-
-    if terms.len() == 1 {
-        terms = split(expr, "-");
-        op = Op::Mins;
+    fn invalid(&self) -> String {
+        format!("`{}` is not a valid math expression", self.expr)
     }
 
-    if terms.len() == 1 {
-        terms = split(expr, "x");
-        op = Op::Multi;
-    }
+    fn expr(&mut self) -> Result<f64, String> {
+        let mut result = self.term()?;
+        while let Some(op @ (Token::Plus | Token::Mins)) = self.peek() {
+            self.pos += 1;
+            let term = self.term()?;
+            match op {
+                Token::Plus => result += term,
+                _ => result -= term,
+            }
+        }
 
-    if terms.len() == 1 {
-        terms = split(expr, "*");
-        op = Op::Multi;
+        Ok(result)
     }
 
-    if terms.len() == 1 {
-        terms = split(expr, "/");
-        op = Op::Div;
+    fn term(&mut self) -> Result<f64, String> {
+        let mut result = self.factor()?;
+        while let Some(op @ (Token::Multi | Token::Div)) = self.peek() {
+            self.pos += 1;
+            let factor = self.factor()?;
+            match op {
+                Token::Multi => result *= factor,
+                _ => result /= factor,
+            }
+        }
+
+        Ok(result)
     }
 
-    let mut result: f64 = 0.0;
-    for (idx, term) in terms.into_iter().enumerate() {
-        let term = match term.parse() {
-            Ok(num) => num,
-            Err(_) => eval(term)?,
+    fn factor(&mut self) -> Result<f64, String> {
+        self.depth += 1;
+        if self.depth > MAX_NESTING {
+            return Err(format!("`{}` is nested too deeply", self.expr));
+        }
+
+        let result = match self.next() {
+            Some(Token::Num(num)) => Ok(num),
+            Some(Token::Mins) => self.factor().map(|factor| -factor),
+            Some(Token::Plus) => self.factor(),
+            Some(Token::Open) => {
+                let result = self.expr()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(result),
+                    _ => Err(self.invalid()),
+                }
+            }
+            _ => Err(self.invalid()),
         };
+        self.depth -= 1;
+        result
+    }
+}
 
-        if idx == 0 {
-            result = term;
-            continue;
-        }
+/// Evaluate a math expression `ex: (12 + 12) / 4`. `*` and `/` bind tighter than `+` and `-`,
+/// operators of the same precedence are applied from left to right and groups are written
+/// with `()` or `[]`
+pub fn eval(expr: &str) -> Result<f64, String> {
+    let re_validate = cached_regex!(RE_INVALID_MATH_EXPR);
 
-        match op {
-            Op::Plus => result += term,
-            Op::Mins => result -= term,
-            Op::Multi => result *= term,
-            Op::Div => result /= term,
-        }
+    if re_validate.is_match(expr) {
+        return Err(format!("`{}` is not a valid math expression", expr));
+    }
+
+    let mut parser = Parser {
+        expr,
+        tokens: tokenize(expr)?,
+        pos: 0,
+        depth: 0,
+    };
+    let result = parser.expr()?;
+    if parser.pos != parser.tokens.len() {
+        return Err(parser.invalid());
     }
 
     Ok(result)
@@ -77,6 +158,7 @@ pub fn eval(expr: &str) -> Result<f64, String> {
 #[cfg(test)]
 mod test {
     use super::eval;
+    use proptest::prelude::*;
 
     #[test]
     fn eval_simple_math_expr() {
@@ -101,4 +183,115 @@ mod test {
         let res = eval("12");
         assert_eq!(res.unwrap(), 12.0)
     }
+
+    #[test]
+    fn eval_signs_and_malformed_exprs() {
+        assert_eq!(eval("-3 * (2 + -1)").unwrap(), -3.0);
+        assert_eq!(eval("4 - (1 - 2)").unwrap(), 5.0);
+        assert_eq!(eval("((1 + 2) * 3)").unwrap(), 9.0);
+        for expr in [
+            "",
+            "1 2",
+            "(",
+            "1 +",
+            "(1 + 2",
+            "1 + 2)",
+            "1..2",
+            &"(".repeat(1000),
+        ] {
+            assert!(eval(expr).is_err(), "`{}` should be invalid", expr);
+        }
+    }
+
+    /// An arithmetic expression and its value computed by Rust
+    #[derive(Debug, Clone)]
+    enum Expr {
+        Num(u16),
+        Neg(Box<Expr>),
+        Bin(Box<Expr>, char, Box<Expr>),
+    }
+
+    impl Expr {
+        fn value(&self) -> f64 {
+            match self {
+                Expr::Num(num) => *num as f64,
+                Expr::Neg(expr) => -expr.value(),
+                Expr::Bin(left, op, right) => {
+                    let (left, right) = (left.value(), right.value());
+                    match op {
+                        '+' => left + right,
+                        '-' => left - right,
+                        '*' => left * right,
+                        _ => left / right,
+                    }
+                }
+            }
+        }
+
+        fn precedence(&self) -> u8 {
+            match self {
+                Expr::Bin(_, '+' | '-', _) => 1,
+                Expr::Bin(..) => 2,
+                _ => 3,
+            }
+        }
+
+        /// Written with the fewest parentheses, relying on precedence and associativity
+        fn source(&self) -> String {
+            match self {
+                Expr::Num(num) => num.to_string(),
+                Expr::Neg(expr) if expr.precedence() < 3 => format!("-({})", expr.source()),
+                Expr::Neg(expr) => format!("-{}", expr.source()),
+                Expr::Bin(left, op, right) => {
+                    let precedence = self.precedence();
+                    let left = match left.precedence() < precedence {
+                        true => format!("({})", left.source()),
+                        false => left.source(),
+                    };
+                    let right = match right.precedence() <= precedence {
+                        true => format!("[{}]", right.source()),
+                        false => right.source(),
+                    };
+                    format!("{} {} {}", left, op, right)
+                }
+            }
+        }
+    }
+
+    fn expr() -> impl Strategy<Value = Expr> {
+        any::<u16>()
+            .prop_map(Expr::Num)
+            .prop_recursive(6, 64, 2, |inner| {
+                prop_oneof![
+                    inner.clone().prop_map(|expr| Expr::Neg(Box::new(expr))),
+                    (
+                        inner.clone(),
+                        prop::sample::select(vec!['+', '-', '*', '/']),
+                        inner
+                    )
+                        .prop_map(|(left, op, right)| Expr::Bin(
+                            Box::new(left),
+                            op,
+                            Box::new(right)
+                        )),
+                ]
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn eval_matches_rust_arithmetic(expr in expr()) {
+            let expected = expr.value();
+            let result = eval(&expr.source()).unwrap();
+            prop_assert!(
+                result == expected || result.is_nan() && expected.is_nan(),
+                "`{}` = {}, expected {}", expr.source(), result, expected
+            );
+        }
+
+        #[test]
+        fn eval_never_panics(expr in "[0-9 ()\\[\\]+*/.-]{0,40}") {
+            let _ = eval(&expr);
+        }
+    }
 }
@@ -1,17 +1,34 @@
 use crate::{
-    cmp::{CompareExpr, CompareExprErr},
+    arg::{Arg, ArgKind, Operand},
+    call::{Call, CallErr},
+    cmp::{CompareExpr, CompareExprErr, Operator},
     conditions::Condition,
+    debugger::Debugger,
     echo::Echo,
-    lang_parser::Expression,
-    regex::{RE_INPUT_FUNC, RE_VAR_EXPANSION},
-    utils::is_input_fn,
-    variables::{VarValue, Variable},
+    filesystem as fs,
+    host::BoxedHostFn,
+    jobs::{JobErr, JobTable, Run},
+    json::{self, JsonErr},
+    lang_parser::{Expression, LangParser},
+    limits::Limits,
+    match_case::{Case, Match, Pattern},
+    readonly::ReadonlyKind,
+    sandbox::{Capability, Sandbox},
+    scope::ScopeKind,
+    streams::Streams,
+    template::{Segment, Template},
+    testing::diff_values,
+    trace::{traced, SetTrace},
+    trap::{Signals, Trap, TrapEvent},
+    try_catch::{Throw, TryCatch},
+    variables::{split_path, CallVariable, DataType, VarErr, VarValue, Variable},
 };
-use regex::Regex;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
     env,
-    io::{stdin, stdout, Write},
+    fmt::Display,
+    io::{self, BufRead, Write},
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
@@ -19,106 +36,1118 @@ use thiserror::Error;
 pub enum ExeError {
     #[error("Compare Expr Error: {0}")]
     CompareExprErr(#[from] CompareExprErr),
-    #[error("Invalid `input` function: {0}")]
-    InvalidInputFunc(String),
+    #[error("IO error: {0}")]
+    IoErr(String),
+    #[error("Unknown function: `{0}`")]
+    UnknownFunction(String),
+    #[error("Invalid call: {0}")]
+    InvalidCall(String),
+    #[error("Call error: {0}")]
+    CallErr(#[from] CallErr),
+    #[error("Type error: {0}")]
+    TypeErr(#[from] VarErr),
+    #[error("{0}")]
+    Thrown(String),
+    #[error("Received SIG{0}")]
+    Signal(TrapEvent),
+    #[error("Command failed: {0}")]
+    CommandFailed(String),
+    #[error("Job error: {0}")]
+    JobErr(#[from] JobErr),
+    #[error("{0}")]
+    HostFnErr(String),
+    #[error("{0}")]
+    JsonErr(#[from] JsonErr),
+    #[error("Permission denied: {1} requires the `{0}` capability")]
+    PermissionDenied(Capability, String),
+    #[error("Step limit of {0} statements exceeded")]
+    StepLimit(u64),
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Memory limit of {0} bytes exceeded")]
+    MemoryLimit(usize),
+    #[error("Output limit of {0} bytes exceeded")]
+    OutputLimit(usize),
+    #[error("Recursion limit of {0} exceeded")]
+    RecursionLimit(usize),
+    #[error("Stopped by the debugger")]
+    DebuggerQuit,
+    #[error("Assertion failed: {0}")]
+    AssertionFailed(String),
+    #[error("`{0}` is a {1} and can't be reassigned")]
+    Readonly(String, ReadonlyKind),
+    #[error("`{0}` is used before its declaration")]
+    Undeclared(String),
 }
 
-pub struct Executor<'a> {
-    vars: HashMap<&'a str, VarValue>,
-    expressions: &'a Vec<Expression>,
-    args: Vec<String>,
+/// The category of a runtime error, exposed to scripts as `err.kind`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorKind {
+    Compare,
+    Io,
+    Call,
+    Type,
+    Throw,
+    Signal,
+    Command,
+    Permission,
+    Limit,
+    Assert,
 }
 
-impl<'a> Executor<'a> {
-    pub fn new(expressions: &'a Vec<Expression>) -> Self {
-        let mut vars = HashMap::new();
-        expressions.iter().for_each(|e| {
-            if let Expression::Var(var) = e {
-                vars.insert(var.name.as_str(), var.value.clone());
-            }
-        });
-        let args = env::args().collect::<Vec<_>>();
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            ErrorKind::Compare => "compare",
+            ErrorKind::Io => "io",
+            ErrorKind::Call => "call",
+            ErrorKind::Type => "type",
+            ErrorKind::Throw => "throw",
+            ErrorKind::Signal => "signal",
+            ErrorKind::Command => "command",
+            ErrorKind::Permission => "permission",
+            ErrorKind::Limit => "limit",
+            ErrorKind::Assert => "assert",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+impl ExeError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            ExeError::CompareExprErr(_) => ErrorKind::Compare,
+            ExeError::IoErr(_) => ErrorKind::Io,
+            ExeError::UnknownFunction(_)
+            | ExeError::InvalidCall(_)
+            | ExeError::CallErr(_)
+            | ExeError::HostFnErr(_) => ErrorKind::Call,
+            ExeError::TypeErr(_)
+            | ExeError::JsonErr(_)
+            | ExeError::Readonly(..)
+            | ExeError::Undeclared(_) => ErrorKind::Type,
+            ExeError::Thrown(_) => ErrorKind::Throw,
+            ExeError::Signal(_) | ExeError::DebuggerQuit => ErrorKind::Signal,
+            ExeError::CommandFailed(_) | ExeError::JobErr(_) => ErrorKind::Command,
+            ExeError::PermissionDenied(..) => ErrorKind::Permission,
+            ExeError::StepLimit(_)
+            | ExeError::Timeout(_)
+            | ExeError::MemoryLimit(_)
+            | ExeError::OutputLimit(_)
+            | ExeError::RecursionLimit(_) => ErrorKind::Limit,
+            ExeError::AssertionFailed(_) => ErrorKind::Assert,
+        }
+    }
+
+    /// Signals and exceeded limits end the script, `try` blocks can't catch them
+    pub fn is_catchable(&self) -> bool {
+        !matches!(self.kind(), ErrorKind::Signal | ErrorKind::Limit)
+    }
+}
+
+#[derive(Default)]
+pub struct Executor {
+    pub vars: HashMap<String, VarValue>,
+    /// Script name `$0`
+    pub script_name: String,
+    /// Positional arguments `$1`, `$2`, ...
+    pub args: Vec<String>,
+    pub streams: Streams,
+    /// Source line of the expression being executed
+    pub(crate) line: usize,
+    /// Registered traps in registration order
+    traps: Vec<Trap>,
+    /// Variables declared with `const` or `readonly`
+    readonly: HashMap<String, ReadonlyKind>,
+    /// Variables declared anywhere in the evaluated programs, reading one before its
+    /// declaration runs is an error
+    declared: HashSet<String>,
+    /// Bodies of the functions defined so far
+    functions: HashMap<String, LangParser>,
+    /// Installed with the first trap so `INT`/`TERM` (and `EXIT`) handlers can run
+    signals: Option<Signals>,
+    jobs: JobTable,
+    /// Functions registered by the embedder, built-ins take precedence
+    pub host_fns: HashMap<String, BoxedHostFn>,
+    pub sandbox: Sandbox,
+    pub limits: Limits,
+    /// Statements executed in the current evaluation
+    steps: u64,
+    deadline: Option<Instant>,
+    /// Bytes written to stdout and stderr in the current evaluation
+    output_bytes: usize,
+    /// Current nesting of blocks and calls
+    depth: usize,
+    /// Blocks being executed, the innermost last
+    pub(crate) frames: Vec<Frame>,
+    /// Bytes held by the script's and the blocks' variables
+    used_bytes: usize,
+    pub(crate) debugger: Option<Debugger>,
+    /// Print every statement to stderr before it runs, toggled by `set trace on|off`
+    pub trace: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FrameKind {
+    /// The top level of the script
+    Script,
+    /// A `try`, `catch`, `trap` or `case` body, or an `if` branch
+    Block,
+    /// The body of a called function, it doesn't see the blocks it's called from
+    Function,
+}
+
+/// A block being executed `ex: try`, with the line of the statement that opened it
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Frame {
+    pub kind: FrameKind,
+    /// The block's keyword `ex: try`, or the name of the called function
+    pub name: String,
+    pub line: usize,
+    /// Variables declared with `let` in the block
+    pub vars: HashMap<String, VarValue>,
+    /// Variables of a function declared with `const` or `readonly`
+    pub readonly: HashMap<String, ReadonlyKind>,
+    /// Bodies of the `defer` blocks of a function, run when it returns
+    pub defers: Vec<LangParser>,
+}
+
+impl Frame {
+    fn new(kind: FrameKind, name: &str, line: usize) -> Self {
         Self {
-            vars,
-            expressions,
-            args,
-        }
-    }
-
-    pub fn execute(&mut self) -> Result<(), ExeError> {
-        for expr in self.expressions {
-            if let Expression::Echo(Echo(s)) = expr {
-                self.eval_echo(s);
-            } else if let Expression::Condition(con) = expr {
-                self.eval_condition(con.as_ref())?;
-            } else if let Expression::Var(Variable { name, value }) = expr {
-                // todo: the `input` function evaluation should go here
-                // todo: Handle the case of empty input function
-                // ! todo: write tests for the `input` function
-                if is_input_fn(&value.to_string()) {
-                    let input = self.read_input(value)?.parse::<VarValue>().unwrap();
-                    self.vars.insert(&name, input);
-                } else {
-                    let result = self.eval_var_expansion(&value.to_string());
-                    self.vars.insert(&name, result);
+            kind,
+            name: name.to_string(),
+            line,
+            vars: HashMap::new(),
+            readonly: HashMap::new(),
+            defers: Vec::new(),
+        }
+    }
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run the program followed by the registered traps. An error raised by a trap is only
+    /// returned if the program itself succeeded. Declarations take effect when they run
+    pub fn execute(&mut self, program: &LangParser) -> Result<(), ExeError> {
+        self.begin();
+        self.declared
+            .extend(program.declarations().into_iter().map(|(name, ..)| name));
+        let result = self.eval_block(program, FrameKind::Script, "script");
+        self.run_traps(result)
+    }
+
+    /// Reset the per evaluation budgets
+    pub(crate) fn begin(&mut self) {
+        self.steps = 0;
+        self.output_bytes = 0;
+        // A timeout too long to be represented is no deadline at all
+        self.deadline = self
+            .limits
+            .timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        self.jobs.deadline = self.deadline;
+    }
+
+    /// `const` and `readonly` variables can't change
+    pub(crate) fn check_writable(&self, name: &str) -> Result<(), ExeError> {
+        match self.readonly.get(name) {
+            Some(kind) => Err(ExeError::Readonly(name.to_string(), *kind)),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail if replacing a variable of `old_size` bytes with `value` exceeds the memory limit,
+    /// the script's and the blocks' variables count
+    fn check_memory(&self, old_size: usize, value: &VarValue) -> Result<(), ExeError> {
+        match self.limits.max_memory {
+            Some(max_memory) if self.used_bytes - old_size + value.size() > max_memory => {
+                Err(ExeError::MemoryLimit(max_memory))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Assign a script variable within the memory limit, `const` and `readonly` ones can't
+    /// change
+    fn set_var(&mut self, name: String, value: VarValue) -> Result<(), ExeError> {
+        self.check_writable(&name)?;
+        self.check_memory(self.vars.get(&name).map_or(0, VarValue::size), &value)?;
+        self.insert_var(name, value);
+
+        Ok(())
+    }
+
+    /// Assign a script variable without checking it, the embedder and the VM write through
+    /// it to keep the memory count
+    pub(crate) fn insert_var(&mut self, name: String, value: VarValue) {
+        self.used_bytes += value.size();
+        if let Some(old) = self.vars.insert(name, value) {
+            self.used_bytes -= old.size();
+        }
+    }
+
+    /// Assign a `let` variable of the frame at `idx`, the memory limit is already checked
+    fn insert_local(&mut self, idx: usize, name: String, value: VarValue) {
+        self.used_bytes += value.size();
+        if let Some(old) = self.frames[idx].vars.insert(name, value) {
+            self.used_bytes -= old.size();
+        }
+    }
+
+    fn push_frame(&mut self, kind: FrameKind, name: &str) {
+        self.frames.push(Frame::new(kind, name, self.line));
+    }
+
+    /// Leave the innermost block, its `let` variables are freed
+    fn pop_frame(&mut self) {
+        if let Some(frame) = self.frames.pop() {
+            self.used_bytes -= frame.vars.values().map(VarValue::size).sum::<usize>();
+        }
+    }
+
+    /// Index of the outermost frame the current statement sees, a function body doesn't see
+    /// the blocks it's called from
+    fn scope_start(&self) -> usize {
+        self.frames
+            .iter()
+            .rposition(|frame| frame.kind == FrameKind::Function)
+            .unwrap_or(0)
+    }
+
+    /// Index of the innermost frame with a `let` variable named `name` the current statement
+    /// sees
+    fn local_frame(&self, name: &str) -> Option<usize> {
+        (self.scope_start()..self.frames.len())
+            .rev()
+            .find(|idx| self.frames[*idx].vars.contains_key(name))
+    }
+
+    /// The variable named `name` the current statement sees, `let` ones shadow the script's
+    fn var(&self, name: &str) -> Option<&VarValue> {
+        match self.local_frame(name) {
+            Some(idx) => self.frames[idx].vars.get(name),
+            None => self.vars.get(name),
+        }
+    }
+
+    /// Assign the innermost variable named `name` the current statement sees, or a script one
+    fn assign(&mut self, name: String, value: VarValue) -> Result<(), ExeError> {
+        let Some(idx) = self.local_frame(&name) else {
+            return self.set_var(name, value);
+        };
+        if let Some(kind) = self.frames[idx].readonly.get(&name) {
+            return Err(ExeError::Readonly(name, *kind));
+        }
+        let old_size = self.frames[idx].vars.get(&name).map_or(0, VarValue::size);
+        self.check_memory(old_size, &value)?;
+        self.insert_local(idx, name, value);
+
+        Ok(())
+    }
+
+    /// Declare a `let` variable in the innermost block, at the top level of the script it's
+    /// a script variable
+    fn declare_local(&mut self, name: String, value: VarValue) -> Result<(), ExeError> {
+        let old_size = match self.frames.last() {
+            Some(frame) if frame.kind != FrameKind::Script => {
+                frame.vars.get(&name).map_or(0, VarValue::size)
+            }
+            _ => return self.set_var(name, value),
+        };
+        self.check_memory(old_size, &value)?;
+        self.insert_local(self.frames.len() - 1, name, value);
+
+        Ok(())
+    }
+
+    /// Declare a `const` or `readonly` variable of the function being called, each call
+    /// declares it again. Outside of a function it's a script variable
+    fn declare_readonly(
+        &mut self,
+        name: String,
+        value: VarValue,
+        kind: ReadonlyKind,
+    ) -> Result<(), ExeError> {
+        let idx = self.scope_start();
+        if self
+            .frames
+            .get(idx)
+            .is_none_or(|frame| frame.kind != FrameKind::Function)
+        {
+            self.set_var(name.clone(), value)?;
+            self.readonly.insert(name, kind);
+            return Ok(());
+        }
+        let old_size = self.frames[idx].vars.get(&name).map_or(0, VarValue::size);
+        self.check_memory(old_size, &value)?;
+        self.insert_local(idx, name.clone(), value);
+        self.frames[idx].readonly.insert(name, kind);
+
+        Ok(())
+    }
+
+    /// The scopes the current statement sees and their variables, innermost first. The
+    /// script's scope is last
+    pub(crate) fn scopes(&self) -> Vec<(&str, &HashMap<String, VarValue>)> {
+        self.frames[self.scope_start()..]
+            .iter()
+            .rev()
+            .filter(|frame| frame.kind != FrameKind::Script)
+            .map(|frame| (frame.name.as_str(), &frame.vars))
+            .chain([("script", &self.vars)])
+            .collect()
+    }
+
+    /// Count a statement against the step budget and the deadline
+    pub(crate) fn step(&mut self) -> Result<(), ExeError> {
+        self.steps += 1;
+        if let Some(max_steps) = self.limits.max_steps {
+            if self.steps > max_steps {
+                return Err(ExeError::StepLimit(max_steps));
+            }
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(ExeError::Timeout(self.limits.timeout.unwrap_or_default()));
+        }
+
+        Ok(())
+    }
+
+    /// Enter a nested block or call, must be paired with `leave`
+    pub(crate) fn enter(&mut self) -> Result<(), ExeError> {
+        if let Some(max_depth) = self.limits.max_depth {
+            if self.depth >= max_depth {
+                return Err(ExeError::RecursionLimit(max_depth));
+            }
+        }
+        self.depth += 1;
+
+        Ok(())
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    pub(crate) fn write_stdout(&mut self, bytes: &[u8]) -> Result<(), ExeError> {
+        self.count_output(bytes.len())?;
+        self.streams
+            .stdout
+            .write_all(bytes)
+            .and_then(|_| self.streams.stdout.flush())
+            .map_err(Executor::write_err)
+    }
+
+    fn write_stderr(&mut self, bytes: &[u8]) -> Result<(), ExeError> {
+        self.count_output(bytes.len())?;
+        self.streams
+            .stderr
+            .write_all(bytes)
+            .map_err(Executor::write_err)
+    }
+
+    fn count_output(&mut self, len: usize) -> Result<(), ExeError> {
+        self.output_bytes += len;
+        match self.limits.max_output {
+            Some(max_output) if self.output_bytes > max_output => {
+                Err(ExeError::OutputLimit(max_output))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn job_err(&self, err: JobErr) -> ExeError {
+        match err {
+            JobErr::TimedOut(_) => ExeError::Timeout(self.limits.timeout.unwrap_or_default()),
+            err => ExeError::JobErr(err),
+        }
+    }
+
+    fn run_traps(&mut self, result: Result<(), ExeError>) -> Result<(), ExeError> {
+        let line = self.line;
+        let mut events = match &result {
+            Ok(()) => vec![],
+            Err(ExeError::Signal(event)) => vec![*event],
+            Err(_) => vec![TrapEvent::Err],
+        };
+        events.push(TrapEvent::Exit);
+
+        let mut trap_err = None;
+        for event in events {
+            for trap in self.traps.iter().rev().cloned().collect::<Vec<_>>() {
+                if !trap.events.contains(&event) {
+                    continue;
                 }
+                if let (TrapEvent::Err, Err(err)) = (event, &result) {
+                    let err = self.err_value(err);
+                    if let Err(err) = self.set_var("err".into(), err) {
+                        trap_err.get_or_insert(err);
+                        continue;
+                    }
+                }
+                if let Err(err) = self.eval_block(&trap.body, FrameKind::Block, "trap") {
+                    trap_err.get_or_insert(err);
+                }
+            }
+        }
+
+        self.traps.clear();
+        // Leave the signals to the host once the script is over
+        self.signals = None;
+        if result.is_err() {
+            self.line = line;
+        }
+        result.and(trap_err.map_or(Ok(()), Err))
+    }
+
+    /// Source line of the last executed expression, points at the failing one after an error
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    fn eval_block(
+        &mut self,
+        block: &LangParser,
+        kind: FrameKind,
+        name: &str,
+    ) -> Result<(), ExeError> {
+        self.enter()?;
+        self.push_frame(kind, name);
+        let result = self.eval_statements(block);
+        let result = self.run_defers(result);
+        self.pop_frame();
+        self.leave();
+        result
+    }
+
+    /// Run the blocks deferred in the innermost frame, the last deferred first. An error raised
+    /// by one is only returned if the frame's statements succeeded
+    fn run_defers(&mut self, result: Result<(), ExeError>) -> Result<(), ExeError> {
+        let line = self.line;
+        let mut defer_err = None;
+        while let Some(body) = self.frames.last_mut().and_then(|frame| frame.defers.pop()) {
+            if let Err(err) = self.eval_block(&body, FrameKind::Block, "defer") {
+                defer_err.get_or_insert(err);
+            }
+        }
+
+        if result.is_err() {
+            self.line = line;
+        }
+        result.and(defer_err.map_or(Ok(()), Err))
+    }
+
+    fn eval_statements(&mut self, block: &LangParser) -> Result<(), ExeError> {
+        for (expr, line) in block.experssions.iter().zip(&block.lines) {
+            if let Some(event) = self.signals.as_ref().and_then(Signals::take) {
+                return Err(ExeError::Signal(event));
+            }
+            self.line = *line;
+            if let Some(mut debugger) = self.debugger.take() {
+                let result = debugger.statement(self);
+                self.debugger = Some(debugger);
+                result?;
+            }
+            self.step()?;
+            self.eval_expr(expr)?;
+        }
+
+        Ok(())
+    }
+
+    fn eval_expr(&mut self, expr: &Expression) -> Result<(), ExeError> {
+        // Conditions and matches are traced once their operands are evaluated
+        if self.trace && !matches!(expr, Expression::Condition(_) | Expression::Match(_)) {
+            let statement = self.trace_expr(expr)?;
+            self.write_trace(&statement)?;
+        }
+
+        match expr {
+            Expression::Echo(Echo(s)) => self.eval_echo(s)?,
+            Expression::Condition(con) => self.eval_condition(con.as_ref())?,
+            Expression::Var(_) | Expression::CallVar(_) => {
+                let (name, value) = self.eval_decl(expr)?;
+                self.assign(name, value)?;
+            }
+            Expression::Call(call) => {
+                self.eval_call(call)?;
+            }
+            Expression::TryCatch(try_catch) => self.eval_try_catch(try_catch)?,
+            Expression::Trap(trap) => {
+                let on_signal = trap
+                    .events
+                    .iter()
+                    .any(|event| matches!(event, TrapEvent::Int | TrapEvent::Term));
+                if on_signal && self.signals.is_none() {
+                    let signals =
+                        Signals::register().map_err(|e| ExeError::IoErr(format!("trap: {}", e)))?;
+                    self.signals = Some(signals);
+                }
+                self.traps.push(trap.as_ref().clone());
+            }
+            Expression::Defer(defer) => {
+                let function = self
+                    .frames
+                    .iter_mut()
+                    .rfind(|frame| frame.kind == FrameKind::Function)
+                    .ok_or_else(|| ExeError::InvalidCall("`defer` outside a function".into()))?;
+                function.defers.push(defer.body.clone());
+            }
+            Expression::Throw(Throw(s)) => {
+                return Err(ExeError::Thrown(self.eval_var_expansion(s)?.to_string()))
+            }
+            Expression::Run(run) => self.eval_run(run)?,
+            Expression::SetTrace(SetTrace(on)) => self.trace = *on,
+            Expression::Function(function) => {
+                self.functions
+                    .insert(function.name.clone(), function.body.clone());
+            }
+            Expression::Match(block) => self.eval_match(block)?,
+            Expression::Readonly(readonly) => {
+                let (name, value) = match (readonly.kind, readonly.decl.as_ref()) {
+                    (ReadonlyKind::Const, Expression::Var(Variable { name, value, .. })) => {
+                        (name.clone(), value.clone())
+                    }
+                    (_, decl) => self.eval_decl(decl)?,
+                };
+                self.declare_readonly(name, value, readonly.kind)?;
+            }
+            Expression::Scoped(scoped) => {
+                let (name, value) = self.eval_decl(&scoped.decl)?;
+                match scoped.kind {
+                    ScopeKind::Let => self.declare_local(name, value)?,
+                    ScopeKind::Global => self.set_var(name, value)?,
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The name and value of a `name: type = value` declaration
+    fn eval_decl(&mut self, decl: &Expression) -> Result<(String, VarValue), ExeError> {
+        match decl {
+            Expression::Var(Variable { name, template, .. }) => {
+                Ok((name.clone(), self.eval_var_expansion(template)?))
+            }
+            Expression::CallVar(CallVariable {
+                name,
+                datatype,
+                call,
+            }) => Ok((name.clone(), self.eval_call(call)?.coerce(datatype)?)),
+            decl => unreachable!("`{:?}` is not a declaration", decl),
+        }
+    }
+
+    /// Write `+ file:line: statement` to stderr
+    fn write_trace(&mut self, statement: &str) -> Result<(), ExeError> {
+        let file = match self.script_name.as_str() {
+            "" => "-",
+            name => name,
+        };
+        let line = format!("+ {}:{}: {}\n", file, self.line, statement);
+        self.write_stderr(line.as_bytes())
+    }
+
+    /// The statement with its variables expanded, calls nested in arguments are shown as
+    /// written since evaluating them could have side effects
+    fn trace_expr(&self, expr: &Expression) -> Result<String, ExeError> {
+        let statement = match expr {
+            Expression::Var(Variable { name, template, .. }) => {
+                format!("{} = {}", name, traced(&self.eval_var_expansion(template)?))
+            }
+            Expression::Echo(Echo(s)) => format!("echo {}", self.expand_vars(s)?),
+            Expression::Throw(Throw(s)) => format!("throw {}", self.expand_vars(s)?),
+            Expression::Call(call) => self.trace_call(call)?,
+            Expression::CallVar(CallVariable {
+                name,
+                datatype,
+                call,
+            }) => format!("{}: {} = {}", name, datatype, self.trace_call(call)?),
+            Expression::Run(Run {
+                program,
+                args,
+                background,
+            }) => {
+                let mut words = vec![self.trace_arg(program)?];
+                for arg in args {
+                    words.push(self.trace_arg(arg)?);
+                }
+                let background = if *background { " &" } else { "" };
+                format!("run {}{}", words.join(" "), background)
+            }
+            Expression::TryCatch(_) => "try".to_string(),
+            Expression::Trap(trap) => {
+                let events = trap
+                    .events
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>();
+                format!("trap {}", events.join(" "))
+            }
+            Expression::SetTrace(SetTrace(on)) => {
+                format!("set trace {}", if *on { "on" } else { "off" })
+            }
+            Expression::Defer(_) => "defer".to_string(),
+            Expression::Function(function) => format!("fn {}", function.name),
+            Expression::Readonly(readonly) => {
+                format!("{} {}", readonly.kind, self.trace_expr(&readonly.decl)?)
+            }
+            Expression::Scoped(scoped) => {
+                let mut chain = self
+                    .scopes()
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>();
+                chain.reverse();
+                format!(
+                    "{} {} (scope: {})",
+                    scoped.kind,
+                    self.trace_expr(&scoped.decl)?,
+                    chain.join(" > ")
+                )
+            }
+            Expression::Condition(_) | Expression::Match(_) => String::new(),
+        };
+
+        Ok(statement)
+    }
+
+    fn trace_call(&self, call: &Call) -> Result<String, ExeError> {
+        let args = call
+            .args
+            .iter()
+            .map(|arg| self.trace_arg(arg))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("{}({})", call.name, args.join(", ")))
+    }
+
+    fn trace_arg(&self, arg: &Arg) -> Result<String, ExeError> {
+        match arg.kind() {
+            ArgKind::Quoted(template) => Ok(traced(&VarValue::Str(self.expand_vars(template)?))),
+            ArgKind::Call(_) => Ok(arg.source().trim().to_string()),
+            ArgKind::Compare(_, operand) | ArgKind::Operand(operand) => {
+                Ok(traced(&self.found_var_or_create(operand)?))
+            }
+        }
+    }
+
+    fn eval_try_catch(&mut self, try_catch: &TryCatch) -> Result<(), ExeError> {
+        let TryCatch {
+            body,
+            err_name,
+            catch_body,
+        } = try_catch;
+
+        match self.eval_block(body, FrameKind::Block, "try") {
+            Err(err) if !err.is_catchable() => Err(err),
+            Err(err) => {
+                let err = self.err_value(&err);
+                self.set_var(err_name.clone(), err)?;
+                self.eval_block(catch_body, FrameKind::Block, "catch")
             }
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// The script representation of an error, a map with `kind`, `message` and `line`
+    pub(crate) fn err_value(&self, err: &ExeError) -> VarValue {
+        VarValue::Map(BTreeMap::from([
+            ("kind".to_string(), VarValue::Str(err.kind().to_string())),
+            ("message".to_string(), VarValue::Str(err.to_string())),
+            ("line".to_string(), VarValue::Int(self.line as i32)),
+        ]))
+    }
+
+    fn eval_run(&mut self, run: &Run) -> Result<(), ExeError> {
+        let program = self.eval_arg(&run.program)?.to_string();
+        let args = run
+            .args
+            .iter()
+            .map(|arg| Ok(self.eval_arg(arg)?.to_string()))
+            .collect::<Result<Vec<_>, ExeError>>()?;
+
+        self.require(Capability::Commands, &format!("`run {}`", program))?;
+        // Output of commands has to go through the sinks to be counted against the limit
+        self.jobs.capture = !self.streams.inherited || self.limits.max_output.is_some();
+        if run.background {
+            self.jobs.spawn(&program, &args)?;
+            return Ok(());
+        }
+
+        let status = self
+            .jobs
+            .run(&program, &args)
+            .map_err(|e| self.job_err(e))?;
+        self.forward_captured()?;
+        if status != 0 {
+            return Err(ExeError::CommandFailed(format!(
+                "`{}` exited with status {}",
+                program, status
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Write the output of commands that ran with redirected streams to the sinks
+    fn forward_captured(&mut self) -> Result<(), ExeError> {
+        for captured in self.jobs.take_captured() {
+            self.write_stdout(&captured.stdout)?;
+            self.write_stderr(&captured.stderr)?;
         }
 
         Ok(())
     }
 
-    fn eval_echo(&self, s: &str) {
-        let res = self.eval_var_expansion(s);
-        println!("{}", res);
+    fn write_err(e: io::Error) -> ExeError {
+        ExeError::IoErr(format!("write: {}", e))
+    }
+
+    fn eval_echo(&mut self, s: &Template) -> Result<(), ExeError> {
+        let res = self.eval_var_expansion(s)?;
+        self.write_stdout(format!("{}\n", res).as_bytes())
+    }
+
+    /// Evaluate a function call, nested calls count against the recursion limit
+    fn eval_call(&mut self, call: &Call) -> Result<VarValue, ExeError> {
+        self.enter()?;
+        let result = self.eval_builtin(call);
+        self.leave();
+        result
+    }
+
+    /// Evaluate a built-in, script or host function call
+    fn eval_builtin(&mut self, call: &Call) -> Result<VarValue, ExeError> {
+        let mut args = call
+            .args
+            .iter()
+            .enumerate()
+            .map(|(idx, arg)| match (call.name.as_str(), idx) {
+                // `assert(count > 2)` checks a comparison
+                ("assert", 0) => self.eval_test(arg),
+                _ => self.eval_arg(arg),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let io_err = |e: io::Error| ExeError::IoErr(format!("{}: {}", call.name, e));
+
+        if matches!(
+            call.name.as_str(),
+            "write_file" | "append_file" | "mkdir" | "rm" | "cp" | "mv"
+        ) {
+            self.require(Capability::FsWrite, &format!("`{}`", call.name))?;
+        }
+        if call.name == "kill" {
+            self.require(Capability::Commands, "`kill`")?;
+        }
+
+        let value = match call.name.as_str() {
+            "input" => {
+                self.require(Capability::Stdin, "`input`")?;
+                let prompt = match args.as_slice() {
+                    [] => String::new(),
+                    [prompt] => prompt.to_string(),
+                    _ => return Err(Executor::arity_err(call, "0 or 1", args.len())),
+                };
+                VarValue::Str(self.read_input(&prompt)?)
+            }
+            "read_file" => {
+                let [path] = Executor::expect_args(call, args)?;
+                VarValue::Str(fs::read_file(&path.to_string()).map_err(io_err)?)
+            }
+            "write_file" | "append_file" => {
+                let [path, content] = Executor::expect_args(call, args)?;
+                let (path, content) = (path.to_string(), content.to_string());
+                let written = if call.name == "write_file" {
+                    fs::write_file(&path, &content)
+                } else {
+                    fs::append_file(&path, &content)
+                };
+                byte_count(written.map_err(io_err)?)
+            }
+            "ls" => {
+                let dir = match args.as_slice() {
+                    [] => ".".to_string(),
+                    [dir] => dir.to_string(),
+                    _ => return Err(Executor::arity_err(call, "0 or 1", args.len())),
+                };
+                let entries = fs::ls(&dir).map_err(io_err)?;
+                VarValue::List(entries.into_iter().map(VarValue::Str).collect())
+            }
+            "mkdir" => {
+                let parents = Executor::take_flag(&mut args, &["-p"]);
+                let [path] = Executor::expect_args(call, args)?;
+                fs::mkdir(&path.to_string(), parents).map_err(io_err)?;
+                VarValue::Bool(true)
+            }
+            "rm" => {
+                let recursive = Executor::take_flag(&mut args, &["-r", "-rf", "-R"]);
+                let [path] = Executor::expect_args(call, args)?;
+                fs::rm(&path.to_string(), recursive).map_err(io_err)?;
+                VarValue::Bool(true)
+            }
+            "cp" => {
+                let [from, to] = Executor::expect_args(call, args)?;
+                let copied = fs::cp(&from.to_string(), &to.to_string()).map_err(io_err)?;
+                byte_count(copied)
+            }
+            "mv" => {
+                let [from, to] = Executor::expect_args(call, args)?;
+                fs::mv(&from.to_string(), &to.to_string()).map_err(io_err)?;
+                VarValue::Bool(true)
+            }
+            "basename" => {
+                let [path] = Executor::expect_args(call, args)?;
+                VarValue::Str(fs::basename(&path.to_string()))
+            }
+            "dirname" => {
+                let [path] = Executor::expect_args(call, args)?;
+                VarValue::Str(fs::dirname(&path.to_string()))
+            }
+            "wait" => {
+                let pid = match args.as_slice() {
+                    [] => None,
+                    [pid] => Some(Executor::pid_arg(call, pid)?),
+                    _ => return Err(Executor::arity_err(call, "0 or 1", args.len())),
+                };
+                let status = self.jobs.wait(pid).map_err(|e| self.job_err(e))?;
+                self.forward_captured()?;
+                VarValue::Int(status)
+            }
+            "jobs" => {
+                Executor::expect_args::<0>(call, args)?;
+                let mut running = Vec::new();
+                let mut table = String::new();
+                for job in self.jobs.jobs()? {
+                    table.push_str(&format!("{}\n", job));
+                    if job.is_running() {
+                        running.push(VarValue::Int(job.pid as i32));
+                    }
+                }
+                self.write_stdout(table.as_bytes())?;
+                self.forward_captured()?;
+                VarValue::List(running)
+            }
+            "kill" => {
+                let (signal, pid) = match args.as_slice() {
+                    [pid] => ("TERM".to_string(), pid),
+                    [signal, pid] => (signal.to_string(), pid),
+                    _ => return Err(Executor::arity_err(call, "1 or 2", args.len())),
+                };
+                let pid = Executor::pid_arg(call, pid)?;
+                self.jobs.kill(pid, &signal)?;
+                VarValue::Bool(true)
+            }
+            "json_parse" => {
+                let [s] = Executor::expect_args(call, args)?;
+                json::parse(&s.to_string())?
+            }
+            "json_stringify" => {
+                let (value, pretty) = match args.as_slice() {
+                    [value] => (value, false),
+                    [value, pretty] => (
+                        value,
+                        pretty.clone().coerce(&DataType::Bool)? == VarValue::Bool(true),
+                    ),
+                    _ => return Err(Executor::arity_err(call, "1 or 2", args.len())),
+                };
+                VarValue::Str(json::stringify(value, pretty))
+            }
+            "assert" | "assert_eq" | "assert_contains" => {
+                self.eval_assert(call, args)?;
+                VarValue::Bool(true)
+            }
+            name if self.functions.contains_key(name) => {
+                Executor::expect_args::<0>(call, args)?;
+                let body = self.functions[name].clone();
+                self.eval_block(&body, FrameKind::Function, name)?;
+                VarValue::Bool(true)
+            }
+            name => match self.host_fns.get(name) {
+                Some(host_fn) => host_fn(name, args)?,
+                None => return Err(ExeError::UnknownFunction(call.name.clone())),
+            },
+        };
+
+        Ok(value)
     }
 
-    fn read_input(&self, value: &VarValue) -> Result<String, ExeError> {
-        let re = Regex::new(RE_INPUT_FUNC).unwrap();
-        if let Some(caps) = re.captures(&value.to_string()) {
-            let prefix = &caps["text"];
-            print!("{}", prefix);
-            stdout().flush().unwrap();
-            let mut buf = String::new();
-            stdin().read_line(&mut buf).unwrap();
-            Ok(buf.trim().to_string())
+    /// `assert(condition)`, `assert_eq(left, right)` and `assert_contains(haystack, needle)`,
+    /// each with an optional message
+    fn eval_assert(&self, call: &Call, mut args: Vec<VarValue>) -> Result<(), ExeError> {
+        let arity = if call.name == "assert" { 1 } else { 2 };
+        if args.len() != arity && args.len() != arity + 1 {
+            let expected = format!("{} or {}", arity, arity + 1);
+            return Err(Executor::arity_err(call, &expected, args.len()));
+        }
+        let message = if args.len() > arity {
+            args.pop().map(|message| message.to_string())
         } else {
-            Err(ExeError::InvalidInputFunc(value.to_string()))
+            None
+        };
+        let raw = |idx: usize| call.args[idx].source().trim();
+
+        let failure = match (call.name.as_str(), args.as_slice()) {
+            ("assert", [condition]) => match condition.clone().coerce(&DataType::Bool)? {
+                VarValue::Bool(true) => None,
+                _ => Some(format!("`{}` is false", raw(0))),
+            },
+            ("assert_eq", [left, right]) if left == right => None,
+            ("assert_eq", [left, right]) => Some(format!(
+                "`{}` != `{}`\n{}",
+                raw(0),
+                raw(1),
+                diff_values(left, right)
+            )),
+            ("assert_contains", [haystack, needle]) => {
+                let found = match haystack {
+                    VarValue::List(items) => items
+                        .iter()
+                        .any(|item| item == needle || item.to_string() == needle.to_string()),
+                    VarValue::Map(fields) => fields.contains_key(&needle.to_string()),
+                    other => other.to_string().contains(&needle.to_string()),
+                };
+                (!found).then(|| {
+                    format!(
+                        "`{}` does not contain {}\n{}",
+                        raw(0),
+                        traced(needle),
+                        traced(haystack)
+                    )
+                })
+            }
+            _ => None,
+        };
+
+        match (failure, message) {
+            (None, _) => Ok(()),
+            (Some(failure), None) => Err(ExeError::AssertionFailed(failure)),
+            (Some(failure), Some(message)) => Err(ExeError::AssertionFailed(format!(
+                "{}\n{}",
+                message, failure
+            ))),
         }
     }
 
-    fn eval_var_expansion(&self, s: &str) -> VarValue {
-        let re = Regex::new(RE_VAR_EXPANSION).unwrap();
-        let mut replaced_str = s.to_string();
-        re.captures_iter(s).for_each(|caps| {
-            let var = &caps["var"];
-            let var_value = self
-                .get_var_value(var, true)
-                .unwrap_or(VarValue::Str("".into()));
+    /// Evaluate a call argument. Quoted arguments are strings (with variable expansion),
+    /// unquoted ones are nested calls, variables or literals
+    fn eval_arg(&mut self, arg: &Arg) -> Result<VarValue, ExeError> {
+        match arg.kind() {
+            ArgKind::Quoted(template) => Ok(VarValue::Str(self.expand_vars(template)?)),
+            ArgKind::Call(call) => self.eval_call(call),
+            ArgKind::Compare(_, operand) | ArgKind::Operand(operand) => {
+                self.found_var_or_create(operand)
+            }
+        }
+    }
+
+    /// Evaluate a condition `ex: count > 2`, an argument that isn't a comparison is evaluated
+    /// as a value
+    fn eval_test(&mut self, arg: &Arg) -> Result<VarValue, ExeError> {
+        match arg.kind() {
+            ArgKind::Compare(cmp, _) => Ok(VarValue::Bool(self.eval_compare(cmp)?.2)),
+            _ => self.eval_arg(arg),
+        }
+    }
+
+    /// The operands of a comparison and its result
+    fn eval_compare(&self, cmp: &CompareExpr) -> Result<(VarValue, VarValue, bool), ExeError> {
+        let left_val = self.found_var_or_create(&cmp.left)?;
+        let right_val = self.found_var_or_create(&cmp.right)?;
+        let is_true = CompareExpr::cmp(&left_val, &right_val, &cmp.operator)?;
+
+        Ok((left_val, right_val, is_true))
+    }
+
+    fn expect_args<const N: usize>(
+        call: &Call,
+        args: Vec<VarValue>,
+    ) -> Result<[VarValue; N], ExeError> {
+        let len = args.len();
+        args.try_into()
+            .map_err(|_| Executor::arity_err(call, &N.to_string(), len))
+    }
+
+    fn pid_arg(call: &Call, pid: &VarValue) -> Result<u32, ExeError> {
+        match pid {
+            VarValue::Int(pid) if *pid > 0 => Ok(*pid as u32),
+            other => Err(ExeError::InvalidCall(format!(
+                "`{}` expects a pid but got `{}`",
+                call.name, other
+            ))),
+        }
+    }
+
+    fn arity_err(call: &Call, expected: &str, found: usize) -> ExeError {
+        ExeError::InvalidCall(format!(
+            "`{}` expects {} argument(s) but got {}",
+            call.name, expected, found
+        ))
+    }
 
-            replaced_str = replaced_str.replace(&caps[0], &var_value.to_string());
-        });
+    /// Remove a leading flag `ex: -p` from the arguments, returns `true` if it was there
+    fn take_flag(args: &mut Vec<VarValue>, flags: &[&str]) -> bool {
+        match args.first() {
+            Some(VarValue::Str(arg)) if flags.contains(&arg.as_str()) => {
+                args.remove(0);
+                true
+            }
+            _ => false,
+        }
+    }
 
-        let default_value = replaced_str.parse::<VarValue>().unwrap();
-        self.get_var_value(&replaced_str, false)
-            .unwrap_or(default_value)
+    fn read_input(&mut self, prompt: &str) -> Result<String, ExeError> {
+        let io_err = |e: io::Error| ExeError::IoErr(format!("input: {}", e));
+        self.write_stdout(prompt.as_bytes())?;
+        let mut buf = String::new();
+        self.streams.stdin.read_line(&mut buf).map_err(io_err)?;
+        Ok(buf.trim().to_string())
+    }
+
+    /// Replace every `$var` and `${var}` in `s` with its value
+    fn expand_vars(&self, template: &Template) -> Result<String, ExeError> {
+        let mut replaced_str = String::new();
+        for segment in template.segments() {
+            match segment {
+                Segment::Lit(lit) => replaced_str.push_str(lit),
+                Segment::Var(var) => {
+                    self.check_declared(var)?;
+                    let var_value = self
+                        .get_var_value(var, true)?
+                        .unwrap_or(VarValue::Str("".into()));
+                    replaced_str.push_str(&var_value.to_string());
+                }
+            }
+        }
+
+        Ok(replaced_str)
+    }
+
+    fn eval_var_expansion(&self, template: &Template) -> Result<VarValue, ExeError> {
+        if !template.has_vars() {
+            self.check_declared(template.source().trim())?;
+        }
+        let replaced_str = self.expand_vars(template)?;
+        let default_value = VarValue::from_literal(&replaced_str);
+        Ok(self
+            .get_var_value(&replaced_str, false)?
+            .unwrap_or(default_value))
     }
 
-    fn eval_condition(&mut self, con: &'a Condition) -> Result<(), ExeError> {
+    fn eval_condition(&mut self, con: &Condition) -> Result<(), ExeError> {
         let Condition {
             if_expr,
             else_expr,
             condition,
         } = con;
-        let CompareExpr {
-            left,
-            right,
-            operator,
-        } = condition;
-
-        let left_val = self.found_var_or_create(left);
-        let right_val = self.found_var_or_create(right);
-        let is_true = CompareExpr::cmp(&left_val, &right_val, operator)?;
+        let (left_val, right_val, is_true) = self.eval_compare(condition)?;
         let expr = match (is_true, else_expr.is_some()) {
             (false, false) => None,
             (false, true) => match else_expr {
@@ -128,77 +1157,251 @@ impl<'a> Executor<'a> {
             (true, _) => Some(if_expr),
         };
 
+        if self.trace {
+            let left = if condition.left.is_empty() {
+                String::new()
+            } else {
+                format!("{} ", traced(&left_val))
+            };
+            let branch = match (is_true, expr.is_some()) {
+                (true, _) => "do",
+                (false, true) => "else",
+                (false, false) => "skip",
+            };
+            let statement = format!(
+                "if {}{} {} ({}) -> {}",
+                left,
+                condition.operator,
+                traced(&right_val),
+                is_true,
+                branch
+            );
+            self.write_trace(&statement)?;
+        }
+
+        // A branch is a block of its own for `let`
         if let Some(expr) = expr {
-            match expr {
-                Expression::Var(var) => {
-                    self.vars.insert(&var.name, var.value.clone());
+            self.push_frame(FrameKind::Block, "if");
+            let result = self.eval_expr(expr);
+            self.pop_frame();
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Run the body of the first case matching the value, a case is skipped if its guard is
+    /// false. Each case is a block of its own, its bindings are assigned before the guard is
+    /// evaluated and are gone once the case is skipped or its body ends
+    fn eval_match(&mut self, block: &Match) -> Result<(), ExeError> {
+        let value = self.eval_arg(&block.value)?;
+        for case in &block.cases {
+            self.enter()?;
+            self.push_frame(FrameKind::Block, "match");
+            let result = self.eval_case(case, &value);
+            self.pop_frame();
+            self.leave();
+            if result? {
+                return Ok(());
+            }
+        }
+
+        if self.trace {
+            self.write_trace(&format!("match {} -> none", traced(&value)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Run the body of `case` in the innermost frame if it matches the value
+    fn eval_case(&mut self, case: &Case, value: &VarValue) -> Result<bool, ExeError> {
+        if !self.case_matches(case, value)? {
+            return Ok(false);
+        }
+        if self.trace {
+            self.write_trace(&format!("match {} -> {}", traced(value), case))?;
+        }
+        self.eval_statements(&case.body)?;
+
+        Ok(true)
+    }
+
+    fn case_matches(&mut self, case: &Case, value: &VarValue) -> Result<bool, ExeError> {
+        for pattern in &case.patterns {
+            let is_match = match pattern {
+                Pattern::Literal(template) => {
+                    let pattern = VarValue::Str(self.expand_vars(template)?);
+                    CompareExpr::cmp(value, &pattern, &Operator::Eq)?
+                }
+                Pattern::Regex(re) => re.is_match(&value.to_string()),
+                Pattern::Range(start, end) => {
+                    let num = match value {
+                        VarValue::Int(num) => Some(*num),
+                        VarValue::Str(s) => s.trim().parse::<i32>().ok(),
+                        _ => None,
+                    };
+                    num.is_some_and(|num| (*start..=*end).contains(&num))
                 }
-                Expression::Echo(Echo(s)) => {
-                    self.eval_echo(s);
+                Pattern::Bind(name) => {
+                    self.declare_local(name.clone(), value.clone())?;
+                    true
                 }
-                Expression::Condition(_) => todo!(),
+                Pattern::Wildcard => true,
+            };
+            if !is_match {
+                continue;
+            }
+
+            let guard = match &case.guard {
+                Some(guard) => self.eval_test(guard)?.coerce(&DataType::Bool)?,
+                None => VarValue::Bool(true),
             };
+            if guard == VarValue::Bool(true) {
+                return Ok(true);
+            }
         }
 
-        Ok(())
+        Ok(false)
+    }
+
+    /// Evaluate a comparison `ex: age > 20` or a value `ex: $name`, `err.message`, `ls(".")`
+    /// the way the current statement would
+    pub(crate) fn eval_watch(&mut self, expr: &str) -> Result<VarValue, ExeError> {
+        self.eval_test(&Arg::parse(expr))
     }
 
-    fn found_var_or_create(&self, s: &str) -> VarValue {
-        if let Some(v) = self.get_var_value(s.trim(), false) {
-            return v;
+    /// Evaluate a comparison operand: a variable, a path into one, an expanded template or a
+    /// literal
+    fn found_var_or_create(&self, operand: &Operand) -> Result<VarValue, ExeError> {
+        let name = operand.source().trim();
+        if !operand.is_template {
+            self.check_declared(name)?;
+            if let Some(v) = self.get_var_value(name, false)? {
+                return Ok(v);
+            }
         }
 
-        let var = match s.trim().parse::<i32>() {
-            Ok(num) => VarValue::Int(num),
-            Err(_) => match s.parse::<bool>() {
-                Ok(b) => VarValue::Bool(b),
-                Err(_) => VarValue::Str(s.to_string()),
-            },
-        };
-        var
+        Ok(parse_operand(self.expand_vars(&operand.template)?))
+    }
+
+    /// Look up a map field or a list item `ex: err.message`, `cfg["servers"][0]`
+    fn get_field(&self, s: &str) -> Option<VarValue> {
+        let (base, path) = split_path(s).filter(|(_, path)| !path.is_empty())?;
+        self.var(base)?.get_path(&path).cloned()
+    }
+
+    /// Fail with a permission error if the sandbox does not grant `capability`
+    fn require(&self, capability: Capability, action: &str) -> Result<(), ExeError> {
+        if self.sandbox.allows(capability) {
+            Ok(())
+        } else {
+            Err(ExeError::PermissionDenied(capability, action.to_string()))
+        }
     }
 
-    fn get_var_value(&self, s: &'a str, is_expansion: bool) -> Option<VarValue> {
-        match self.vars.get(s) {
-            Some(var) => Some((*var).clone()),
+    /// Fail if `name`, or the variable of a path `ex: err.kind`, is declared in the script but
+    /// its declaration didn't run yet
+    fn check_declared(&self, name: &str) -> Result<(), ExeError> {
+        let name = split_path(name).map_or(name, |(base, _)| base);
+        if self.declared.contains(name) && self.var(name).is_none() {
+            return Err(ExeError::Undeclared(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn get_var_value(&self, s: &str, is_expansion: bool) -> Result<Option<VarValue>, ExeError> {
+        match self.var(s) {
+            Some(var) => Ok(Some((*var).clone())),
+            None if self.get_field(s).is_some() => Ok(self.get_field(s)),
             None => {
-                if !s.starts_with("$") && is_expansion == false {
-                    return None;
+                if !s.starts_with('$') && !is_expansion {
+                    return Ok(None);
                 }
                 let mut chars = s.chars();
                 chars.next();
                 let mut var = chars.as_str();
 
-                if is_expansion == true {
+                if is_expansion {
                     var = s;
                 }
 
-                let value = match var.parse::<usize>() {
-                    Ok(idx) => {
-                        VarValue::Str(self.args.get(idx + 1).unwrap_or(&"".to_string()).clone())
-                    }
-                    Err(_) => VarValue::Str(env::var(var).unwrap_or_default()),
-                };
-
-                Some(value)
+                self.special_var(var).map(Some)
             }
         }
     }
+
+    /// Value of `$var` that isn't a script variable: `$?`, `$!`, positional arguments and
+    /// environment variables
+    pub(crate) fn special_var(&self, var: &str) -> Result<VarValue, ExeError> {
+        let value = match var.parse::<usize>() {
+            Err(_) if var == "?" => VarValue::Int(self.jobs.last_status),
+            Err(_) if var == "!" => self
+                .jobs
+                .last_pid
+                .map_or(VarValue::Str("".into()), |pid| VarValue::Int(pid as i32)),
+            Ok(0) => VarValue::Str(self.script_name.clone()),
+            Ok(idx) => VarValue::Str(self.args.get(idx - 1).cloned().unwrap_or_default()),
+            Err(_) => {
+                self.require(Capability::Env, &format!("reading `${}`", var))?;
+                VarValue::Str(env::var(var).unwrap_or_default())
+            }
+        };
+
+        Ok(value)
+    }
+}
+
+/// A number of bytes as an `int`, counts past `i32::MAX` `ex: a 3 GiB copy` are capped
+fn byte_count<T: TryInto<i32>>(n: T) -> VarValue {
+    VarValue::Int(n.try_into().unwrap_or(i32::MAX))
+}
+
+/// A comparison operand that isn't a variable, ints and booleans are parsed
+pub(crate) fn parse_operand(s: String) -> VarValue {
+    match s.trim().parse::<i32>() {
+        Ok(num) => VarValue::Int(num),
+        Err(_) => match s.parse::<bool>() {
+            Ok(b) => VarValue::Bool(b),
+            Err(_) => VarValue::Str(s),
+        },
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{lang_parser::LangParser, variables::VarValue};
+    use std::collections::BTreeMap;
+
+    use crate::{
+        lang_parser::LangParser, readonly::ReadonlyKind, streams::OutputBuffer, trap::TrapEvent,
+        variables::VarValue,
+    };
 
-    use super::Executor;
+    use super::{byte_count, ErrorKind, ExeError, Executor};
+
+    /// Run `script` and return what it printed, or the error as a string
+    fn run(script: &str) -> Result<String, String> {
+        run_with(&mut Executor::new(), script)
+    }
+
+    /// Run `script` on `exe`, its stdout is captured
+    fn run_with(exe: &mut Executor, script: &str) -> Result<String, String> {
+        let parse_result = script.parse::<LangParser>().unwrap();
+        let stdout = OutputBuffer::default();
+        exe.streams.stdout = Box::new(stdout.clone());
+        exe.execute(&parse_result)
+            .map(|_| stdout.contents())
+            .map_err(|err| err.to_string())
+    }
 
     #[test]
     fn eval_var_expansion_with_curly_braces_syntax() {
         let expr = "name: str=Jone\necho \"Hello, ${name}\"";
         let parse_result = expr.parse::<LangParser>().unwrap();
-        let exe = Executor::new(&parse_result.experssions);
+        let mut exe = Executor::new();
+        exe.execute(&parse_result).unwrap();
 
-        let value = exe.eval_var_expansion("Hello, ${name}");
+        let value = exe.eval_var_expansion(&"Hello, ${name}".into()).unwrap();
 
         assert_eq!(value, VarValue::Str("Hello, Jone".into()));
     }
@@ -207,10 +1410,430 @@ mod test {
     fn eval_var_expansion_without_curly_braces() {
         let expr = "name: str=Jone\necho \"Hello, $name\"";
         let parse_result = expr.parse::<LangParser>().unwrap();
-        let exe = Executor::new(&parse_result.experssions);
+        let mut exe = Executor::new();
+        exe.execute(&parse_result).unwrap();
 
-        let value = exe.eval_var_expansion("Hello, $name");
+        let value = exe.eval_var_expansion(&"Hello, $name".into()).unwrap();
 
         assert_eq!(value, VarValue::Str("Hello, Jone".into()));
     }
+
+    #[test]
+    fn eval_filesystem_builtins() {
+        let dir = std::env::temp_dir().join(format!("mybash-exe-fs-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let expr = format!(
+            "dir: str = \"{dir}\"
+mkdir -p \"${{dir}}/logs\"
+written: int = write_file(\"${{dir}}/logs/app.log\", \"started\")
+append_file(\"${{dir}}/logs/app.log\", \" ok\")
+content: str = read_file(\"${{dir}}/logs/app.log\")
+files: list = ls(\"${{dir}}/logs\")
+name: str = basename(\"${{dir}}/logs/app.log\")
+if -s \"${{dir}}/logs/app.log\"
+do found: bool = true
+endif
+rm -r dir"
+        );
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.execute(&parse_result).unwrap();
+
+        assert_eq!(exe.vars["written"], VarValue::Int(7));
+        assert_eq!(exe.vars["content"], VarValue::Str("started ok".into()));
+        assert_eq!(
+            exe.vars["files"],
+            VarValue::List(vec![VarValue::Str("app.log".into())])
+        );
+        assert_eq!(exe.vars["name"], VarValue::Str("app.log".into()));
+        assert_eq!(exe.vars["found"], VarValue::Bool(true));
+        assert!(!std::path::Path::new(dir).exists());
+
+        // Sizes of 2 GiB and more don't fit an `int`
+        assert_eq!(byte_count(3u64 << 30), VarValue::Int(i32::MAX));
+        assert_eq!(byte_count(7usize), VarValue::Int(7));
+    }
+
+    #[test]
+    fn builtin_errors_are_not_panics() {
+        let expr = "content: str = read_file(\"/no/such/file.txt\")";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        assert!(matches!(
+            exe.execute(&parse_result),
+            Err(ExeError::IoErr(_))
+        ));
+
+        let expr = "content: str = read_file()";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        assert!(matches!(
+            exe.execute(&parse_result),
+            Err(ExeError::InvalidCall(_))
+        ));
+
+        let expr = "files: int = ls(\".\")";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        assert!(matches!(
+            exe.execute(&parse_result),
+            Err(ExeError::TypeErr(_))
+        ));
+    }
+
+    #[test]
+    fn catch_runtime_errors() {
+        let expr = "try\ncontent: str = read_file(\"/no/such/file\")\nreached: bool = true\ncatch err\nkind: str = \"$err.kind\"\nend";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.execute(&parse_result).unwrap();
+
+        assert!(!exe.vars.contains_key("reached"));
+        assert_eq!(exe.vars["kind"], VarValue::Str("io".into()));
+        let VarValue::Map(err) = &exe.vars["err"] else {
+            panic!("`err` should be a map")
+        };
+        assert_eq!(err["line"], VarValue::Int(2));
+    }
+
+    #[test]
+    fn catch_thrown_errors() {
+        let expr = "name: str = Jone\ntry\nthrow \"bad $name\"\ncatch e\necho e\nend";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.execute(&parse_result).unwrap();
+
+        assert_eq!(
+            exe.vars["e"],
+            VarValue::Map(BTreeMap::from([
+                ("kind".to_string(), VarValue::Str("throw".into())),
+                ("message".to_string(), VarValue::Str("bad Jone".into())),
+                ("line".to_string(), VarValue::Int(3)),
+            ]))
+        );
+    }
+
+    #[test]
+    fn rethrow_from_catch_block() {
+        let expr = "try\nthrow 'inner'\ncatch\nthrow \"outer: $err.message\"\nend";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        let err = exe.execute(&parse_result).err().unwrap();
+
+        assert_eq!(err.kind(), ErrorKind::Throw);
+        assert_eq!(err.to_string(), "outer: inner");
+        assert_eq!(exe.line(), 4);
+    }
+
+    #[test]
+    fn run_traps_in_reverse_order_on_exit() {
+        let expr = "order: str = start-\ntrap EXIT\norder: str = \"${order}a\"\nend\ntrap EXIT\norder: str = \"${order}b\"\nend\ntrap ERR\norder: str = 'error'\nend";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.execute(&parse_result).unwrap();
+
+        assert_eq!(exe.vars["order"], VarValue::Str("start-ba".into()));
+    }
+
+    #[test]
+    fn run_err_traps_on_uncaught_errors() {
+        let expr = "trap EXIT\ncleaned: bool = true\nend\ntrap ERR\nkind: str = \"$err.kind\"\nend\nthrow 'deploy failed'";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        let err = exe.execute(&parse_result).err().unwrap();
+
+        assert_eq!(err.to_string(), "deploy failed");
+        assert_eq!(exe.line(), 7);
+        assert_eq!(exe.vars["kind"], VarValue::Str("throw".into()));
+        assert_eq!(exe.vars["cleaned"], VarValue::Bool(true));
+    }
+
+    #[test]
+    fn run_defers_when_functions_return() {
+        let expr = "order: str = start-\nfn build\ndefer\norder: str = \"${order}a\"\nend\ndefer\norder: str = \"${order}b\"\nend\norder: str = \"${order}c\"\nend\nbuild()\nbuild()";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.execute(&parse_result).unwrap();
+        assert_eq!(exe.vars["order"], VarValue::Str("start-cbacba".into()));
+
+        // The function's error wins over the ones raised by its deferred blocks
+        let expr = "fn build\ndefer\ncleaned: bool = true\nend\ndefer\nthrow 'cleanup failed'\nend\nthrow 'build failed'\nend\nbuild()";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        let err = exe.execute(&parse_result).err().unwrap();
+        assert_eq!(err.to_string(), "build failed");
+        assert_eq!(exe.line(), 8);
+        assert_eq!(exe.vars["cleaned"], VarValue::Bool(true));
+    }
+
+    #[test]
+    fn run_signal_traps() {
+        let expr = "trap EXIT\ncleaned: bool = true\nend\ntrap INT\ninterrupted: bool = true\nend\necho 'not reached'";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.eval_expr(&parse_result.experssions[0]).unwrap();
+        exe.eval_expr(&parse_result.experssions[1]).unwrap();
+        exe.signals.as_ref().unwrap().send(TrapEvent::Int);
+
+        let err = exe.execute(&parse_result).err().unwrap();
+        assert!(matches!(err, ExeError::Signal(TrapEvent::Int)));
+        assert_eq!(exe.vars["interrupted"], VarValue::Bool(true));
+        assert_eq!(exe.vars["cleaned"], VarValue::Bool(true));
+        assert!(exe.signals.is_none());
+
+        // Handlers are only installed for `INT` and `TERM` traps and removed at the end
+        let parse_result = "trap EXIT\necho 'bye'\nend".parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.eval_expr(&parse_result.experssions[0]).unwrap();
+        assert!(exe.signals.is_none());
+        let parse_result = "trap TERM\necho 'bye'\nend".parse::<LangParser>().unwrap();
+        exe.execute(&parse_result).unwrap();
+        assert!(exe.signals.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_commands_and_background_jobs() {
+        let expr = "run true\nrun sh -c \"exit 4\" &\npid: str = \"$!\"\nstatus: int = wait($!)\ntry\nrun false\ncatch\nkind: str = \"$err.kind\"\nlast: str = \"$?\"\nend";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.execute(&parse_result).unwrap();
+
+        assert_eq!(exe.vars["status"], VarValue::Int(4));
+        assert_eq!(exe.vars["kind"], VarValue::Str("command".into()));
+        assert_eq!(exe.vars["last"], VarValue::Int(1));
+        assert_ne!(exe.vars["pid"], VarValue::Str("".into()));
+    }
+
+    #[test]
+    fn trace_statements_and_branches() {
+        let expr = "name: str = \"Jone \"\nset trace on\ncount: int = 2\nif name == \"Jone\"\ndo echo \"found $name\"\nelse\ndo echo \"missing $count\"\nendif\nset trace off\necho 'quiet'";
+        let parse_result = expr.parse::<LangParser>().unwrap();
+        let stderr = OutputBuffer::default();
+        let mut exe = Executor::new();
+        exe.script_name = "check.mb".into();
+        exe.streams.stderr = Box::new(stderr.clone());
+        exe.execute(&parse_result).unwrap();
+
+        assert_eq!(
+            stderr.contents(),
+            "+ check.mb:3: count = 2
++ check.mb:4: if \"Jone \" == \"Jone\" (false) -> else
++ check.mb:4: echo missing 2
++ check.mb:9: set trace off
+"
+        );
+    }
+
+    #[test]
+    fn match_values_against_patterns() {
+        let script = "match $1
+case \"start\"
+res: str = \"starting\"
+case 'stop' | \"ha*\"
+res: str = \"stopping\"
+case /^v[0-9]+$/
+res: str = \"version\"
+case 1..10
+res: str = \"small\"
+case n if n == \"42\"
+res: str = \"answer $n\"
+case _
+res: str = \"unknown\"
+end";
+        let parse_result = script.parse::<LangParser>().unwrap();
+        let cases = [
+            ("start", "starting"),
+            ("halt", "stopping"),
+            ("v12", "version"),
+            ("7", "small"),
+            ("42", "answer 42"),
+            ("11", "unknown"),
+        ];
+        for (arg, expected) in cases {
+            let mut exe = Executor::new();
+            exe.args = vec![arg.into()];
+            exe.execute(&parse_result).unwrap();
+            assert_eq!(exe.vars["res"], VarValue::Str(expected.into()), "{}", arg);
+        }
+
+        let script = "count: int = 3\nset trace on\nmatch count\ncase 5..9\necho 'many'\nend\nmatch count\ncase 1..2 | 3\necho 'few'\nend";
+        let parse_result = script.parse::<LangParser>().unwrap();
+        let stderr = OutputBuffer::default();
+        let mut exe = Executor::new();
+        exe.streams.stderr = Box::new(stderr.clone());
+        exe.streams.stdout = Box::new(OutputBuffer::default());
+        exe.execute(&parse_result).unwrap();
+
+        assert_eq!(
+            stderr.contents(),
+            "+ -:3: match 3 -> none
++ -:7: match 3 -> case 1..2 | 3
++ -:9: echo few
+"
+        );
+    }
+
+    #[test]
+    fn bind_case_patterns_in_their_case() {
+        // A binding is gone once its guard fails or its case ends
+        let script =
+            "match 5\ncase n if n > 9\nres: str = \"big\"\ncase m\nres: str = \"small\"\nend";
+        let parse_result = script.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.execute(&parse_result).unwrap();
+        assert_eq!(exe.vars["res"], VarValue::Str("small".into()));
+        assert!(!exe.vars.contains_key("n"));
+        assert!(!exe.vars.contains_key("m"));
+
+        // The guard reads the binding, not a `let` variable with the same name
+        let script = "fn check\nlet n: int = 0\nmatch 5\ncase n if n > 3\nres: str = \"${n}\"\ncase _\nres: str = \"${n}\"\nend\nlast: str = \"${n}\"\nend\ncheck()";
+        let parse_result = script.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.execute(&parse_result).unwrap();
+        assert_eq!(exe.vars["res"], VarValue::Int(5));
+        assert_eq!(exe.vars["last"], VarValue::Int(0));
+    }
+
+    #[test]
+    fn reject_readonly_reassignments() {
+        // A function declares its constants again on every call
+        let script = "fn setup\nconst K: int = 1\nreadonly started: str = \"${K}\"\nres: str = \"${res}${started}\"\nend\nres: str = start-\nsetup()\nsetup()\nconst MAX: int = 3\necho MAX";
+        let parse_result = script.parse::<LangParser>().unwrap();
+        let mut exe = Executor::new();
+        exe.streams.stdout = Box::new(OutputBuffer::default());
+        exe.execute(&parse_result).unwrap();
+        assert_eq!(exe.vars["res"], VarValue::Str("start-11".into()));
+        assert_eq!(exe.vars["MAX"], VarValue::Int(3));
+        assert!(!exe.vars.contains_key("K"));
+
+        let parse_result = "try\nMAX: int = 4\ncatch e\necho e.kind\nend"
+            .parse::<LangParser>()
+            .unwrap();
+        let stdout = OutputBuffer::default();
+        exe.streams.stdout = Box::new(stdout.clone());
+        exe.execute(&parse_result).unwrap();
+        assert_eq!(stdout.contents(), "type\n");
+        assert_eq!(exe.vars["MAX"], VarValue::Int(3));
+
+        let parse_result = "MAX: int = 5".parse::<LangParser>().unwrap();
+        assert!(matches!(
+            exe.execute(&parse_result),
+            Err(ExeError::Readonly(ref name, ReadonlyKind::Const)) if name == "MAX"
+        ));
+    }
+
+    #[test]
+    fn declarations_take_effect_when_they_run() {
+        let undeclared = |name: &str| Err(ExeError::Undeclared(name.into()).to_string());
+
+        // Top level
+        assert_eq!(run("echo name\nname: str = Jone"), undeclared("name"));
+        assert_eq!(
+            run("echo \"Hi $name\"\nname: str = Jone"),
+            undeclared("name")
+        );
+        assert_eq!(
+            run("name: str = Jone\necho name\nname: str = Jane\necho name"),
+            Ok("Jone\nJane\n".into())
+        );
+        assert_eq!(run("echo hello"), Ok("hello\n".into()));
+
+        // Branches
+        let script = "if $1 == 'yes'\ndo x: int = 1\nendif\necho x";
+        assert_eq!(run(script), undeclared("x"));
+        assert_eq!(
+            run("if 1 == 1\ndo x: int = 1\nendif\necho x"),
+            Ok("1\n".into())
+        );
+        assert_eq!(
+            run("if x == 1\ndo echo 'one'\nendif\nx: int = 1"),
+            undeclared("x")
+        );
+
+        // Functions read the variables when they are called
+        let script = "fn greet\necho \"Hello, $name\"\nend\nname: str = Jone\ngreet()";
+        assert_eq!(run(script), Ok("Hello, Jone\n".into()));
+        let script = "fn greet\necho \"Hello, $name\"\nend\ngreet()\nname: str = Jone";
+        assert_eq!(run(script), undeclared("name"));
+        let script = "fn setup\ncount: int = 1\nend\nsetup()\necho count";
+        assert_eq!(run(script), Ok("1\n".into()));
+
+        let script = "try\necho x\ncatch\necho err.kind\nend\nx: int = 1";
+        assert_eq!(run(script), Ok("type\n".into()));
+    }
+
+    #[test]
+    fn let_declarations_are_scoped_to_their_block() {
+        let script = "count: int = 1
+try
+let count: int = 2
+echo count
+count: int = 3
+echo count
+global count: int = 10
+echo count
+global total: str = \"$count\"
+catch
+end
+echo count
+echo total";
+        let mut exe = Executor::new();
+        assert_eq!(run_with(&mut exe, script), Ok("2\n3\n3\n10\n3\n".into()));
+        assert_eq!(exe.vars["count"], VarValue::Int(10));
+
+        // Functions don't see the `let` variables of their callers
+        let script =
+            "count: int = 1\nfn show\necho count\nend\ntry\nlet count: int = 5\nshow()\ncatch\nend";
+        assert_eq!(run(script), Ok("1\n".into()));
+
+        let script = "if 1 == 1\ndo let branch: int = 1\nendif\necho branch";
+        assert_eq!(
+            run(script),
+            Err(ExeError::Undeclared("branch".into()).to_string())
+        );
+        let mut exe = Executor::new();
+        run_with(&mut exe, "let top: int = 1").unwrap();
+        assert_eq!(exe.vars["top"], VarValue::Int(1));
+
+        let script = "set trace on\nfn setup\nlet ready: bool = true\nglobal started: str = \"$ready\"\nend\nsetup()";
+        let stderr = OutputBuffer::default();
+        let mut exe = Executor::new();
+        exe.streams.stderr = Box::new(stderr.clone());
+        run_with(&mut exe, script).unwrap();
+        assert_eq!(
+            stderr.contents(),
+            "+ -:2: fn setup
++ -:6: setup()
++ -:3: let ready = true (scope: script > setup)
++ -:4: global started = true (scope: script > setup)
+"
+        );
+    }
+
+    #[test]
+    fn parse_and_stringify_json() {
+        let script = r#"cfg: map = json_parse('{"servers": [{"host": "a.local", "port": 80}], "debug": true}')
+echo cfg["servers"][0]["host"]
+host: str = cfg["servers"][0]['host']
+echo "${host}:${cfg.servers[0].port}"
+if cfg["debug"] == true
+do echo "debug"
+endif
+servers: str = json_stringify(cfg["servers"])
+echo servers
+pretty: str = json_stringify(cfg["servers"][0], true)
+echo pretty"#;
+        assert_eq!(
+            run(script),
+            Ok("a.local\na.local:80\ndebug\n[{\"host\":\"a.local\",\"port\":80}]\n{\n  \"host\": \"a.local\",\n  \"port\": 80\n}\n".into())
+        );
+
+        let script = "try\nbad: map = json_parse('[1, }')\ncatch\necho err.message\nend";
+        assert_eq!(
+            run(script),
+            Ok("Invalid JSON at byte 4: expected value\n".into())
+        );
+        assert!(run("x: str = json_stringify()").is_err());
+    }
 }
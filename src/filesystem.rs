@@ -0,0 +1,239 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::cmp::Operator;
+
+/// Evaluate a file test operator. Unary operators (`-e`, `-f`, ...) only look at `right`,
+/// binary ones (`-nt`, `-ot`) compare the modification times of `left` and `right`
+pub fn file_test(left: &str, right: &str, op: &Operator) -> bool {
+    let path = Path::new(right);
+    match op {
+        Operator::Exists => path.exists(),
+        Operator::IsFile => path.is_file(),
+        Operator::IsDir => path.is_dir(),
+        Operator::IsReadable => is_readable(path),
+        Operator::IsWritable => fs::metadata(path)
+            .map(|meta| !meta.permissions().readonly())
+            .unwrap_or(false),
+        Operator::IsExecutable => is_executable(path),
+        Operator::IsNotEmpty => fs::metadata(path)
+            .map(|meta| meta.len() > 0)
+            .unwrap_or(false),
+        Operator::NewerThan => match (modified(Path::new(left)), modified(path)) {
+            (Some(left_time), Some(right_time)) => left_time > right_time,
+            (Some(_), None) => true,
+            _ => false,
+        },
+        Operator::OlderThan => match (modified(Path::new(left)), modified(path)) {
+            (Some(left_time), Some(right_time)) => left_time < right_time,
+            (None, Some(_)) => true,
+            _ => false,
+        },
+        _ => unreachable!("`{:?}` is not a file test operator", op),
+    }
+}
+
+fn modified(path: &Path) -> Option<std::time::SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn is_readable(path: &Path) -> bool {
+    if path.is_dir() {
+        fs::read_dir(path).is_ok()
+    } else {
+        File::open(path).is_ok()
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .map(|meta| meta.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.exists()
+}
+
+pub fn read_file(path: &str) -> io::Result<String> {
+    fs::read_to_string(path)
+}
+
+/// Write `content` to `path` (truncating it) and return the number of bytes written
+pub fn write_file(path: &str, content: &str) -> io::Result<usize> {
+    fs::write(path, content)?;
+    Ok(content.len())
+}
+
+/// Append `content` to `path` (creating it if needed) and return the number of bytes written
+pub fn append_file(path: &str, content: &str) -> io::Result<usize> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(content.as_bytes())?;
+    Ok(content.len())
+}
+
+/// List the entries of `dir` sorted by name
+pub fn ls(dir: &str) -> io::Result<Vec<String>> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.file_name().to_string_lossy().to_string()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+pub fn mkdir(path: &str, parents: bool) -> io::Result<()> {
+    if parents {
+        fs::create_dir_all(path)
+    } else {
+        fs::create_dir(path)
+    }
+}
+
+pub fn rm(path: &str, recursive: bool) -> io::Result<()> {
+    if Path::new(path).is_dir() {
+        if recursive {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_dir(path)
+        }
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Copy `from` to `to`. If `to` is a directory the file is copied into it
+pub fn cp(from: &str, to: &str) -> io::Result<u64> {
+    fs::copy(from, into_dir(from, to))
+}
+
+/// Move `from` to `to`. If `to` is a directory the file is moved into it
+pub fn mv(from: &str, to: &str) -> io::Result<()> {
+    fs::rename(from, into_dir(from, to))
+}
+
+fn into_dir(from: &str, to: &str) -> std::path::PathBuf {
+    let to = Path::new(to);
+    match Path::new(from).file_name() {
+        Some(name) if to.is_dir() => to.join(name),
+        _ => to.to_path_buf(),
+    }
+}
+
+pub fn basename(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+pub fn dirname(path: &str) -> String {
+    match Path::new(path).parent() {
+        Some(parent) if parent.as_os_str().is_empty() => ".".into(),
+        Some(parent) => parent.to_string_lossy().to_string(),
+        None => path.into(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mybash-fs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn should_evaluate_unary_file_tests() {
+        let dir = temp_dir("unary");
+        let file = dir.join("a.txt");
+        let empty = dir.join("empty.txt");
+        fs::write(&file, "hello").unwrap();
+        fs::write(&empty, "").unwrap();
+        let (dir, file, empty) = (
+            dir.to_str().unwrap(),
+            file.to_str().unwrap(),
+            empty.to_str().unwrap(),
+        );
+
+        assert!(file_test("", file, &Operator::Exists));
+        assert!(file_test("", file, &Operator::IsFile));
+        assert!(!file_test("", file, &Operator::IsDir));
+        assert!(file_test("", dir, &Operator::IsDir));
+        assert!(file_test("", file, &Operator::IsReadable));
+        assert!(file_test("", file, &Operator::IsWritable));
+        assert!(file_test("", file, &Operator::IsNotEmpty));
+        assert!(!file_test("", empty, &Operator::IsNotEmpty));
+        assert!(!file_test("", "/no/such/file", &Operator::Exists));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_compare_modification_times() {
+        let dir = temp_dir("mtime");
+        let old = dir.join("old.txt");
+        let new = dir.join("new.txt");
+        fs::write(&old, "old").unwrap();
+        File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap();
+        fs::write(&new, "new").unwrap();
+        let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+        assert!(file_test(new, old, &Operator::NewerThan));
+        assert!(file_test(old, new, &Operator::OlderThan));
+        assert!(!file_test(old, new, &Operator::NewerThan));
+        assert!(file_test(new, "/no/such/file", &Operator::NewerThan));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_manage_files_and_dirs() {
+        let dir = temp_dir("manage");
+        let root = dir.to_str().unwrap();
+        let nested = format!("{}/a/b", root);
+        let file = format!("{}/a/b/notes.txt", root);
+
+        assert!(mkdir(&nested, false).is_err());
+        mkdir(&nested, true).unwrap();
+        assert_eq!(write_file(&file, "one\n").unwrap(), 4);
+        assert_eq!(append_file(&file, "two\n").unwrap(), 4);
+        assert_eq!(read_file(&file).unwrap(), "one\ntwo\n");
+
+        cp(&file, root).unwrap();
+        mv(
+            &format!("{}/notes.txt", root),
+            &format!("{}/moved.txt", root),
+        )
+        .unwrap();
+        assert_eq!(ls(root).unwrap(), vec!["a".to_string(), "moved.txt".into()]);
+
+        assert!(rm(&format!("{}/a", root), false).is_err());
+        rm(&format!("{}/a", root), true).unwrap();
+        assert_eq!(ls(root).unwrap(), vec!["moved.txt".to_string()]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn should_split_paths() {
+        assert_eq!(basename("/var/log/app.log"), "app.log");
+        assert_eq!(dirname("/var/log/app.log"), "/var/log");
+        assert_eq!(dirname("app.log"), ".");
+        assert_eq!(basename("/"), "");
+    }
+}
@@ -0,0 +1,671 @@
+use crate::{
+    arg::Arg,
+    call::Call,
+    conditions::Condition,
+    echo::Echo,
+    jobs::Run,
+    lang_parser::{Expression, LangParser, ParseErr},
+    match_case::Case,
+    readonly::Readonly,
+    regex::{cached_regex, RE_CALL, RE_CMP, RE_ECHO, RE_FILE_TEST, RE_THROW, RE_VAR},
+    scope::Scoped,
+    trace::SetTrace,
+    trap::Trap,
+    try_catch::{Throw, TryCatch},
+    utils::{comment, remove_comments},
+    variables::{CallVariable, VarValue, Variable},
+};
+
+const INDENT: &str = "    ";
+
+/// Format a script into its canonical form
+///
+/// Example
+/// ```text
+/// name: str=Jone               name: str = "Jone"
+/// echo 'Hello, $name'     =>   echo "Hello, $name"
+/// try                          try
+/// throw 'oops' # why               throw "oops" # why
+/// catch                        catch
+/// end                          end
+/// ```
+/// Blocks are indented with 4 spaces, runs of blank lines are collapsed and comments are
+/// kept. A statement is only rewritten if the result parses to the same expression,
+/// otherwise it's kept as written
+pub fn format(source: &str) -> Result<String, ParseErr> {
+    let ast = source.parse::<LangParser>()?;
+    let mut formatter = Formatter {
+        lines: source.lines().collect(),
+        out: String::new(),
+        next: 1,
+        pending_blank: false,
+        block_start: true,
+    };
+    formatter.block(&ast, 0);
+    formatter.flush(formatter.lines.len() + 1, 0);
+
+    Ok(formatter.out)
+}
+
+struct Formatter<'a> {
+    lines: Vec<&'a str>,
+    out: String,
+    /// Next source line (1-based) that wasn't written yet
+    next: usize,
+    pending_blank: bool,
+    /// Nothing was written since the start of the current block
+    block_start: bool,
+}
+
+impl Formatter<'_> {
+    fn code(&self, line: usize) -> String {
+        remove_comments(self.lines[line - 1])
+    }
+
+    /// Write the comments and blank lines before `line`
+    fn flush(&mut self, line: usize, depth: usize) {
+        while self.next < line {
+            let raw = self.lines[self.next - 1];
+            if raw.trim().is_empty() {
+                self.pending_blank = true;
+            } else if let Some(text) = comment(raw).filter(|_| self.code(self.next).is_empty()) {
+                self.push(depth, &format!("#{}", text));
+            }
+            self.next += 1;
+        }
+    }
+
+    fn push(&mut self, depth: usize, text: &str) {
+        if self.pending_blank && !self.block_start {
+            self.out.push('\n');
+        }
+        self.pending_blank = false;
+        self.block_start = false;
+        self.out.push_str(&INDENT.repeat(depth));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// Write `text` for the source `line`, keeping the line's inline comment
+    fn line(&mut self, line: usize, depth: usize, text: &str) {
+        self.flush(line, depth);
+        match comment(self.lines[line - 1]) {
+            Some(comment) => self.push(depth, &format!("{} #{}", text, comment)),
+            None => self.push(depth, text),
+        }
+        self.next = line + 1;
+    }
+
+    /// Write a line closing the block at `depth + 1`, the block's trailing comments stay inside
+    fn closer(&mut self, depth: usize, is_closer: fn(&str) -> bool) {
+        let line = (self.next..=self.lines.len())
+            .find(|line| is_closer(&self.code(*line)))
+            .expect("the parser checked the block is closed");
+        self.flush(line, depth + 1);
+        self.pending_blank = false;
+        let text = self
+            .code(line)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.line(line, depth, &escape_comments(&text));
+    }
+
+    fn block(&mut self, block: &LangParser, depth: usize) {
+        self.block_start = true;
+        for (expr, line) in block.experssions.iter().zip(&block.lines) {
+            self.expr(expr, *line, depth);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expression, line: usize, depth: usize) {
+        match expr {
+            Expression::TryCatch(try_catch) => {
+                let TryCatch {
+                    body, catch_body, ..
+                } = try_catch.as_ref();
+                self.line(line, depth, "try");
+                self.block(body, depth + 1);
+                self.closer(depth, TryCatch::is_catch);
+                self.block(catch_body, depth + 1);
+                self.closer(depth, |s| s == "end");
+            }
+            Expression::Trap(trap) => {
+                let Trap { events, body } = trap.as_ref();
+                let events = events
+                    .iter()
+                    .map(|event| event.to_string())
+                    .collect::<Vec<_>>();
+                self.line(line, depth, &format!("trap {}", events.join(" ")));
+                self.block(body, depth + 1);
+                self.closer(depth, |s| s == "end");
+            }
+            Expression::Defer(defer) => {
+                self.line(line, depth, "defer");
+                self.block(&defer.body, depth + 1);
+                self.closer(depth, |s| s == "end");
+            }
+            Expression::Function(function) => {
+                self.line(line, depth, &format!("fn {}", function.name));
+                self.block(&function.body, depth + 1);
+                self.closer(depth, |s| s == "end");
+            }
+            Expression::Match(block) => {
+                self.line(
+                    line,
+                    depth,
+                    &escape_comments(&format!("match {}", block.value)),
+                );
+                for case in &block.cases {
+                    let canonical = case.to_string();
+                    let text = if Case::parse_header(&canonical)
+                        .is_ok_and(|header| header == (case.patterns.clone(), case.guard.clone()))
+                    {
+                        canonical
+                    } else {
+                        self.code(case.line)
+                    };
+                    self.flush(case.line, depth + 1);
+                    self.line(case.line, depth, &escape_comments(text.trim()));
+                    self.block(&case.body, depth + 1);
+                }
+                self.closer(depth, |s| s == "end");
+            }
+            Expression::Condition(con) => self.condition(con, line, depth),
+            _ => {
+                let (text, _) = statement(expr, &self.code(line));
+                self.line(line, depth, &text);
+            }
+        }
+    }
+
+    fn condition(&mut self, con: &Condition, line: usize, depth: usize) {
+        // The `if`, `do`, `else`, `do` and `endif` lines, comments may sit between them
+        let mut lines = Vec::new();
+        for line in line..=self.lines.len() {
+            let code = self.code(line);
+            if !code.is_empty() {
+                lines.push(line);
+            }
+            if Condition::is_endif(&code) {
+                break;
+            }
+        }
+        let raw = lines
+            .iter()
+            .map(|line| self.code(*line))
+            .collect::<Vec<_>>();
+
+        let (if_text, if_expr) = statement(&con.if_expr, after_do(&raw[1]));
+        let mut texts = vec![
+            format!("if {}", compare(&raw[0])),
+            format!("do {}", if_text),
+        ];
+        let mut expected = Condition {
+            condition: con.condition.clone(),
+            if_expr,
+            else_expr: None,
+        };
+        if let (Some(else_expr), 5) = (&con.else_expr, raw.len()) {
+            let (else_text, else_expr) = statement(else_expr, after_do(&raw[3]));
+            texts.push("else".into());
+            texts.push(format!("do {}", else_text));
+            expected.else_expr = Some(else_expr);
+        }
+        texts.push("endif".into());
+
+        let expected = Expression::Condition(Box::new(expected));
+        let texts = if texts.len() == raw.len() && parses_to(&texts.join("\n"), &expected) {
+            texts
+        } else {
+            raw.iter().map(|raw| escape_comments(raw)).collect()
+        };
+        for (line, text) in lines.iter().zip(texts) {
+            self.line(*line, depth, &text);
+        }
+    }
+}
+
+/// The canonical form of a single line statement and the expression it parses to, or `raw`
+/// if the canonical form would change its meaning
+fn statement(expr: &Expression, raw: &str) -> (String, Expression) {
+    let keep = || (escape_comments(raw), expr.clone());
+    let canonical = match expr {
+        Expression::Readonly(Readonly { kind, decl }) => {
+            return keyword_decl(&kind.to_string(), decl, raw, |decl| {
+                Expression::Readonly(Readonly { kind: *kind, decl })
+            })
+        }
+        Expression::Scoped(Scoped { kind, decl }) => {
+            return keyword_decl(&kind.to_string(), decl, raw, |decl| {
+                Expression::Scoped(Scoped { kind: *kind, decl })
+            })
+        }
+        Expression::Var(Variable { name, value, .. }) => {
+            let caps = cached_regex!(RE_VAR).captures(raw);
+            let raw_value = caps
+                .as_ref()
+                .map_or(raw, |caps| caps.name("value").unwrap().as_str());
+            let (datatype, value) = match value {
+                VarValue::Int(_) => ("int", format_math(raw_value)),
+                VarValue::Bool(value) => ("bool", value.to_string()),
+                VarValue::Str(value) => ("str", quote(value)),
+                _ => return keep(),
+            };
+            format!("{}: {} = {}", name, datatype, value)
+        }
+        Expression::CallVar(CallVariable {
+            name,
+            datatype,
+            call,
+        }) => {
+            let raw_call = raw.split_once('=').map_or(raw, |(_, call)| call.trim());
+            format!("{}: {} = {}", name, datatype, format_call(call, raw_call))
+        }
+        Expression::Call(call) => format_call(call, raw),
+        Expression::Echo(Echo(template)) => {
+            let is_quoted = cached_regex!(RE_ECHO)
+                .captures(raw)
+                .is_some_and(|caps| caps.get(1).is_some());
+            format!("echo {}", quote_if(template.source(), is_quoted))
+        }
+        Expression::Throw(Throw(template)) => {
+            let is_quoted = cached_regex!(RE_THROW)
+                .captures(raw)
+                .is_some_and(|caps| caps.get(1).is_some());
+            format!("throw {}", quote_if(template.source(), is_quoted))
+        }
+        Expression::Run(Run {
+            program,
+            args,
+            background,
+        }) => {
+            let mut words = vec![program.to_string()];
+            words.extend(args.iter().map(|arg| format_arg(arg.source())));
+            let background = if *background { " &" } else { "" };
+            format!("run {}{}", words.join(" "), background)
+        }
+        Expression::SetTrace(SetTrace(on)) => {
+            format!("set trace {}", if *on { "on" } else { "off" })
+        }
+        _ => return keep(),
+    };
+
+    let canonical = escape_comments(&canonical);
+    let expected = with_formatted_args(expr);
+    if parses_to(&canonical, &expected) {
+        (canonical, expected)
+    } else {
+        keep()
+    }
+}
+
+/// A declaration prefixed with a keyword `ex: const MAX: int = 3`, `wrap` builds the
+/// expression around the declaration
+fn keyword_decl<F>(keyword: &str, decl: &Expression, raw: &str, wrap: F) -> (String, Expression)
+where
+    F: Fn(Box<Expression>) -> Expression,
+{
+    let raw_decl = raw
+        .trim()
+        .split_once(char::is_whitespace)
+        .map_or(raw, |(_, decl)| decl);
+    let (text, formatted) = statement(decl, raw_decl.trim());
+    // The declaration is already escaped
+    let canonical = format!("{} {}", keyword, text);
+    let expected = wrap(Box::new(formatted));
+    if parses_to(&canonical, &expected) {
+        (canonical, expected)
+    } else {
+        (escape_comments(raw), wrap(Box::new(decl.clone())))
+    }
+}
+
+/// `expr` with the arguments of calls and commands formatted, the way its canonical form
+/// parses
+fn with_formatted_args(expr: &Expression) -> Expression {
+    let format_args = |args: &[Arg]| {
+        args.iter()
+            .map(|arg| format_arg(arg.source()))
+            .collect::<Vec<_>>()
+    };
+    match expr {
+        Expression::Call(call) => Expression::Call(Call::new(&call.name, format_args(&call.args))),
+        Expression::CallVar(var) => Expression::CallVar(CallVariable {
+            call: Call::new(&var.call.name, format_args(&var.call.args)),
+            ..var.clone()
+        }),
+        Expression::Run(run) => Expression::Run(Run {
+            args: format_args(&run.args).into_iter().map(Arg::parse).collect(),
+            ..run.clone()
+        }),
+        Expression::Readonly(readonly) => Expression::Readonly(Readonly {
+            kind: readonly.kind,
+            decl: Box::new(with_formatted_args(&readonly.decl)),
+        }),
+        Expression::Scoped(scoped) => Expression::Scoped(Scoped {
+            kind: scoped.kind,
+            decl: Box::new(with_formatted_args(&scoped.decl)),
+        }),
+        _ => expr.clone(),
+    }
+}
+
+fn parses_to(source: &str, expr: &Expression) -> bool {
+    source
+        .parse::<LangParser>()
+        .is_ok_and(|ast| ast.experssions.len() == 1 && ast.experssions[0] == *expr)
+}
+
+/// `age > 20`, `name == "Jone"`, `-f "notes.txt"`
+fn compare(raw_if: &str) -> String {
+    let con = raw_if.strip_prefix("if").unwrap_or(raw_if).trim();
+    if let Some(caps) = cached_regex!(RE_FILE_TEST).captures(con) {
+        let is_quoted = caps.get(2).is_some();
+        return format!(
+            "{} {}",
+            &caps["op"],
+            quote_if(caps["right"].trim(), is_quoted)
+        );
+    }
+
+    match cached_regex!(RE_CMP).captures(con) {
+        Some(caps) => format!(
+            "{} {} {}",
+            quote_if(caps["left"].trim(), caps.get(1).is_some()),
+            &caps["op"],
+            quote_if(caps["right"].trim(), caps.get(5).is_some())
+        ),
+        None => con.to_string(),
+    }
+}
+
+fn after_do(raw: &str) -> &str {
+    raw.strip_prefix("do").unwrap_or(raw).trim()
+}
+
+fn format_call(call: &Call, raw: &str) -> String {
+    let args = call
+        .args
+        .iter()
+        .map(|arg| format_arg(arg.source()))
+        .collect::<Vec<_>>();
+    if cached_regex!(RE_CALL).is_match(raw.trim()) {
+        format!("{}({})", call.name, args.join(", "))
+    } else if args.is_empty() {
+        call.name.clone()
+    } else {
+        format!("{} {}", call.name, args.join(" "))
+    }
+}
+
+/// Quote arguments with `"` and format nested calls
+fn format_arg(arg: &str) -> String {
+    if arg.len() >= 2 && arg.starts_with('\'') && arg.ends_with('\'') {
+        return quote(&arg[1..arg.len() - 1]);
+    }
+    if cached_regex!(RE_CALL).is_match(arg) {
+        if let Ok(call) = arg.parse::<Call>() {
+            return format_call(&call, arg);
+        }
+    }
+
+    arg.to_string()
+}
+
+/// Wrap `s` in double quotes, or single quotes if it contains double quotes
+fn quote(s: &str) -> String {
+    match (s.contains('"'), s.contains('\'')) {
+        (false, _) => format!("\"{}\"", s),
+        (true, false) => format!("'{}'", s),
+        (true, true) => s.to_string(),
+    }
+}
+
+fn quote_if(s: &str, is_quoted: bool) -> String {
+    if is_quoted {
+        quote(s)
+    } else {
+        s.to_string()
+    }
+}
+
+/// Put a single space around binary operators `ex: (12+12)/4 => (12 + 12) / 4`
+fn format_math(s: &str) -> String {
+    let mut out = String::new();
+    let mut after_operand = false;
+    for c in s.chars().filter(|c| !c.is_whitespace()) {
+        match c {
+            '+' | '-' | '*' | '/' | '%' | '^' if after_operand => {
+                out.push_str(&format!(" {} ", c));
+                after_operand = false;
+            }
+            '(' | '[' => {
+                out.push(c);
+                after_operand = false;
+            }
+            _ => {
+                out.push(c);
+                after_operand = true;
+            }
+        }
+    }
+
+    out
+}
+
+/// `#` starts a comment, literal ones are escaped
+fn escape_comments(s: &str) -> String {
+    s.replace('#', "\\#")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format, format_math, with_formatted_args};
+    use crate::lang_parser::{Expression, LangParser};
+    use proptest::prelude::*;
+
+    #[test]
+    fn normalise_spacing_and_quotes() {
+        let source = "const  MAX:int=3*4\nreadonly   user:str =  '$USER'\nglobal  count:int=1\nname: str=Jone\nemail:string =  'jone@mail.com'\nres: int=(12+12)/4\necho 'Hello, $name'\necho name\nthrow 'done'";
+        assert_eq!(
+            format(source).unwrap(),
+            "const MAX: int = 3 * 4\nreadonly user: str = \"$USER\"\nglobal count: int = 1\nname: str = \"Jone\"\nemail: str = \"jone@mail.com\"\nres: int = (12 + 12) / 4\necho \"Hello, $name\"\necho name\nthrow \"done\"\n"
+        );
+    }
+
+    #[test]
+    fn indent_blocks_and_keep_comments() {
+        let source = "#!/usr/bin/env mybash\n\n\n# Setup\ntry\n  mkdir  -p 'logs'   # make room\n\n# nothing else\n   catch e\necho e.message\n# last\nend\ntrap  EXIT\nrm -r \"logs\"\nend\n";
+        assert_eq!(
+            format(source).unwrap(),
+            "#!/usr/bin/env mybash\n\n# Setup\ntry\n    mkdir -p \"logs\" # make room\n\n    # nothing else\ncatch e\n    echo e.message\n    # last\nend\ntrap EXIT\n    rm -r \"logs\"\nend\n"
+        );
+    }
+
+    #[test]
+    fn format_conditions_and_calls() {
+        let source = "age: int = 30\nif age   >   40 # old?\ndo echo 'old'\nelse\ndo   write_file('out.txt',\"young\")\nendif\nif -f 'notes.txt'\ndo content: str = read_file( 'notes.txt' )\nendif\nif content  !=   \"\"\ndo echo content\nendif";
+        assert_eq!(
+            format(source).unwrap(),
+            "age: int = 30\nif age > 40 # old?\ndo echo \"old\"\nelse\ndo write_file(\"out.txt\", \"young\")\nendif\nif -f \"notes.txt\"\ndo content: str = read_file(\"notes.txt\")\nendif\nif content != \"\"\ndo echo content\nendif\n"
+        );
+    }
+
+    #[test]
+    fn format_match_blocks() {
+        let source = "match  $1 # command\ncase 'start'|\"go\"\necho 'starting'\n\n# versions\ncase   /^v[0-9]+$/ |1..3\nif $1 ==  v1\ndo echo 'old'\nendif\ncase n if  n ~= \"X\"\ncase _\nend\n";
+        assert_eq!(
+            format(source).unwrap(),
+            "match $1 # command\ncase \"start\" | \"go\"\n    echo \"starting\"\n\n    # versions\ncase /^v[0-9]+$/ | 1..3\n    if $1 == v1\n    do echo \"old\"\n    endif\ncase n if n ~= \"X\"\ncase _\nend\n"
+        );
+    }
+
+    #[test]
+    fn keep_statements_the_canonical_form_would_change() {
+        // The blank path is trimmed and `-f ""` doesn't parse
+        let source = "name: str = \"\\#hash\"\nif -f  \" \"\ndo echo name\nendif\n";
+        assert_eq!(format(source).unwrap(), source);
+    }
+
+    #[test]
+    fn format_is_idempotent() {
+        for entry in std::fs::read_dir("lang").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "mb") {
+                continue;
+            }
+            let source = std::fs::read_to_string(path).unwrap();
+            let formatted = format(&source).unwrap();
+            assert_eq!(format(&formatted).unwrap(), formatted);
+        }
+        assert_eq!(format_math("-3*(2+-1)"), "-3 * (2 + -1)");
+    }
+
+    /// Spaces the formatter normalises, `min` of them are required
+    fn spaces(min: usize) -> impl Strategy<Value = String> {
+        (min..min + 3).prop_map(|n| " ".repeat(n))
+    }
+
+    fn text() -> impl Strategy<Value = String> {
+        ("[a-z][a-z ]{0,7}", any::<bool>()).prop_map(|(text, double)| match double {
+            true => format!("\"{}\"", text),
+            false => format!("'{}'", text),
+        })
+    }
+
+    fn math() -> impl Strategy<Value = String> {
+        let number = "[0-9]{1,3}".prop_map(String::from);
+        number.prop_recursive(3, 8, 2, |inner| {
+            prop_oneof![
+                (inner.clone(), spaces(0), "[-+*/]", spaces(0), inner.clone())
+                    .prop_map(|(l, s1, op, s2, r)| format!("{}{}{}{}{}", l, s1, op, s2, r)),
+                inner.prop_map(|expr| format!("({})", expr)),
+            ]
+        })
+    }
+
+    /// A single line statement written in a non canonical way
+    fn statement() -> impl Strategy<Value = String> {
+        let name = "[a-z]{1,4}";
+        prop_oneof![
+            (name, spaces(0), spaces(0), spaces(0), text())
+                .prop_map(|(n, s1, s2, s3, v)| format!("{}:{}str{}={}{}", n, s1, s2, s3, v)),
+            (name, spaces(1), spaces(0), math())
+                .prop_map(|(n, s1, s2, v)| format!("{}:{}int{}= {}", n, s1, s2, v)),
+            (spaces(1), text()).prop_map(|(s, t)| format!("echo{}{}", s, t)),
+            name.prop_map(|n| format!("echo {}", n)),
+            (spaces(0), text(), spaces(0), text())
+                .prop_map(|(s1, a, s2, b)| format!("write_file({}{},{}{})", s1, a, s2, b)),
+            text().prop_map(|t| format!("throw {}", t)),
+        ]
+    }
+
+    /// Lines of statements and well formed blocks, without blank lines so the line numbers
+    /// of the formatted script stay the same
+    fn script() -> impl Strategy<Value = Vec<String>> {
+        let line = prop_oneof![
+            (spaces(0), statement()).prop_map(|(indent, stmt)| format!("{}{}", indent, stmt)),
+            "# [a-z ]{0,8}".prop_map(String::from),
+        ];
+        prop::collection::vec(line, 1..4).prop_recursive(3, 24, 4, |inner| {
+            let block = prop_oneof![
+                (inner.clone(), inner.clone()).prop_map(|(body, handler)| {
+                    [
+                        vec!["try".to_string()],
+                        body,
+                        vec!["catch e".into()],
+                        handler,
+                        vec!["end".into()],
+                    ]
+                    .concat()
+                }),
+                ("[a-z]{1,4}", inner.clone()).prop_map(|(name, body)| {
+                    [vec![format!("fn {}", name)], body, vec!["end".into()]].concat()
+                }),
+                (
+                    spaces(1),
+                    "[a-z]{1,4}",
+                    "[0-9]{1,2}",
+                    statement(),
+                    statement()
+                )
+                    .prop_map(|(s, name, num, then, other)| {
+                        vec![
+                            format!("if {}{}>{}{}", name, s, s, num),
+                            format!("do {}", then),
+                            "else".into(),
+                            format!("do {}", other),
+                            "endif".into(),
+                        ]
+                    }),
+                (spaces(0), spaces(1), inner.clone(), inner.clone()).prop_map(
+                    |(s, s1, first, other)| {
+                        [
+                            vec!["match $1".to_string(), format!("case 'a'{}|{}1..3", s, s)],
+                            first,
+                            vec![format!("case{}n if n ~= \"b\"", s1), "case _".into()],
+                            other,
+                            vec!["end".into()],
+                        ]
+                        .concat()
+                    }
+                ),
+                inner.clone(),
+            ];
+            prop::collection::vec(block, 1..4).prop_map(|blocks| blocks.concat())
+        })
+    }
+
+    /// `ast` with call arguments quoted the way the formatter quotes them
+    fn canonical(ast: &LangParser) -> LangParser {
+        let experssions = ast.experssions.iter().map(|expr| match expr {
+            Expression::Condition(con) => {
+                let mut con = con.clone();
+                con.if_expr = with_formatted_args(&con.if_expr);
+                con.else_expr = con.else_expr.as_ref().map(with_formatted_args);
+                Expression::Condition(con)
+            }
+            Expression::TryCatch(try_catch) => {
+                let mut try_catch = try_catch.clone();
+                try_catch.body = canonical(&try_catch.body);
+                try_catch.catch_body = canonical(&try_catch.catch_body);
+                Expression::TryCatch(try_catch)
+            }
+            Expression::Defer(defer) => {
+                let mut defer = defer.clone();
+                defer.body = canonical(&defer.body);
+                Expression::Defer(defer)
+            }
+            Expression::Function(function) => {
+                let mut function = function.clone();
+                function.body = canonical(&function.body);
+                Expression::Function(function)
+            }
+            Expression::Match(block) => {
+                let mut block = block.clone();
+                for case in &mut block.cases {
+                    case.body = canonical(&case.body);
+                }
+                Expression::Match(block)
+            }
+            expr => with_formatted_args(expr),
+        });
+
+        LangParser {
+            experssions: experssions.collect(),
+            lines: ast.lines.clone(),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn format_round_trips(lines in script()) {
+            let source = lines.join("\n");
+            let ast = source.parse::<LangParser>().unwrap();
+            let formatted = format(&source).unwrap();
+            prop_assert_eq!(canonical(&formatted.parse::<LangParser>().unwrap()), canonical(&ast));
+            prop_assert_eq!(format(&formatted).unwrap(), formatted);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    lang_parser::{find_block_end, LangParser, ParseErr, SourceLine},
+    regex::{cached_regex, RE_FN},
+};
+use serde::{Deserialize, Serialize};
+
+/// A named block run by calling it
+///
+/// Example
+/// ```text
+/// fn greet
+/// echo "Hello, $name"
+/// end
+///
+/// greet()
+/// ```
+/// Functions take no arguments and share the script's variables. A function is defined
+/// when its `fn` statement runs, built-ins take precedence over functions with the same name
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Function {
+    pub name: String,
+    pub body: LangParser,
+}
+
+impl Function {
+    pub fn is_function(s: &str) -> bool {
+        s.trim().starts_with("fn ")
+    }
+
+    /// Parse the block starting at `lines[start_idx]`, returns the block and the index of its `end`
+    pub fn from_lines(lines: &[SourceLine], start_idx: usize) -> Result<(Self, usize), ParseErr> {
+        let (fn_line, header) = &lines[start_idx];
+        let re = cached_regex!(RE_FN);
+        let caps = re.captures(header.trim()).ok_or_else(|| {
+            ParseErr::InvalidExperssion(format!(
+                "`{}` is not a valid function, expected `fn <name>`",
+                header.trim()
+            ))
+        })?;
+
+        let end_idx = find_block_end(lines, start_idx).ok_or_else(|| {
+            ParseErr::UnterminatedBlock(format!("`fn` at line {} is missing `end`", fn_line))
+        })?;
+
+        Ok((
+            Self {
+                name: caps["name"].to_string(),
+                body: LangParser::from_lines(&lines[start_idx + 1..end_idx])?,
+            },
+            end_idx,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        call::Call,
+        echo::Echo,
+        lang_parser::{Expression, LangParser, ParseErr},
+    };
+
+    use super::Function;
+
+    #[test]
+    fn parse_function() {
+        let expr = "fn greet\ntry\necho 'hi'\ncatch\nend\nend\ngreet()";
+        let result = expr.parse::<LangParser>().unwrap();
+
+        assert_eq!(result.lines, vec![1, 7]);
+        let Expression::Function(function) = &result.experssions[0] else {
+            panic!("expected a function")
+        };
+        assert_eq!(function.name, "greet");
+        assert!(matches!(
+            function.body.experssions[0],
+            Expression::TryCatch(_)
+        ));
+        assert_eq!(
+            result.experssions[1],
+            Expression::Call(Call::new("greet", vec![]))
+        );
+
+        let function = "fn say\necho 'hi'\nend".parse::<LangParser>().unwrap();
+        assert_eq!(
+            function.experssions,
+            vec![Expression::Function(Box::new(Function {
+                name: "say".into(),
+                body: LangParser {
+                    experssions: vec![Expression::Echo(Echo("hi".into()))],
+                    lines: vec![2]
+                }
+            }))]
+        );
+    }
+
+    #[test]
+    fn parse_invalid_function() {
+        assert!(matches!(
+            "fn greet(name)\nend".parse::<LangParser>(),
+            Err(ParseErr::InvalidExperssion(_))
+        ));
+        assert_eq!(
+            "fn greet\necho 'hi'".parse::<LangParser>().err().unwrap(),
+            ParseErr::UnterminatedBlock("`fn` at line 1 is missing `end`".into())
+        );
+    }
+}
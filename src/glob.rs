@@ -0,0 +1,129 @@
+/// Returns `true` if `s` contains any of the glob metacharacters `*`, `?` or `[`
+pub fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+/// Bash-style glob matching
+///
+/// Supported syntax
+/// ```text
+/// *       matches any sequence of characters (including none)
+/// ?       matches exactly one character
+/// [abc]   matches one character from the set
+/// [a-z]   matches one character from the range
+/// [!a-z]  matches one character not in the range (`[^a-z]` works too)
+/// \*      matches a literal `*`
+/// ```
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` in the pattern and the text index it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let matched = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(p + 1),
+            Some('[') => match_class(&pattern, p, text[t]),
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(p + 2),
+            Some(c) => (*c == text[t]).then_some(p + 1),
+            None => None,
+        };
+
+        match (matched, backtrack) {
+            (Some(next), _) => {
+                p = next;
+                t += 1;
+            }
+            (None, Some((star_p, star_t))) => {
+                p = star_p + 1;
+                t = star_t + 1;
+                backtrack = Some((star_p, star_t + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Match `c` against the character class starting at `pattern[start] == '['`.
+/// Returns the pattern index right after the class on success. An unterminated
+/// class is treated as a literal `[`.
+fn match_class(pattern: &[char], start: usize, c: char) -> Option<usize> {
+    let mut idx = start + 1;
+    let negated = matches!(pattern.get(idx), Some('!') | Some('^'));
+    if negated {
+        idx += 1;
+    }
+
+    let mut found = false;
+    let mut first = true;
+    while idx < pattern.len() && (pattern[idx] != ']' || first) {
+        first = false;
+        let lo = pattern[idx];
+        if pattern.get(idx + 1) == Some(&'-') && idx + 2 < pattern.len() && pattern[idx + 2] != ']'
+        {
+            let hi = pattern[idx + 2];
+            found |= lo <= c && c <= hi;
+            idx += 3;
+        } else {
+            found |= lo == c;
+            idx += 1;
+        }
+    }
+
+    if idx >= pattern.len() {
+        // No closing `]`, so the `[` is just a regular character
+        return (c == '[').then_some(start + 1);
+    }
+
+    (found != negated).then_some(idx + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{glob_match, is_glob};
+
+    #[test]
+    fn should_detect_glob_patterns() {
+        assert!(is_glob("*.log"));
+        assert!(is_glob("file?.txt"));
+        assert!(is_glob("[abc]"));
+        assert!(!is_glob("plain.txt"));
+    }
+
+    #[test]
+    fn should_match_wildcards() {
+        assert!(glob_match("*.log", "server.log"));
+        assert!(glob_match("*.log", ".log"));
+        assert!(!glob_match("*.log", "server.log.gz"));
+        assert!(glob_match("s*r*.log", "server.log"));
+        assert!(glob_match("file?.txt", "file1.txt"));
+        assert!(!glob_match("file?.txt", "file10.txt"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn should_match_character_classes() {
+        assert!(glob_match("[abc].txt", "b.txt"));
+        assert!(!glob_match("[abc].txt", "d.txt"));
+        assert!(glob_match("v[0-9]", "v7"));
+        assert!(glob_match("v[!0-9]", "vx"));
+        assert!(!glob_match("v[!0-9]", "v7"));
+        assert!(glob_match("[]]", "]"));
+        assert!(glob_match("[", "["));
+    }
+
+    #[test]
+    fn should_match_escaped_metacharacters() {
+        assert!(glob_match(r"\*.txt", "*.txt"));
+        assert!(!glob_match(r"\*.txt", "a.txt"));
+    }
+}
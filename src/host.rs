@@ -0,0 +1,276 @@
+use crate::{executor::ExeError, variables::VarValue};
+use std::{collections::BTreeMap, fmt::Display};
+
+/// A host function after its arguments and return value were erased to `VarValue`s
+pub type BoxedHostFn = Box<dyn Fn(&str, Vec<VarValue>) -> Result<VarValue, ExeError>>;
+
+/// Conversion from a script value into a host function argument
+pub trait FromVarValue: Sized {
+    /// Type name used in error messages
+    const TYPE: &'static str;
+
+    fn from_var_value(value: VarValue) -> Option<Self>;
+}
+
+/// Conversion from a host function return value into a script value
+pub trait IntoVarValue {
+    fn into_var_value(self) -> VarValue;
+}
+
+/// The return type of a host function, either a value or a `Result` whose error becomes a
+/// script-level error
+pub trait HostReturn {
+    fn into_result(self) -> Result<VarValue, String>;
+}
+
+impl<T: IntoVarValue> HostReturn for T {
+    fn into_result(self) -> Result<VarValue, String> {
+        Ok(self.into_var_value())
+    }
+}
+
+impl<T: IntoVarValue, E: Display> HostReturn for Result<T, E> {
+    fn into_result(self) -> Result<VarValue, String> {
+        self.map(IntoVarValue::into_var_value)
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl FromVarValue for VarValue {
+    const TYPE: &'static str = "any";
+
+    fn from_var_value(value: VarValue) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl FromVarValue for i32 {
+    const TYPE: &'static str = "int";
+
+    fn from_var_value(value: VarValue) -> Option<Self> {
+        match value {
+            VarValue::Int(val) => Some(val),
+            VarValue::Str(val) => val.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl FromVarValue for bool {
+    const TYPE: &'static str = "bool";
+
+    fn from_var_value(value: VarValue) -> Option<Self> {
+        match value {
+            VarValue::Bool(val) => Some(val),
+            VarValue::Str(val) => val.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Any value can be passed as a string, just like `name: str = ...`
+impl FromVarValue for String {
+    const TYPE: &'static str = "str";
+
+    fn from_var_value(value: VarValue) -> Option<Self> {
+        match value {
+            VarValue::Str(val) => Some(val),
+            other => Some(other.to_string()),
+        }
+    }
+}
+
+impl<T: FromVarValue> FromVarValue for Vec<T> {
+    const TYPE: &'static str = "list";
+
+    fn from_var_value(value: VarValue) -> Option<Self> {
+        match value {
+            VarValue::List(items) => items.into_iter().map(T::from_var_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: FromVarValue> FromVarValue for BTreeMap<String, T> {
+    const TYPE: &'static str = "map";
+
+    fn from_var_value(value: VarValue) -> Option<Self> {
+        match value {
+            VarValue::Map(fields) => fields
+                .into_iter()
+                .map(|(key, value)| Some((key, T::from_var_value(value)?)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+impl IntoVarValue for VarValue {
+    fn into_var_value(self) -> VarValue {
+        self
+    }
+}
+
+/// Functions without a return value evaluate to `true`, like the filesystem built-ins
+impl IntoVarValue for () {
+    fn into_var_value(self) -> VarValue {
+        VarValue::Bool(true)
+    }
+}
+
+impl IntoVarValue for i32 {
+    fn into_var_value(self) -> VarValue {
+        VarValue::Int(self)
+    }
+}
+
+impl IntoVarValue for bool {
+    fn into_var_value(self) -> VarValue {
+        VarValue::Bool(self)
+    }
+}
+
+impl IntoVarValue for String {
+    fn into_var_value(self) -> VarValue {
+        VarValue::Str(self)
+    }
+}
+
+impl IntoVarValue for &str {
+    fn into_var_value(self) -> VarValue {
+        VarValue::Str(self.to_string())
+    }
+}
+
+impl<T: IntoVarValue> IntoVarValue for Vec<T> {
+    fn into_var_value(self) -> VarValue {
+        VarValue::List(self.into_iter().map(T::into_var_value).collect())
+    }
+}
+
+impl<T: IntoVarValue> IntoVarValue for BTreeMap<String, T> {
+    fn into_var_value(self) -> VarValue {
+        VarValue::Map(
+            self.into_iter()
+                .map(|(key, value)| (key, value.into_var_value()))
+                .collect(),
+        )
+    }
+}
+
+/// A Rust closure that can be called from scripts. Implemented for closures of up to
+/// four arguments whose types implement `FromVarValue` and whose return type implements
+/// `HostReturn`
+pub trait HostFn<Args>: 'static {
+    fn call(&self, name: &str, args: Vec<VarValue>) -> Result<VarValue, ExeError>;
+
+    fn boxed(self) -> BoxedHostFn
+    where
+        Self: Sized,
+    {
+        Box::new(move |name, args| self.call(name, args))
+    }
+}
+
+/// Convert the argument at `idx` (0-based) for the host function `name`
+fn arg<T: FromVarValue>(name: &str, idx: usize, value: VarValue) -> Result<T, ExeError> {
+    let found = value.to_string();
+    T::from_var_value(value).ok_or_else(|| {
+        ExeError::InvalidCall(format!(
+            "`{}` expects argument {} to be {} but got `{}`",
+            name,
+            idx + 1,
+            T::TYPE,
+            found
+        ))
+    })
+}
+
+macro_rules! impl_host_fn {
+    ($len:literal $(, $arg:ident)*) => {
+        impl<F, R, $($arg,)*> HostFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: HostReturn,
+            $($arg: FromVarValue,)*
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call(&self, name: &str, args: Vec<VarValue>) -> Result<VarValue, ExeError> {
+                if args.len() != $len {
+                    return Err(ExeError::InvalidCall(format!(
+                        "`{}` expects {} argument(s) but got {}",
+                        name,
+                        $len,
+                        args.len()
+                    )));
+                }
+
+                let mut args = args.into_iter().enumerate();
+                $(
+                    let (idx, value) = args.next().unwrap();
+                    let $arg = arg::<$arg>(name, idx, value)?;
+                )*
+                (self)($($arg),*)
+                    .into_result()
+                    .map_err(|e| ExeError::HostFnErr(format!("`{}` failed: {}", name, e)))
+            }
+        }
+    };
+}
+
+impl_host_fn!(0);
+impl_host_fn!(1, A);
+impl_host_fn!(2, A, B);
+impl_host_fn!(3, A, B, C);
+impl_host_fn!(4, A, B, C, D);
+
+#[cfg(test)]
+mod test {
+    use super::{FromVarValue, HostFn, IntoVarValue};
+    use crate::{executor::ExeError, variables::VarValue};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn convert_values() {
+        assert_eq!(i32::from_var_value(VarValue::Str(" 42".into())), Some(42));
+        assert_eq!(bool::from_var_value(VarValue::Int(1)), None);
+        assert_eq!(
+            String::from_var_value(VarValue::Int(7)),
+            Some("7".to_string())
+        );
+        assert_eq!(
+            Vec::<i32>::from_var_value(VarValue::List(vec![VarValue::Int(1), VarValue::Int(2)])),
+            Some(vec![1, 2])
+        );
+        assert_eq!(
+            BTreeMap::from([("a".to_string(), true)]).into_var_value(),
+            VarValue::Map(BTreeMap::from([("a".to_string(), VarValue::Bool(true))]))
+        );
+    }
+
+    #[test]
+    fn call_typed_closures() {
+        let add = (|a: i32, b: i32| a + b).boxed();
+        assert_eq!(
+            add("add", vec![VarValue::Int(1), VarValue::Str("2".into())]).unwrap(),
+            VarValue::Int(3)
+        );
+
+        let err = add("add", vec![VarValue::Int(1)]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid call: `add` expects 2 argument(s) but got 1"
+        );
+
+        let err = add("add", vec![VarValue::Int(1), VarValue::Bool(true)])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid call: `add` expects argument 2 to be int but got `true`"
+        );
+
+        let fail = (|| -> Result<(), String> { Err("no access".into()) }).boxed();
+        assert!(matches!(fail("fail", vec![]), Err(ExeError::HostFnErr(_))));
+    }
+}
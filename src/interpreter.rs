@@ -0,0 +1,467 @@
+use crate::{
+    bytecode::{CompileErr, Compiler},
+    compiled::{self, CompiledErr},
+    debugger::Debugger,
+    executor::{ExeError, Executor},
+    host::HostFn,
+    lang_parser::{LangParser, ParseErr},
+    limits::Limits,
+    sandbox::Sandbox,
+    variables::VarValue,
+    vm::Vm,
+};
+use std::{
+    io::{BufRead, Write},
+    path::Path,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum InterpreterErr {
+    #[error("`{0}` not found")]
+    FileNotFound(String),
+    #[error("IO Error: `{0}`")]
+    IoError(#[from] std::io::Error),
+    #[error("Parse error: {0}")]
+    ParseErr(#[from] ParseErr),
+    #[error("Compile error: {0}")]
+    CompileErr(#[from] CompileErr),
+    #[error("Compiled script error: {0}")]
+    CompiledErr(#[from] CompiledErr),
+    #[error("Executor error at line {line}: {err}")]
+    ExeError { err: ExeError, line: usize },
+}
+
+/// Embeddable mybash interpreter. Variables are kept between evaluations
+///
+/// Example
+/// ```
+/// use mybash::{Interpreter, VarValue};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_var("name", VarValue::Str("Jone".into()));
+/// interpreter.eval_str("greeting: str = \"Hello, $name\"").unwrap();
+/// assert_eq!(
+///     interpreter.get_var("greeting"),
+///     Some(&VarValue::Str("Hello, Jone".into()))
+/// );
+/// ```
+#[derive(Default)]
+pub struct Interpreter {
+    exe: Executor,
+    vm: bool,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse and run a script
+    pub fn eval_str(&mut self, source: &str) -> Result<(), InterpreterErr> {
+        let ast = source.parse::<LangParser>()?;
+        self.eval_ast(&ast)
+    }
+
+    /// Run an already parsed script
+    pub fn eval_ast(&mut self, ast: &LangParser) -> Result<(), InterpreterErr> {
+        let result = if self.vm && !self.exe.trace {
+            let program = Compiler::compile(ast, self.exe.vars.keys())?;
+            Vm::new(&mut self.exe, &program).run()
+        } else {
+            self.exe.execute(ast)
+        };
+
+        result.map_err(|err| InterpreterErr::ExeError {
+            err,
+            line: self.exe.line(),
+        })
+    }
+
+    /// Run the script at `path`, either a source file or a script compiled with
+    /// `mybash compile`. `$0` is set to the path
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), InterpreterErr> {
+        let path = path.as_ref();
+        let ast = compiled::load(path)?;
+        self.exe.script_name = path.display().to_string();
+        self.eval_ast(&ast)
+    }
+
+    pub fn set_var<T: Into<String>>(&mut self, name: T, value: VarValue) {
+        self.exe.insert_var(name.into(), value);
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<&VarValue> {
+        self.exe.vars.get(name)
+    }
+
+    /// Expose a Rust closure to scripts as the function `name`. Arguments and the return
+    /// value are converted from and to `VarValue`s, returning `Err` raises a `call` error
+    ///
+    /// Example
+    /// ```
+    /// use mybash::Interpreter;
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.register_fn("deploy", |env: String| env == "prod");
+    /// interpreter.register_fn("get_secret", |name: String| -> Result<String, String> {
+    ///     Err(format!("no secret named `{}`", name))
+    /// });
+    ///
+    /// interpreter.eval_str("deployed: bool = deploy(\"prod\")").unwrap();
+    /// assert!(interpreter.eval_str("token: str = get_secret(\"token\")").is_err());
+    /// ```
+    pub fn register_fn<Args, F: HostFn<Args>>(&mut self, name: &str, host_fn: F) {
+        self.exe.host_fns.insert(name.to_string(), host_fn.boxed());
+    }
+
+    /// Set the positional arguments `$1`, `$2`, ...
+    pub fn set_args<I, S>(&mut self, args: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exe.args = args.into_iter().map(Into::into).collect();
+    }
+
+    /// Run scripts on the bytecode VM instead of walking the AST. The VM doesn't support
+    /// function calls, `run`, `trap` and `set trace` yet, scripts using them fail to compile
+    pub fn set_vm(&mut self, enabled: bool) {
+        self.vm = enabled;
+    }
+
+    /// Restrict what scripts are allowed to do, violations raise `permission` errors
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.exe.sandbox = sandbox;
+    }
+
+    /// Limit the resources a script may use, exceeding a limit ends the script with a
+    /// `limit` error that `try` can't catch
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.exe.limits = limits;
+    }
+
+    /// Stop scripts at breakpoints and while stepping, only the tree-walking interpreter
+    /// supports debugging
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.exe.debugger = Some(debugger);
+    }
+
+    /// Print every statement to stderr before it runs, the same as starting the script with
+    /// `set trace on`. Traced scripts run on the tree-walking interpreter
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.exe.trace = enabled;
+    }
+
+    pub fn set_stdout<W: Write + 'static>(&mut self, stdout: W) {
+        self.exe.streams.stdout = Box::new(stdout);
+        self.exe.streams.inherited = false;
+    }
+
+    pub fn set_stderr<W: Write + 'static>(&mut self, stderr: W) {
+        self.exe.streams.stderr = Box::new(stderr);
+        self.exe.streams.inherited = false;
+    }
+
+    pub fn set_stdin<R: BufRead + 'static>(&mut self, stdin: R) {
+        self.exe.streams.stdin = Box::new(stdin);
+        self.exe.streams.inherited = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Interpreter, InterpreterErr};
+    use crate::{
+        executor::{ErrorKind, ExeError},
+        limits::Limits,
+        sandbox::{Capability, Sandbox},
+        streams::OutputBuffer,
+        variables::VarValue,
+    };
+    use proptest::prelude::*;
+    use std::{io::Cursor, time::Duration};
+
+    #[test]
+    fn redirect_output_and_input() {
+        let out = OutputBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_stdout(out.clone());
+        interpreter.set_stdin(Cursor::new("Jone\n"));
+
+        interpreter
+            .eval_str("name: str = input(\"Name? \")\necho \"Hello, $name\"")
+            .unwrap();
+
+        assert_eq!(out.contents(), "Name? Hello, Jone\n");
+    }
+
+    #[test]
+    fn positional_args_and_variables() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_args(["foo", "bar"]);
+        interpreter.set_var("count", VarValue::Int(2));
+
+        interpreter
+            .eval_str("first: str = \"$1\"\nmissing: str = \"${3}!\"")
+            .unwrap();
+        interpreter.eval_str("echo count").unwrap();
+
+        assert_eq!(
+            interpreter.get_var("first"),
+            Some(&VarValue::Str("foo".into()))
+        );
+        assert_eq!(
+            interpreter.get_var("missing"),
+            Some(&VarValue::Str("!".into()))
+        );
+        assert_eq!(interpreter.get_var("count"), Some(&VarValue::Int(2)));
+    }
+
+    #[test]
+    fn report_errors_with_line() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_stdout(OutputBuffer::default());
+
+        let err = interpreter
+            .eval_str("echo 'ok'\nthrow 'boom'")
+            .err()
+            .unwrap();
+        match err {
+            InterpreterErr::ExeError { err, line } => {
+                assert_eq!(err.kind(), ErrorKind::Throw);
+                assert_eq!(line, 2);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+        assert!(matches!(
+            interpreter.eval_file("/no/such/script.mb"),
+            Err(InterpreterErr::FileNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn call_host_functions() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_fn("deploy", |env: String| env == "prod");
+        interpreter.register_fn("add", |a: i32, b: i32| a + b);
+        interpreter.register_fn("fail", || -> Result<i32, String> { Err("boom".into()) });
+
+        interpreter
+            .eval_str("env: str = prod\nok: bool = deploy(env)\nsum: int = add(2, add(3, 4))")
+            .unwrap();
+        assert_eq!(interpreter.get_var("ok"), Some(&VarValue::Bool(true)));
+        assert_eq!(interpreter.get_var("sum"), Some(&VarValue::Int(9)));
+
+        let err = interpreter
+            .eval_str("first: int = add(1, 2)\nsum: int = add(1, true)")
+            .err()
+            .unwrap();
+        let InterpreterErr::ExeError { err, line } = err else {
+            panic!("expected an executor error")
+        };
+        assert_eq!(err.kind(), ErrorKind::Call);
+        assert_eq!(line, 2);
+
+        interpreter
+            .eval_str("try\nfail()\ncatch\nmessage: str = \"$err.message\"\nend")
+            .unwrap();
+        assert_eq!(
+            interpreter.get_var("message"),
+            Some(&VarValue::Str("`fail` failed: boom".into()))
+        );
+    }
+
+    #[test]
+    fn deny_sandboxed_capabilities() {
+        let denied = |source: &str| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_sandbox(Sandbox::deny_all());
+            match interpreter.eval_str(source) {
+                Err(InterpreterErr::ExeError {
+                    err: ExeError::PermissionDenied(capability, _),
+                    ..
+                }) => capability,
+                other => panic!("`{}` should be denied, got {:?}", source, other),
+            }
+        };
+
+        assert_eq!(denied("run true"), Capability::Commands);
+        assert_eq!(denied("kill 1"), Capability::Commands);
+        assert_eq!(
+            denied("write_file(\"x.txt\", \"data\")"),
+            Capability::FsWrite
+        );
+        assert_eq!(denied("rm -r \"/\""), Capability::FsWrite);
+        assert_eq!(denied("echo \"$HOME\""), Capability::Env);
+        assert_eq!(denied("name: str = input()"), Capability::Stdin);
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_sandbox(Sandbox::deny_all());
+        interpreter.set_args(["foo"]);
+        interpreter
+            .eval_str("name: str = Jone\ngreeting: str = \"$name $1\"")
+            .unwrap();
+        assert_eq!(
+            interpreter.get_var("greeting"),
+            Some(&VarValue::Str("Jone foo".into()))
+        );
+
+        let err = interpreter.eval_str("echo $PATH").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Executor error at line 1: Permission denied: reading `$PATH` requires the `env` capability"
+        );
+    }
+
+    #[test]
+    fn enforce_resource_limits() {
+        let exceeded = |limits: Limits, source: &str| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_stdout(OutputBuffer::default());
+            interpreter.set_limits(limits);
+            match interpreter.eval_str(source) {
+                Err(InterpreterErr::ExeError { err, .. }) => err,
+                other => panic!("`{}` should exceed a limit, got {:?}", source, other),
+            }
+        };
+
+        let err = exceeded(
+            Limits {
+                max_steps: Some(2),
+                ..Limits::default()
+            },
+            "echo 'a'\necho 'b'\ntry\necho 'c'\ncatch\nend",
+        );
+        assert!(matches!(err, ExeError::StepLimit(2)));
+        assert_eq!(err.kind(), ErrorKind::Limit);
+
+        let err = exceeded(
+            Limits {
+                max_memory: Some(8),
+                ..Limits::default()
+            },
+            "name: str = Jone\nlast: str = \"Doe Smith\"",
+        );
+        assert!(matches!(err, ExeError::MemoryLimit(8)));
+
+        // Caught errors count, `let` variables are freed when their block ends
+        let err = exceeded(
+            Limits {
+                max_memory: Some(20),
+                ..Limits::default()
+            },
+            "try\nthrow 'x'\ncatch\nend",
+        );
+        assert!(matches!(err, ExeError::MemoryLimit(20)));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(Limits {
+            max_memory: Some(8),
+            ..Limits::default()
+        });
+        interpreter
+            .eval_str("try\nlet a: str = abcdefgh\ncatch\nend\nb: str = abcdefgh")
+            .unwrap();
+
+        let err = exceeded(
+            Limits {
+                max_output: Some(4),
+                ..Limits::default()
+            },
+            "echo 'abc'\necho 'def'",
+        );
+        assert!(matches!(err, ExeError::OutputLimit(4)));
+
+        let err = exceeded(
+            Limits {
+                max_depth: Some(2),
+                ..Limits::default()
+            },
+            "try\ntry\necho 'deep'\ncatch\nend\ncatch\nend",
+        );
+        assert!(matches!(err, ExeError::RecursionLimit(2)));
+
+        let err = exceeded(
+            Limits {
+                timeout: Some(Duration::ZERO),
+                ..Limits::default()
+            },
+            "echo 'late'",
+        );
+        assert!(matches!(err, ExeError::Timeout(_)));
+
+        // A timeout past what `Instant` can hold never expires
+        let mut interpreter = Interpreter::new();
+        interpreter.set_stdout(OutputBuffer::default());
+        interpreter.set_limits(Limits {
+            timeout: Some(Duration::MAX),
+            ..Limits::default()
+        });
+        interpreter.eval_str("echo 'never late'").unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kill_commands_at_the_timeout() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(Limits {
+            timeout: Some(Duration::from_millis(100)),
+            ..Limits::default()
+        });
+
+        let err = interpreter.eval_str("run sleep 5").err().unwrap();
+        assert!(matches!(
+            err,
+            InterpreterErr::ExeError {
+                err: ExeError::Timeout(_),
+                line: 1
+            }
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn capture_command_output() {
+        let out = OutputBuffer::default();
+        let err = OutputBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_stdout(out.clone());
+        interpreter.set_stderr(err.clone());
+
+        interpreter
+            .eval_str("run sh -c \"echo out; echo err >&2\"\nrun echo background &\nwait")
+            .unwrap();
+
+        assert_eq!(out.contents(), "out\nbackground\n");
+        assert_eq!(err.contents(), "err\n");
+    }
+
+    /// A line that is likely to get past the first checks of the parser
+    fn line() -> impl Strategy<Value = String> {
+        let keyword = "(if|do|else|endif|echo|try|catch|end|trap|throw|run|set trace|fn|match|case|const |readonly |let |global |[a-z]{1,3}: (int|str|bool|list|map) =|[a-z]{1,3}\\()";
+        prop_oneof![
+            "[ -~]{0,24}",
+            (keyword, "[ -~$(){}\\[\\]\"'#*/+-]{0,16}")
+                .prop_map(|(keyword, rest)| format!("{} {}", keyword, rest)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn any_script_is_a_result_not_a_panic(lines in prop::collection::vec(line(), 0..12)) {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_sandbox(Sandbox::deny_all());
+            interpreter.set_limits(Limits {
+                max_steps: Some(1_000),
+                timeout: Some(Duration::from_secs(1)),
+                max_memory: Some(1 << 20),
+                max_output: Some(1 << 16),
+                max_depth: Some(32),
+            });
+            interpreter.set_stdout(OutputBuffer::default());
+            interpreter.set_stderr(OutputBuffer::default());
+            interpreter.set_stdin(Cursor::new(Vec::new()));
+            let _ = interpreter.eval_str(&lines.join("\n"));
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use crate::{
+    arg::Arg,
+    regex::{cached_regex, RE_RUN},
+    utils::split_args,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    io::{self, Read},
+    process::{Child, Command, ExitStatus, Stdio},
+    str::FromStr,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum JobErr {
+    #[error("`{0}` is not a valid run statment")]
+    InvalidRun(String),
+    #[error("No such job: {0}")]
+    NoSuchJob(u32),
+    #[error("`{0}` is not a valid signal")]
+    InvalidSignal(String),
+    #[error("{0}")]
+    Io(String),
+    /// The command was killed at the deadline, holds its exit status
+    #[error("Killed at the deadline")]
+    TimedOut(i32),
+}
+
+impl From<io::Error> for JobErr {
+    fn from(e: io::Error) -> Self {
+        JobErr::Io(e.to_string())
+    }
+}
+
+/// Run an external command
+///
+/// Example
+/// ```text
+/// run cargo build --release
+/// run ./deploy.sh "$env" &
+/// ```
+/// Arguments are parsed once and keep their source as written (quotes included).
+/// A trailing `&` runs the command in the background
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Run {
+    pub program: Arg,
+    pub args: Vec<Arg>,
+    pub background: bool,
+}
+
+impl Run {
+    pub fn is_run(s: &str) -> bool {
+        let re = cached_regex!(RE_RUN);
+        re.is_match(s.trim())
+    }
+}
+
+impl FromStr for Run {
+    type Err = JobErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let re = cached_regex!(RE_RUN);
+
+        if let Some(caps) = re.captures(s.trim()) {
+            let mut args = split_args(&caps["cmd"], None)
+                .into_iter()
+                .map(Arg::parse)
+                .collect::<Vec<_>>();
+            let program = args.remove(0);
+            Ok(Self {
+                program,
+                args,
+                background: caps.name("bg").is_some(),
+            })
+        } else {
+            Err(JobErr::InvalidRun(s.into()))
+        }
+    }
+}
+
+pub struct Job {
+    pub id: usize,
+    pub pid: u32,
+    pub command: String,
+    /// `None` once the job finished and was reaped
+    child: Option<Child>,
+    status: Option<i32>,
+}
+
+impl Job {
+    pub fn is_running(&self) -> bool {
+        self.status.is_none()
+    }
+}
+
+impl Display for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match self.status {
+            None => "Running".to_string(),
+            Some(status) => format!("Done({})", status),
+        };
+        write!(f, "[{}] {} {}\t{}", self.id, self.pid, state, self.command)
+    }
+}
+
+/// Output of a command run while `JobTable::capture` is set
+#[derive(Debug, PartialEq, Eq)]
+pub struct Captured {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// The background jobs started by a script
+#[derive(Default)]
+pub struct JobTable {
+    jobs: Vec<Job>,
+    /// PID of the last background job `$!`
+    pub last_pid: Option<u32>,
+    /// Exit status of the last foreground command or waited job `$?`
+    pub last_status: i32,
+    /// Capture the output of commands instead of letting them inherit the process' streams.
+    /// Background jobs hand over their output once they are waited for
+    pub capture: bool,
+    captured: Vec<Captured>,
+    /// Commands still running at the deadline are killed
+    pub deadline: Option<Instant>,
+}
+
+impl JobTable {
+    fn command(&self, program: &str, args: &[String]) -> Command {
+        let mut command = Command::new(program);
+        command.args(args);
+        if self.capture {
+            command
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+        }
+        command
+    }
+
+    /// Run a command in the foreground and return its exit status
+    pub fn run(&mut self, program: &str, args: &[String]) -> Result<i32, JobErr> {
+        let child = self.command(program, args).spawn()?;
+        let (status, captured) = match wait_child(child, self.deadline) {
+            Err(JobErr::TimedOut(status)) => {
+                self.last_status = status;
+                return Err(JobErr::TimedOut(status));
+            }
+            result => result?,
+        };
+        self.captured.extend(captured);
+        self.last_status = exit_code(status);
+        Ok(self.last_status)
+    }
+
+    /// Output captured since the last call
+    pub fn take_captured(&mut self) -> Vec<Captured> {
+        std::mem::take(&mut self.captured)
+    }
+
+    /// Start a command in the background and return its PID
+    pub fn spawn(&mut self, program: &str, args: &[String]) -> Result<u32, JobErr> {
+        let child = self.command(program, args).spawn()?;
+        let pid = child.id();
+        let command = std::iter::once(program.to_string())
+            .chain(args.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.jobs.push(Job {
+            id: self.jobs.len() + 1,
+            pid,
+            command,
+            child: Some(child),
+            status: None,
+        });
+        self.last_pid = Some(pid);
+
+        Ok(pid)
+    }
+
+    /// Wait for the job with `pid`, or for every job when `pid` is `None`.
+    /// Returns the exit status of the (last) waited job
+    pub fn wait(&mut self, pid: Option<u32>) -> Result<i32, JobErr> {
+        let mut status = 0;
+        let mut found = false;
+        for job in self.jobs.iter_mut() {
+            if pid.is_some_and(|pid| pid != job.pid) {
+                continue;
+            }
+            found = true;
+            status = match job.status {
+                Some(status) => status,
+                None => JobTable::reap(job, &mut self.captured, self.deadline)?,
+            };
+        }
+
+        match pid {
+            Some(pid) if !found => Err(JobErr::NoSuchJob(pid)),
+            _ => {
+                self.last_status = status;
+                Ok(status)
+            }
+        }
+    }
+
+    /// The job table with up to date statuses
+    pub fn jobs(&mut self) -> Result<&[Job], JobErr> {
+        for job in self.jobs.iter_mut() {
+            let finished = match job.child.as_mut() {
+                Some(child) => child.try_wait()?.is_some(),
+                None => false,
+            };
+            if finished {
+                JobTable::reap(job, &mut self.captured, None)?;
+            }
+        }
+
+        Ok(&self.jobs)
+    }
+
+    /// Wait for `job` to finish and record its status (and output when captured)
+    fn reap(
+        job: &mut Job,
+        captured: &mut Vec<Captured>,
+        deadline: Option<Instant>,
+    ) -> Result<i32, JobErr> {
+        let Some(child) = job.child.take() else {
+            return Ok(job.status.unwrap_or_default());
+        };
+
+        let (status, output) = match wait_child(child, deadline) {
+            Err(JobErr::TimedOut(status)) => {
+                job.status = Some(status);
+                return Err(JobErr::TimedOut(status));
+            }
+            result => result?,
+        };
+        captured.extend(output);
+        let status = exit_code(status);
+        job.status = Some(status);
+
+        Ok(status)
+    }
+
+    /// Send `signal` (`ex: TERM`, `-9`, `KILL`) to `pid`
+    pub fn kill(&mut self, pid: u32, signal: &str) -> Result<(), JobErr> {
+        send_signal(pid, signal)
+    }
+}
+
+/// Wait for `child` and collect its output if it was piped. The child is killed once
+/// `deadline` passes
+fn wait_child(
+    mut child: Child,
+    deadline: Option<Instant>,
+) -> Result<(ExitStatus, Option<Captured>), JobErr> {
+    // Drain the pipes while waiting so a chatty child can't block on a full pipe
+    let stdout = child.stdout.take().map(read_pipe);
+    let stderr = child.stderr.take().map(read_pipe);
+
+    let status = match deadline {
+        None => child.wait()?,
+        Some(deadline) => loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                child.kill()?;
+                return Err(JobErr::TimedOut(exit_code(child.wait()?)));
+            }
+            thread::sleep(Duration::from_millis(5));
+        },
+    };
+
+    let captured = match (stdout, stderr) {
+        (Some(stdout), Some(stderr)) => Some(Captured {
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        }),
+        _ => None,
+    };
+
+    Ok((status, captured))
+}
+
+fn read_pipe<R: Read + Send + 'static>(mut pipe: R) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        buf
+    })
+}
+
+/// The exit code of a process, `128 + signal` if it was killed by a signal
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+
+    status.code().unwrap_or(1)
+}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: &str) -> Result<(), JobErr> {
+    let name = signal.trim_start_matches('-').trim_start_matches("SIG");
+    let signal = match name {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "KILL" => libc::SIGKILL,
+        "TERM" => libc::SIGTERM,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        num => num
+            .parse::<i32>()
+            .map_err(|_| JobErr::InvalidSignal(signal.into()))?,
+    };
+
+    // SAFETY: `kill` has no memory safety requirements
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_signal(_pid: u32, signal: &str) -> Result<(), JobErr> {
+    Err(JobErr::InvalidSignal(signal.into()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Captured, JobErr, JobTable, Run};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn parse_run_statment() {
+        let run = "run cargo build --release".parse::<Run>().unwrap();
+        assert_eq!(
+            run,
+            Run {
+                program: "cargo".into(),
+                args: vec!["build".into(), "--release".into()],
+                background: false
+            }
+        );
+
+        let run = "run ./deploy.sh \"$env\" &".parse::<Run>().unwrap();
+        assert_eq!(run.args, ["\"$env\""]);
+        assert!(run.background);
+        assert!(!Run::is_run("runner"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_and_wait_for_jobs() {
+        let mut jobs = JobTable::default();
+        assert_eq!(jobs.run("true", &[]).unwrap(), 0);
+        assert_eq!(jobs.run("sh", &["-c".into(), "exit 3".into()]).unwrap(), 3);
+
+        let ok = jobs.spawn("true", &[]).unwrap();
+        let failed = jobs.spawn("sh", &["-c".into(), "exit 2".into()]).unwrap();
+        assert_eq!(jobs.last_pid, Some(failed));
+
+        assert_eq!(jobs.wait(Some(ok)).unwrap(), 0);
+        assert_eq!(jobs.wait(None).unwrap(), 2);
+        assert_eq!(jobs.last_status, 2);
+        assert_eq!(jobs.wait(Some(1)), Err(JobErr::NoSuchJob(1)));
+        assert_eq!(
+            jobs.jobs().unwrap()[1].to_string(),
+            format!("[2] {} Done(2)\tsh -c exit 2", failed)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kill_background_job() {
+        let mut jobs = JobTable::default();
+        let pid = jobs.spawn("sleep", &["5".into()]).unwrap();
+        assert!(jobs.jobs().unwrap()[0].to_string().contains("Running"));
+
+        jobs.kill(pid, "TERM").unwrap();
+        assert_eq!(jobs.wait(Some(pid)).unwrap(), 128 + libc::SIGTERM);
+        assert!(jobs.kill(pid, "-NOPE").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn capture_command_output() {
+        let mut jobs = JobTable {
+            capture: true,
+            ..Default::default()
+        };
+        jobs.run("sh", &["-c".into(), "echo out; echo err >&2".into()])
+            .unwrap();
+        let pid = jobs.spawn("echo", &["background".into()]).unwrap();
+        jobs.wait(Some(pid)).unwrap();
+
+        assert_eq!(
+            jobs.take_captured(),
+            vec![
+                Captured {
+                    stdout: b"out\n".to_vec(),
+                    stderr: b"err\n".to_vec()
+                },
+                Captured {
+                    stdout: b"background\n".to_vec(),
+                    stderr: vec![]
+                }
+            ]
+        );
+        assert!(jobs.take_captured().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kill_commands_at_the_deadline() {
+        let mut jobs = JobTable {
+            deadline: Some(Instant::now() + Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let err = jobs.run("sleep", &["5".into()]).err().unwrap();
+        assert_eq!(err, JobErr::TimedOut(128 + libc::SIGKILL));
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        let pid = jobs.spawn("sleep", &["5".into()]).unwrap();
+        assert!(jobs.wait(Some(pid)).is_err());
+        assert!(!jobs.jobs().unwrap()[0].is_running());
+    }
+}
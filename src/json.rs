@@ -0,0 +1,169 @@
+use crate::variables::VarValue;
+use serde_json::{error::Category, Map, Number, Value};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum JsonErr {
+    #[error("Invalid JSON at byte {offset}: {message}")]
+    InvalidJson { offset: usize, message: String },
+}
+
+/// Parse a JSON document into nested maps and lists
+///
+/// Example
+/// ```text
+/// {"name": "api", "port": 8080, "tags": ["a", "b"], "ratio": 0.5, "owner": null}
+/// =>  {name: api, owner: , port: 8080, ratio: 0.5, tags: [a, b]}
+/// ```
+/// Numbers that aren't an `int` are kept as written and `null` reads as an empty string,
+/// both are written back unchanged by `stringify`
+pub fn parse(s: &str) -> Result<VarValue, JsonErr> {
+    let value = serde_json::from_str::<Value>(s).map_err(|e| {
+        let message = e.to_string();
+        let message = match message.rsplit_once(" at line ") {
+            Some((message, _)) => message.to_string(),
+            None => message,
+        };
+        // At the end of the input the column points at the last byte
+        let offset = match e.classify() {
+            Category::Eof => s.len(),
+            _ => offset(s, e.line(), e.column()),
+        };
+        JsonErr::InvalidJson { offset, message }
+    })?;
+
+    Ok(from_json(value))
+}
+
+/// Write a value as JSON, `pretty` indents it with 2 spaces
+pub fn stringify(value: &VarValue, pretty: bool) -> String {
+    let value = to_json(value);
+    if pretty {
+        serde_json::to_string_pretty(&value).unwrap_or_default()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Byte offset of a 1-based line and column, the column counts bytes
+fn offset(s: &str, line: usize, column: usize) -> usize {
+    let line_start = s
+        .split_inclusive('\n')
+        .take(line.saturating_sub(1))
+        .map(str::len)
+        .sum::<usize>();
+    (line_start + column.saturating_sub(1)).min(s.len())
+}
+
+fn from_json(value: Value) -> VarValue {
+    match value {
+        Value::Null => VarValue::Null,
+        Value::Bool(b) => VarValue::Bool(b),
+        Value::Number(num) => match num.as_i64().and_then(|num| i32::try_from(num).ok()) {
+            Some(num) => VarValue::Int(num),
+            None => VarValue::Number(num.to_string()),
+        },
+        Value::String(s) => VarValue::Str(s),
+        Value::Array(items) => VarValue::List(items.into_iter().map(from_json).collect()),
+        Value::Object(fields) => VarValue::Map(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key, from_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+fn to_json(value: &VarValue) -> Value {
+    match value {
+        VarValue::Int(num) => Value::Number(Number::from(*num)),
+        VarValue::Str(s) => Value::String(s.clone()),
+        VarValue::Bool(b) => Value::Bool(*b),
+        VarValue::Null => Value::Null,
+        VarValue::Number(num) => num
+            .parse::<Number>()
+            .map_or_else(|_| Value::String(num.clone()), Value::Number),
+        VarValue::List(items) => Value::Array(items.iter().map(to_json).collect()),
+        VarValue::Map(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), to_json(value)))
+                .collect::<Map<_, _>>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, stringify, JsonErr};
+    use crate::variables::VarValue;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn parse_nested_values() {
+        let value = parse(
+            r#"{"servers": [{"host": "a.local", "port": 80}], "debug": true, "ratio": 0.5, "owner": null}"#,
+        )
+        .unwrap();
+
+        let server = BTreeMap::from([
+            ("host".to_string(), VarValue::Str("a.local".into())),
+            ("port".to_string(), VarValue::Int(80)),
+        ]);
+        assert_eq!(
+            value,
+            VarValue::Map(BTreeMap::from([
+                (
+                    "servers".to_string(),
+                    VarValue::List(vec![VarValue::Map(server)])
+                ),
+                ("debug".to_string(), VarValue::Bool(true)),
+                ("ratio".to_string(), VarValue::Number("0.5".into())),
+                ("owner".to_string(), VarValue::Null),
+            ]))
+        );
+        assert_eq!(
+            parse("9999999999").unwrap(),
+            VarValue::Number("9999999999".into())
+        );
+    }
+
+    #[test]
+    fn report_the_byte_offset_of_errors() {
+        let invalid = |offset: usize, message: &str| {
+            Err(JsonErr::InvalidJson {
+                offset,
+                message: message.into(),
+            })
+        };
+
+        assert_eq!(parse(r#"{"a": }"#), invalid(6, "expected value"));
+        assert_eq!(
+            parse("{\n  \"a\": 1,\n  \"b\" 2\n}"),
+            invalid(18, "expected `:`")
+        );
+        assert_eq!(parse("[1, 2"), invalid(5, "EOF while parsing a list"));
+        assert_eq!(parse("[1] x"), invalid(4, "trailing characters"));
+    }
+
+    #[test]
+    fn stringify_values() {
+        let value = parse(r#"{"name": "api", "tags": ["a", "b"], "port": 8080}"#).unwrap();
+
+        assert_eq!(
+            stringify(&value, false),
+            r#"{"name":"api","port":8080,"tags":["a","b"]}"#
+        );
+        assert_eq!(
+            stringify(&value, true),
+            "{\n  \"name\": \"api\",\n  \"port\": 8080,\n  \"tags\": [\n    \"a\",\n    \"b\"\n  ]\n}"
+        );
+        assert_eq!(parse(&stringify(&value, true)).unwrap(), value);
+        let value = r#"{"a":null,"big":9999999999,"exp":1e-7,"r":0.5}"#;
+        assert_eq!(stringify(&parse(value).unwrap(), false), value);
+        assert_eq!(
+            stringify(&VarValue::Str("say \"hi\"".into()), false),
+            r#""say \"hi\"""#
+        );
+    }
+}
@@ -1,16 +1,61 @@
+use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use thiserror::Error;
 
 use crate::{
+    call::{Call, CallErr},
     conditions::{Condition, ConditionErr},
     echo::{Echo, EchoErr},
+    function::Function,
+    jobs::{JobErr, Run},
+    match_case::{Match, Pattern},
+    readonly::{check_redeclarations, Readonly, ReadonlyKind},
+    scope::Scoped,
+    trace::SetTrace,
+    trap::{check_defers, Defer, Trap, TrapEvent},
+    try_catch::{Throw, TryCatch},
     utils::remove_comments,
-    variables::{VarErr, Variable},
+    variables::{CallVariable, VarErr, Variable},
 };
 
-#[derive(Debug)]
+/// A source line (1-based line number, text without comments)
+pub type SourceLine = (usize, String);
+
+/// A sequence of expressions, `lines[i]` is the source line of `experssions[i]`
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
 pub struct LangParser {
     pub experssions: Vec<Expression>,
+    pub lines: Vec<usize>,
+}
+
+/// Lines that open a block terminated by `end`
+pub fn is_block_start(s: &str) -> bool {
+    TryCatch::is_try(s)
+        || Trap::is_trap(s)
+        || Defer::is_defer(s)
+        || Function::is_function(s)
+        || Match::is_match(s)
+}
+
+pub fn is_block_end(s: &str) -> bool {
+    s.trim() == "end"
+}
+
+/// Index of the `end` closing the block opened at `lines[start_idx]`, nested blocks are skipped
+pub fn find_block_end(lines: &[SourceLine], start_idx: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, (_, line)) in lines.iter().enumerate().skip(start_idx + 1) {
+        if is_block_start(line) {
+            depth += 1;
+        } else if is_block_end(line) {
+            if depth == 0 {
+                return Some(idx);
+            }
+            depth -= 1;
+        }
+    }
+
+    None
 }
 
 #[derive(Debug, Error, PartialEq, Eq)]
@@ -19,35 +64,90 @@ pub enum ParseErr {
     VarErr(#[from] VarErr),
     #[error("Echo Error: `{0}`")]
     EchoErr(#[from] EchoErr),
+    #[error("Call Error: `{0}`")]
+    CallErr(#[from] CallErr),
+    #[error("Run Error: `{0}`")]
+    JobErr(#[from] JobErr),
     #[error("Condtion Error: `{0}`")]
     CondtionErr(#[from] Box<ConditionErr>),
     #[error("Invlaid experssion: {0}")]
     InvalidExperssion(String),
+    #[error("Unterminated block: {0}")]
+    UnterminatedBlock(String),
+    #[error("Redeclared variable: {0}")]
+    Redeclared(String),
 }
 
 impl FromStr for LangParser {
     type Err = ParseErr;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let lines = s
-            .trim()
             .lines()
-            .map(|l| remove_comments(l))
-            .filter(|l| !l.is_empty())
+            .enumerate()
+            .map(|(idx, l)| (idx + 1, remove_comments(l)))
+            .filter(|(_, l)| !l.is_empty())
             .collect::<Vec<_>>();
+
+        let ast = LangParser::from_lines(&lines)?;
+        check_redeclarations(&ast)?;
+        check_defers(&ast)?;
+
+        Ok(ast)
+    }
+}
+
+impl LangParser {
+    pub fn from_lines(lines: &[SourceLine]) -> Result<Self, ParseErr> {
+        let texts = lines.iter().map(|(_, l)| l.clone()).collect::<Vec<_>>();
         let mut idx = 0;
         let mut experssions = Vec::new();
+        let mut line_numbers = Vec::new();
         while idx < lines.len() {
-            let line = &lines[idx];
-            if Variable::is_var(line) {
+            let (line_number, line) = &lines[idx];
+            line_numbers.push(*line_number);
+            if TryCatch::is_try(line) {
+                let (expr, curr_idx) = TryCatch::from_lines(lines, idx)?;
+                idx = curr_idx;
+                experssions.push(Expression::TryCatch(Box::new(expr)));
+            } else if Trap::is_trap(line) {
+                let (expr, curr_idx) = Trap::from_lines(lines, idx)?;
+                idx = curr_idx;
+                experssions.push(Expression::Trap(Box::new(expr)));
+            } else if Defer::is_defer(line) {
+                let (expr, curr_idx) = Defer::from_lines(lines, idx)?;
+                idx = curr_idx;
+                experssions.push(Expression::Defer(Box::new(expr)));
+            } else if Function::is_function(line) {
+                let (expr, curr_idx) = Function::from_lines(lines, idx)?;
+                idx = curr_idx;
+                experssions.push(Expression::Function(Box::new(expr)));
+            } else if Match::is_match(line) {
+                let (expr, curr_idx) = Match::from_lines(lines, idx)?;
+                idx = curr_idx;
+                experssions.push(Expression::Match(Box::new(expr)));
+            } else if Readonly::is_readonly(line) {
+                experssions.push(Expression::Readonly(line.parse::<Readonly>()?))
+            } else if Scoped::is_scoped(line) {
+                experssions.push(Expression::Scoped(line.parse::<Scoped>()?))
+            } else if SetTrace::is_set_trace(line) {
+                experssions.push(Expression::SetTrace(line.parse::<SetTrace>()?))
+            } else if Run::is_run(line) {
+                experssions.push(Expression::Run(line.parse::<Run>()?))
+            } else if Throw::is_throw(line) {
+                experssions.push(Expression::Throw(line.parse::<Throw>()?))
+            } else if CallVariable::is_call_var(line) {
+                experssions.push(Expression::CallVar(line.parse::<CallVariable>()?))
+            } else if Call::is_call(line) {
+                experssions.push(Expression::Call(line.parse::<Call>()?))
+            } else if Variable::is_var(line) {
                 experssions.push(Expression::Var(line.parse::<Variable>()?))
             } else if Echo::is_echo(line) {
                 experssions.push(Expression::Echo(line.parse::<Echo>()?))
             } else if Condition::is_if_statment(line) {
-                let (expr, curr_idx) =
-                    Condition::from_lines(&lines, idx).map_err(|e| Box::new(e))?;
+                let (expr, curr_idx) = Condition::from_lines(&texts, idx).map_err(Box::new)?;
                 idx = curr_idx;
                 experssions.push(Expression::Condition(Box::new(
-                    expr.parse::<Condition>().map_err(|e| Box::new(e))?,
+                    expr.parse::<Condition>().map_err(Box::new)?,
                 )));
             } else {
                 return Err(ParseErr::InvalidExperssion(line.into()));
@@ -56,15 +156,91 @@ impl FromStr for LangParser {
             idx += 1;
         }
 
-        Ok(Self { experssions })
+        Ok(Self {
+            experssions,
+            lines: line_numbers,
+        })
+    }
+
+    /// Every declaration in the program, nested blocks and function bodies included, with
+    /// its line and whether it's a `const` or `readonly` one. `catch` names, `match` bindings
+    /// and the `err` of `trap ERR` count as declarations
+    pub fn declarations(&self) -> Vec<(String, usize, Option<ReadonlyKind>)> {
+        let mut decls = Vec::new();
+        collect_decls(self, &mut decls);
+        decls
+    }
+}
+
+fn collect_decls(block: &LangParser, decls: &mut Vec<(String, usize, Option<ReadonlyKind>)>) {
+    for (expr, line) in block.experssions.iter().zip(&block.lines) {
+        collect_expr_decls(expr, *line, decls);
+    }
+}
+
+fn collect_expr_decls(
+    expr: &Expression,
+    line: usize,
+    decls: &mut Vec<(String, usize, Option<ReadonlyKind>)>,
+) {
+    match expr {
+        Expression::Var(Variable { name, .. }) | Expression::CallVar(CallVariable { name, .. }) => {
+            decls.push((name.clone(), line, None))
+        }
+        Expression::Readonly(readonly) => {
+            decls.push((readonly.name().to_string(), line, Some(readonly.kind)))
+        }
+        Expression::Scoped(scoped) => collect_expr_decls(&scoped.decl, line, decls),
+        Expression::Condition(con) => {
+            collect_expr_decls(&con.if_expr, line, decls);
+            if let Some(else_expr) = &con.else_expr {
+                collect_expr_decls(else_expr, line, decls);
+            }
+        }
+        Expression::TryCatch(try_catch) => {
+            collect_decls(&try_catch.body, decls);
+            decls.push((try_catch.err_name.clone(), line, None));
+            collect_decls(&try_catch.catch_body, decls);
+        }
+        Expression::Trap(trap) => {
+            if trap.events.contains(&TrapEvent::Err) {
+                decls.push(("err".into(), line, None));
+            }
+            collect_decls(&trap.body, decls);
+        }
+        Expression::Defer(defer) => collect_decls(&defer.body, decls),
+        Expression::Function(function) => collect_decls(&function.body, decls),
+        Expression::Match(block) => {
+            for case in &block.cases {
+                for pattern in &case.patterns {
+                    if let Pattern::Bind(name) = pattern {
+                        decls.push((name.clone(), case.line, None));
+                    }
+                }
+                collect_decls(&case.body, decls);
+            }
+        }
+        _ => {}
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum Expression {
     Var(Variable),
     Echo(Echo),
     Condition(Box<Condition>),
+    Call(Call),
+    CallVar(CallVariable),
+    TryCatch(Box<TryCatch>),
+    Trap(Box<Trap>),
+    Defer(Box<Defer>),
+    Throw(Throw),
+    Run(Run),
+    SetTrace(SetTrace),
+    Function(Box<Function>),
+    Match(Box<Match>),
+    Readonly(Readonly),
+    Scoped(Scoped),
 }
 
 #[cfg(test)]
@@ -83,7 +259,7 @@ mod test {
     fn declare_var_and_echo_it() {
         let expr = "name: string = 'Jone'\necho name";
         let result = expr.parse::<LangParser>().unwrap();
-        let LangParser { experssions } = result;
+        let LangParser { experssions, .. } = result;
 
         assert_eq!(experssions.len(), 2);
         assert_eq!(
@@ -97,7 +273,7 @@ mod test {
     fn parse_if_statment() {
         let expr = include_str!("../lang/script_1.mb");
         let result = expr.parse::<LangParser>().unwrap();
-        let LangParser { experssions } = result;
+        let LangParser { experssions, .. } = result;
 
         assert_eq!(experssions.len(), 3);
         assert_eq!(
@@ -0,0 +1,62 @@
+//! A very minimalistic programming language built with Rust
+//!
+//! Example
+//! ```
+//! use mybash::{Interpreter, OutputBuffer};
+//!
+//! let out = OutputBuffer::default();
+//! let mut interpreter = Interpreter::new();
+//! interpreter.set_args(["bar"]);
+//! interpreter.set_stdout(out.clone());
+//! interpreter
+//!     .eval_str("if $1 == \"bar\"\ndo echo \"I got bar\"\nendif")
+//!     .unwrap();
+//! assert_eq!(out.contents(), "I got bar\n");
+//! ```
+mod arg;
+mod bytecode;
+mod call;
+mod cmp;
+pub mod compiled;
+mod conditions;
+pub mod dap;
+pub mod debugger;
+mod echo;
+mod eval;
+mod executor;
+mod filesystem;
+pub mod formatter;
+mod function;
+mod glob;
+mod host;
+mod interpreter;
+mod jobs;
+mod json;
+mod lang_parser;
+mod limits;
+pub mod lint;
+pub mod lsp;
+mod match_case;
+mod readonly;
+mod regex;
+mod sandbox;
+mod scope;
+mod streams;
+mod template;
+pub mod testing;
+mod trace;
+mod trap;
+mod try_catch;
+mod utils;
+mod variables;
+mod vm;
+
+pub use bytecode::CompileErr;
+pub use executor::{ErrorKind, ExeError};
+pub use host::{FromVarValue, HostFn, HostReturn, IntoVarValue};
+pub use interpreter::{Interpreter, InterpreterErr};
+pub use lang_parser::{LangParser, ParseErr};
+pub use limits::Limits;
+pub use sandbox::{Capability, Sandbox};
+pub use streams::OutputBuffer;
+pub use variables::VarValue;
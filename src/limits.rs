@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+/// Resource limits enforced while a script runs, nothing is limited by default
+///
+/// Example
+/// ```
+/// use mybash::{Interpreter, Limits};
+/// use std::time::Duration;
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_limits(Limits {
+///     max_steps: Some(10_000),
+///     timeout: Some(Duration::from_secs(5)),
+///     ..Limits::default()
+/// });
+/// ```
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Limits {
+    /// Maximum number of statements executed per evaluation
+    pub max_steps: Option<u64>,
+    /// Wall-clock time per evaluation. Checked between statements, external commands still
+    /// running at the deadline are killed
+    pub timeout: Option<Duration>,
+    /// Maximum number of bytes held by variables (strings, lists and maps)
+    pub max_memory: Option<usize>,
+    /// Maximum number of bytes written to stdout and stderr per evaluation
+    pub max_output: Option<usize>,
+    /// Maximum nesting of blocks and function calls
+    pub max_depth: Option<usize>,
+}
@@ -0,0 +1,649 @@
+use crate::{
+    arg::{Arg, ArgKind},
+    call::Call,
+    cmp::CompareExpr,
+    conditions::Condition,
+    echo::Echo,
+    executor::parse_operand,
+    lang_parser::{Expression, LangParser, ParseErr},
+    match_case::{Match, Pattern},
+    template::{Segment, Template},
+    trap::{Trap, TrapEvent},
+    try_catch::{Throw, TryCatch},
+    utils::{comment, remove_comments},
+    variables::{split_path, CallVariable, DataType, VarValue, Variable},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+};
+
+/// The checks of the linter, each one can be disabled with a `# lint: allow(<rule>)` comment
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// A variable is declared but never read
+    UnusedVariable,
+    /// A variable is read before its first declaration, which fails at runtime
+    ReadBeforeDeclared,
+    /// A comparison between types that fails at runtime `ex: age > "old"`
+    TypeMismatch,
+    /// A comparison between constants, one of the branches never runs
+    ConstantCondition,
+    /// A declaration or a `catch` rebinds a variable to another type
+    ShadowedVariable,
+    /// A `match` without a `case _`, other values silently run nothing
+    MissingDefault,
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rule = match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::ReadBeforeDeclared => "read-before-declared",
+            Rule::TypeMismatch => "type-mismatch",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::ShadowedVariable => "shadowed-variable",
+            Rule::MissingDefault => "missing-default",
+        };
+        write!(f, "{}", rule)
+    }
+}
+
+/// A warning about the statement at `line` (1-based)
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Lint {
+    pub line: usize,
+    pub rule: Rule,
+    pub message: String,
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "warning[{}]: {}", self.rule, self.message)
+    }
+}
+
+/// Check a script for problems without running it, lints are sorted by line
+///
+/// Example
+/// ```text
+/// age: int = 30
+/// name: str = "Jone"      unused-variable       `name` is declared but never used
+/// if age > "old"          type-mismatch         can't compare int with str using `>`
+/// do echo age
+/// endif
+/// if 1 > 2                constant-condition    `1 > 2` is always false
+/// do echo "never"
+/// endif
+/// ```
+/// A lint is disabled by a `# lint: allow(<rule>, ...)` comment at the end of its line or
+/// alone on the line above
+pub fn lint(source: &str) -> Result<Vec<Lint>, ParseErr> {
+    let ast = source.parse::<LangParser>()?;
+    let mut linter = Linter::default();
+    linter.collect_decls(&ast);
+    linter.block(&ast);
+    linter.unused();
+
+    let lines = source.lines().collect::<Vec<_>>();
+    let mut lints = linter
+        .lints
+        .into_iter()
+        .filter(|lint| !is_allowed(&lines, lint))
+        .collect::<Vec<_>>();
+    lints.sort_by_key(|lint| lint.line);
+
+    Ok(lints)
+}
+
+/// A binding of a variable to a type
+#[derive(Debug, Clone)]
+struct Decl {
+    line: usize,
+    datatype: DataType,
+}
+
+/// An operand of a comparison with a value of its type, `is_const` if it's a literal
+struct Operand {
+    value: VarValue,
+    is_const: bool,
+}
+
+#[derive(Default)]
+struct Linter {
+    /// Line of the first declaration of every variable, `catch` names included
+    declared: HashMap<String, usize>,
+    /// Variables declared with `name: type = ...` in the order of their first declaration
+    vars: Vec<(String, usize)>,
+    /// The latest declaration of every variable up to the statement being checked
+    scope: HashMap<String, Decl>,
+    used: HashSet<String>,
+    /// Variables bound by a `case` to a value whose type is only known at runtime
+    untyped: HashSet<String>,
+    /// Trap and function bodies can run after every declaration
+    deferred: bool,
+    lints: Vec<Lint>,
+}
+
+impl Linter {
+    fn collect_decls(&mut self, block: &LangParser) {
+        for (expr, line) in block.experssions.iter().zip(&block.lines) {
+            self.collect_expr_decls(expr, *line);
+        }
+    }
+
+    fn collect_expr_decls(&mut self, expr: &Expression, line: usize) {
+        match expr {
+            Expression::Var(Variable { name, .. })
+            | Expression::CallVar(CallVariable { name, .. })
+                if !self.declared.contains_key(name) =>
+            {
+                self.declared.insert(name.clone(), line);
+                self.vars.push((name.clone(), line));
+            }
+            Expression::Condition(con) => {
+                self.collect_expr_decls(&con.if_expr, line);
+                if let Some(else_expr) = &con.else_expr {
+                    self.collect_expr_decls(else_expr, line);
+                }
+            }
+            Expression::TryCatch(try_catch) => {
+                self.collect_decls(&try_catch.body);
+                self.declared
+                    .entry(try_catch.err_name.clone())
+                    .or_insert(line);
+                self.collect_decls(&try_catch.catch_body);
+            }
+            Expression::Readonly(readonly) => self.collect_expr_decls(&readonly.decl, line),
+            Expression::Scoped(scoped) => self.collect_expr_decls(&scoped.decl, line),
+            Expression::Trap(trap) => self.collect_decls(&trap.body),
+            Expression::Defer(defer) => self.collect_decls(&defer.body),
+            Expression::Function(function) => self.collect_decls(&function.body),
+            Expression::Match(block) => {
+                for case in &block.cases {
+                    for pattern in &case.patterns {
+                        if let Pattern::Bind(name) = pattern {
+                            self.declared.entry(name.clone()).or_insert(case.line);
+                        }
+                    }
+                    self.collect_decls(&case.body);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn warn(&mut self, line: usize, rule: Rule, message: String) {
+        self.lints.push(Lint {
+            line,
+            rule,
+            message,
+        });
+    }
+
+    fn block(&mut self, block: &LangParser) {
+        for (expr, line) in block.experssions.iter().zip(&block.lines) {
+            self.expr(expr, *line);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expression, line: usize) {
+        match expr {
+            Expression::Var(Variable {
+                name,
+                value,
+                template,
+            }) => {
+                self.read_value(template.source(), line);
+                self.declare(name, value.datatype(), line);
+            }
+            Expression::CallVar(CallVariable {
+                name,
+                datatype,
+                call,
+            }) => {
+                self.read_call(call, line);
+                self.declare(name, datatype.clone(), line);
+            }
+            Expression::Echo(Echo(template)) | Expression::Throw(Throw(template)) => {
+                self.read_value(template.source(), line)
+            }
+            Expression::Call(call) => self.read_call(call, line),
+            Expression::Run(run) => {
+                self.read_arg(&run.program, line);
+                for arg in &run.args {
+                    self.read_arg(arg, line);
+                }
+            }
+            Expression::Condition(con) => self.condition(con, line),
+            Expression::TryCatch(try_catch) => {
+                let TryCatch {
+                    body,
+                    err_name,
+                    catch_body,
+                } = try_catch.as_ref();
+                self.block(body);
+                if let Some(decl) = self
+                    .scope
+                    .get(err_name)
+                    .filter(|decl| decl.datatype != DataType::Map)
+                {
+                    let message = format!(
+                        "`catch {}` overwrites the {} `{}` declared at line {}",
+                        err_name, decl.datatype, err_name, decl.line
+                    );
+                    self.warn(line, Rule::ShadowedVariable, message);
+                }
+                self.bind(err_name, DataType::Map, line);
+                self.block(catch_body);
+            }
+            Expression::Trap(trap) => {
+                let Trap { events, body } = trap.as_ref();
+                let scope = self.scope.clone();
+                let deferred = std::mem::replace(&mut self.deferred, true);
+                if events.contains(&TrapEvent::Err) {
+                    self.bind("err", DataType::Map, line);
+                }
+                self.block(body);
+                self.deferred = deferred;
+                self.scope = scope;
+            }
+            Expression::Defer(defer) => {
+                let scope = self.scope.clone();
+                let deferred = std::mem::replace(&mut self.deferred, true);
+                self.block(&defer.body);
+                self.deferred = deferred;
+                self.scope = scope;
+            }
+            Expression::Function(function) => {
+                let scope = self.scope.clone();
+                let deferred = std::mem::replace(&mut self.deferred, true);
+                self.block(&function.body);
+                self.deferred = deferred;
+                self.scope = scope;
+            }
+            Expression::Match(block) => self.match_block(block, line),
+            Expression::Readonly(readonly) => self.expr(&readonly.decl, line),
+            Expression::Scoped(scoped) => self.expr(&scoped.decl, line),
+            Expression::SetTrace(_) => {}
+        }
+    }
+
+    fn match_block(&mut self, block: &Match, line: usize) {
+        self.read_arg(&block.value, line);
+        let datatype = (!matches!(block.value.kind(), ArgKind::Call(_)))
+            .then(|| self.operand(block.value.source()))
+            .flatten()
+            .map(|operand| operand.value.datatype());
+
+        for case in &block.cases {
+            // Bindings only live in their case, the variables they shadow come back after it
+            let shadowed = case
+                .patterns
+                .iter()
+                .filter_map(|pattern| match pattern {
+                    Pattern::Bind(name) => Some((
+                        name.clone(),
+                        self.scope.get(name).cloned(),
+                        self.untyped.contains(name),
+                    )),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for pattern in &case.patterns {
+                match pattern {
+                    Pattern::Literal(template) => self.read_template(template, case.line),
+                    Pattern::Bind(name) => match &datatype {
+                        Some(datatype) => self.bind(name, datatype.clone(), case.line),
+                        None => {
+                            self.scope.remove(name);
+                            self.untyped.insert(name.clone());
+                        }
+                    },
+                    _ => {}
+                }
+            }
+            if let Some(guard) = &case.guard {
+                match guard.kind() {
+                    ArgKind::Compare(cmp, _) => {
+                        self.read_value(cmp.left.source(), case.line);
+                        self.read_value(cmp.right.source(), case.line);
+                    }
+                    _ => self.read_arg(guard, case.line),
+                }
+            }
+            self.block(&case.body);
+            for (name, decl, untyped) in shadowed {
+                match decl {
+                    Some(decl) => self.scope.insert(name.clone(), decl),
+                    None => self.scope.remove(&name),
+                };
+                if !untyped {
+                    self.untyped.remove(&name);
+                }
+            }
+        }
+
+        if !block.cases.iter().any(|case| case.is_default()) {
+            let message = format!(
+                "`match {}` has no default case, add `case _` to handle the other values",
+                block.value
+            );
+            self.warn(line, Rule::MissingDefault, message);
+        }
+    }
+
+    /// Bind `name` to `datatype`, warns if it was bound to another type
+    fn declare(&mut self, name: &str, datatype: DataType, line: usize) {
+        if let Some(decl) = self
+            .scope
+            .get(name)
+            .filter(|decl| decl.datatype != datatype)
+        {
+            let message = format!(
+                "`{}` is redeclared as {}, shadowing the {} declared at line {}",
+                name, datatype, decl.datatype, decl.line
+            );
+            self.warn(line, Rule::ShadowedVariable, message);
+        }
+        self.bind(name, datatype, line);
+    }
+
+    fn bind(&mut self, name: &str, datatype: DataType, line: usize) {
+        self.untyped.remove(name);
+        self.scope.insert(name.to_string(), Decl { line, datatype });
+    }
+
+    /// Record a read of the variable `name`, map fields `ex: err.kind` read their map
+    fn read(&mut self, name: &str, line: usize) {
+        let name = split_path(name).map_or(name, |(base, _)| base);
+        self.used.insert(name.to_string());
+        if self.deferred || self.untyped.contains(name) {
+            return;
+        }
+        if let Some(decl_line) = self.declared.get(name).copied() {
+            if line <= decl_line && !self.scope.contains_key(name) {
+                let message = format!(
+                    "`{}` is read before its declaration at line {}",
+                    name, decl_line
+                );
+                self.warn(line, Rule::ReadBeforeDeclared, message);
+            }
+        }
+    }
+
+    fn read_template(&mut self, template: &Template, line: usize) {
+        for segment in template.segments() {
+            if let Segment::Var(name) = segment {
+                self.read(name, line);
+            }
+        }
+    }
+
+    /// A value that is expanded and then looked up as a whole `ex: echo name`
+    fn read_value(&mut self, s: &str, line: usize) {
+        let template = Template::parse(s.trim());
+        if template.has_vars() {
+            self.read_template(&template, line);
+        } else if !s.trim().is_empty() {
+            self.read(s.trim(), line);
+        }
+    }
+
+    /// A call argument, quoted arguments are only expanded
+    fn read_arg(&mut self, arg: &Arg, line: usize) {
+        match arg.kind() {
+            ArgKind::Quoted(template) => self.read_template(template, line),
+            ArgKind::Call(call) => self.read_call(call, line),
+            ArgKind::Compare(..) | ArgKind::Operand(_) => self.read_value(arg.source(), line),
+        }
+    }
+
+    fn read_call(&mut self, call: &Call, line: usize) {
+        for arg in &call.args {
+            self.read_arg(arg, line);
+        }
+    }
+
+    fn condition(&mut self, con: &Condition, line: usize) {
+        let CompareExpr {
+            left,
+            right,
+            operator,
+        } = &con.condition;
+        self.read_value(left.source(), line);
+        self.read_value(right.source(), line);
+
+        if !operator.is_file_test() {
+            if let (Some(left_op), Some(right_op)) =
+                (self.operand(left.source()), self.operand(right.source()))
+            {
+                let text = format!("{} {} {}", left, operator, right);
+                match CompareExpr::cmp(&left_op.value, &right_op.value, operator) {
+                    Err(_) => {
+                        let message = format!(
+                            "`{}` can't compare {} with {} using `{}`",
+                            text,
+                            left_op.value.datatype(),
+                            right_op.value.datatype(),
+                            operator
+                        );
+                        self.warn(line, Rule::TypeMismatch, message);
+                    }
+                    Ok(is_true) if left_op.is_const && right_op.is_const => {
+                        let message = match (is_true, &con.else_expr) {
+                            (true, Some(_)) => {
+                                format!("`{}` is always true, the `else` branch never runs", text)
+                            }
+                            (true, None) => format!("`{}` is always true", text),
+                            (false, _) => {
+                                format!("`{}` is always false, the `do` branch never runs", text)
+                            }
+                        };
+                        self.warn(line, Rule::ConstantCondition, message);
+                    }
+                    Ok(_) => {}
+                }
+            }
+        }
+
+        self.expr(&con.if_expr, line);
+        if let Some(else_expr) = &con.else_expr {
+            self.expr(else_expr, line);
+        }
+    }
+
+    /// A value of the operand's type, `None` if the type is only known at runtime
+    fn operand(&self, s: &str) -> Option<Operand> {
+        let s = s.trim();
+        let template = Template::parse(s);
+        let name = match template.single_var() {
+            Some(name) => name,
+            None if template.has_vars() => return None,
+            None => s,
+        };
+        if self.untyped.contains(name) {
+            return None;
+        }
+        if let Some(decl) = self.scope.get(name) {
+            return Some(Operand {
+                value: placeholder(&decl.datatype),
+                is_const: false,
+            });
+        }
+        let base = split_path(name).map_or(name, |(base, _)| base);
+        if self.scope.contains_key(base) || self.declared.contains_key(base) {
+            return None;
+        }
+
+        let value = match name {
+            _ if !template.has_vars() => {
+                return Some(Operand {
+                    value: parse_operand(s.to_string()),
+                    is_const: true,
+                })
+            }
+            "?" => VarValue::Int(0),
+            "!" => return None,
+            // Positional arguments and environment variables
+            _ => VarValue::Str(String::new()),
+        };
+
+        Some(Operand {
+            value,
+            is_const: false,
+        })
+    }
+
+    fn unused(&mut self) {
+        for (name, line) in std::mem::take(&mut self.vars) {
+            if !self.used.contains(&name) {
+                let message = format!("`{}` is declared but never used", name);
+                self.warn(line, Rule::UnusedVariable, message);
+            }
+        }
+    }
+}
+
+/// A value of `datatype`, the comparison rules only depend on the types
+fn placeholder(datatype: &DataType) -> VarValue {
+    match datatype {
+        DataType::Int => VarValue::Int(0),
+        DataType::Str => VarValue::Str(String::new()),
+        DataType::Bool => VarValue::Bool(false),
+        DataType::List => VarValue::List(Vec::new()),
+        DataType::Map => VarValue::Map(BTreeMap::new()),
+    }
+}
+
+/// `lint` is disabled by a comment at the end of its line or alone on the line above
+fn is_allowed(lines: &[&str], lint: &Lint) -> bool {
+    let allows = |line: usize| {
+        comment(lines[line - 1]).is_some_and(|comment| allows_rule(&comment, lint.rule))
+    };
+    allows(lint.line)
+        || lint.line > 1
+            && remove_comments(lines[lint.line - 2]).is_empty()
+            && allows(lint.line - 1)
+}
+
+/// `lint: allow(unused-variable, shadowed-variable)`
+fn allows_rule(comment: &str, rule: Rule) -> bool {
+    let rules = comment
+        .trim()
+        .strip_prefix("lint:")
+        .and_then(|s| s.trim().strip_prefix("allow("))
+        .and_then(|s| s.strip_suffix(')'));
+    rules.is_some_and(|rules| rules.split(',').any(|name| name.trim() == rule.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lint, Rule};
+
+    fn rules(source: &str) -> Vec<(usize, Rule)> {
+        lint(source)
+            .unwrap()
+            .into_iter()
+            .map(|lint| (lint.line, lint.rule))
+            .collect()
+    }
+
+    #[test]
+    fn report_unused_and_early_reads() {
+        let source = "echo \"Hello, $name\"\nname: str = \"Jone\"\nage: int = 30\ntry\nthrow 'x'\ncatch e\necho e.message\nend";
+        assert_eq!(
+            rules(source),
+            vec![(1, Rule::ReadBeforeDeclared), (3, Rule::UnusedVariable)]
+        );
+
+        // Traps run after the script, `err` is bound by `trap ERR`
+        let source =
+            "trap EXIT\necho done\nend\ntrap ERR\necho err.message\nend\ndone: bool = true";
+        assert_eq!(rules(source), vec![]);
+    }
+
+    #[test]
+    fn report_type_mismatches_and_constant_conditions() {
+        let source = "age: int = 30\nif age > \"old\"\ndo echo age\nendif\nif 1 > 2\ndo echo 'never'\nelse\ndo echo 'always'\nendif\nif $1 >= 10\ndo echo 'big'\nendif\nif age == $1\ndo echo age\nendif";
+        let lints = lint(source).unwrap();
+        assert_eq!(
+            lints
+                .iter()
+                .map(|lint| (lint.line, lint.rule))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, Rule::TypeMismatch),
+                (5, Rule::ConstantCondition),
+                (10, Rule::TypeMismatch)
+            ]
+        );
+        assert_eq!(
+            lints[0].message,
+            "`age > old` can't compare int with str using `>`"
+        );
+        assert_eq!(
+            lints[1].message,
+            "`1 > 2` is always false, the `do` branch never runs"
+        );
+    }
+
+    #[test]
+    fn report_shadowed_names() {
+        let source = "count: int = 1\ncount: str = \"one\"\necho count\ntry\nthrow 'x'\ncatch count\necho count\nend";
+        assert_eq!(
+            rules(source),
+            vec![(2, Rule::ShadowedVariable), (4, Rule::ShadowedVariable)]
+        );
+    }
+
+    #[test]
+    fn report_matches_without_default() {
+        let source = "count: int = 3\nmatch count\ncase 1..2\necho 'few'\ncase n if n > limit\necho n\nend\nlimit: int = 10\nmatch read_file(\"mode\")\ncase mode if mode == 1\necho mode\ncase _\nend";
+        let lints = lint(source).unwrap();
+        assert_eq!(
+            lints
+                .iter()
+                .map(|lint| (lint.line, lint.rule))
+                .collect::<Vec<_>>(),
+            vec![(2, Rule::MissingDefault), (5, Rule::ReadBeforeDeclared)]
+        );
+        assert_eq!(
+            lints[0].message,
+            "`match count` has no default case, add `case _` to handle the other values"
+        );
+    }
+
+    #[test]
+    fn disable_lints_with_comments() {
+        let source = "# lint: allow(unused-variable)\nname: str = \"Jone\"\nage: int = 30 # lint: allow(shadowed-variable, unused-variable)\nemail: str = \"\" # lint: allow(shadowed-variable)";
+        assert_eq!(rules(source), vec![(4, Rule::UnusedVariable)]);
+    }
+
+    #[test]
+    fn lint_example_scripts() {
+        for entry in std::fs::read_dir("lang").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "mb") {
+                continue;
+            }
+            let source = std::fs::read_to_string(&path).unwrap();
+            let lints = lint(&source).unwrap();
+            let expected = match path.file_name().unwrap().to_str().unwrap() {
+                "script_11.mb" => vec![(11, Rule::ConstantCondition)],
+                _ => vec![],
+            };
+            assert_eq!(
+                lints
+                    .iter()
+                    .map(|lint| (lint.line, lint.rule))
+                    .collect::<Vec<_>>(),
+                expected,
+                "{}",
+                path.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,802 @@
+use crate::{
+    conditions::Condition,
+    function::Function,
+    lang_parser::{
+        find_block_end, is_block_end, is_block_start, Expression, LangParser, SourceLine,
+    },
+    lint::lint,
+    match_case::{Case, Match},
+    regex::{cached_regex, RE_FN},
+    trap::{Defer, Trap},
+    try_catch::TryCatch,
+    utils::remove_comments,
+    variables::{CallVariable, DataType, Variable},
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+/// Built-in functions with their signature
+const BUILTINS: &[(&str, &str)] = &[
+    ("input", "input(prompt?) -> str"),
+    ("read_file", "read_file(path) -> str"),
+    ("write_file", "write_file(path, content) -> int"),
+    ("append_file", "append_file(path, content) -> int"),
+    ("ls", "ls(dir?) -> list"),
+    ("mkdir", "mkdir [-p] dir"),
+    ("rm", "rm [-r] path"),
+    ("cp", "cp from to"),
+    ("mv", "mv from to"),
+    ("basename", "basename(path) -> str"),
+    ("dirname", "dirname(path) -> str"),
+    ("json_parse", "json_parse(str) -> map"),
+    ("json_stringify", "json_stringify(value, pretty?) -> str"),
+    ("wait", "wait(pid?) -> int"),
+    ("jobs", "jobs -> list"),
+    ("kill", "kill [-SIGNAL] pid"),
+    ("assert", "assert(condition, message?)"),
+    ("assert_eq", "assert_eq(left, right, message?)"),
+    (
+        "assert_contains",
+        "assert_contains(haystack, needle, message?)",
+    ),
+];
+
+const KEYWORDS: &[&str] = &[
+    "if", "do", "else", "endif", "echo", "try", "catch", "end", "trap", "throw", "run", "set",
+    "fn", "defer", "match", "case", "const", "readonly", "let", "global",
+];
+
+// LSP constants
+const SYNC_FULL: u8 = 1;
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+const COMPLETION_FUNCTION: u8 = 3;
+const COMPLETION_VARIABLE: u8 = 6;
+const COMPLETION_KEYWORD: u8 = 14;
+const SYMBOL_FUNCTION: u8 = 12;
+const SYMBOL_VARIABLE: u8 = 13;
+const SYMBOL_EVENT: u8 = 24;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// Serve the language server protocol over `input` and `output` until the client sends `exit`
+///
+/// Example
+/// ```text
+/// Content-Length: 52\r\n
+/// \r\n
+/// {"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}
+/// ```
+/// Documents are synced in full. The server publishes parse errors and lints as
+/// diagnostics and answers hover, go-to-definition, completion and document symbol
+/// requests for variables, functions and built-ins
+pub fn serve<R: BufRead, W: Write>(input: R, output: W) -> io::Result<()> {
+    let mut server = Server {
+        input,
+        output,
+        docs: HashMap::new(),
+    };
+    while let Some(msg) = server.read()? {
+        let method = msg["method"].as_str().unwrap_or_default();
+        let params = &msg["params"];
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": SYNC_FULL,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": {},
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "mybash" },
+            }),
+            "textDocument/didOpen" => {
+                let doc = &params["textDocument"];
+                server.open(uri(params), doc["text"].as_str().unwrap_or_default())?;
+                continue;
+            }
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                if let Some(change) = changes.and_then(|changes| changes.last()) {
+                    server.open(uri(params), change["text"].as_str().unwrap_or_default())?;
+                }
+                continue;
+            }
+            "textDocument/didClose" => {
+                server.docs.remove(uri(params));
+                server.notify(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri(params), "diagnostics": [] }),
+                )?;
+                continue;
+            }
+            "textDocument/hover" => server.query(params, |doc| doc.hover(position(params))),
+            "textDocument/definition" => {
+                server.query(params, |doc| doc.definition(uri(params), position(params)))
+            }
+            "textDocument/completion" => {
+                server.query(params, |doc| doc.completion(position(params)))
+            }
+            "textDocument/documentSymbol" => server.query(params, Document::symbols),
+            "shutdown" => Value::Null,
+            "exit" => return Ok(()),
+            _ if msg.get("id").is_none() => continue,
+            _ => {
+                let error = json!({
+                    "code": METHOD_NOT_FOUND,
+                    "message": format!("Unknown method `{}`", method),
+                });
+                server.send(json!({ "jsonrpc": "2.0", "id": msg["id"], "error": error }))?;
+                continue;
+            }
+        };
+        server.send(json!({ "jsonrpc": "2.0", "id": msg["id"], "result": result }))?;
+    }
+
+    Ok(())
+}
+
+struct Server<R, W> {
+    input: R,
+    output: W,
+    docs: HashMap<String, Document>,
+}
+
+impl<R: BufRead, W: Write> Server<R, W> {
+    fn read(&mut self) -> io::Result<Option<Value>> {
+        read_message(&mut self.input)
+    }
+
+    fn send(&mut self, msg: Value) -> io::Result<()> {
+        write_message(&mut self.output, &msg)
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    /// Store the text of a document and publish its diagnostics. While the text doesn't
+    /// parse, the declarations of the last version that did are kept
+    fn open(&mut self, uri: &str, text: &str) -> io::Result<()> {
+        let mut doc = Document::new(text);
+        if let (Some(_), Some(last)) = (&doc.parse_err, self.docs.remove(uri)) {
+            doc.decls = last.decls;
+        }
+        let diagnostics = doc.diagnostics();
+        self.docs.insert(uri.to_string(), doc);
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    /// Answer a request about an open document, `null` for unknown documents
+    fn query(&self, params: &Value, f: impl FnOnce(&Document) -> Value) -> Value {
+        self.docs.get(uri(params)).map_or(Value::Null, f)
+    }
+}
+
+/// Read the next `Content-Length` framed JSON-RPC message, `None` at the end of the input.
+/// The debug adapter protocol uses the same framing
+pub(crate) fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                len = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let len = len.ok_or_else(|| invalid_data("missing Content-Length header"))?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| invalid_data(&e.to_string()))
+}
+
+pub(crate) fn write_message<W: Write>(output: &mut W, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn uri(params: &Value) -> &str {
+    params["textDocument"]["uri"].as_str().unwrap_or_default()
+}
+
+/// 0-based line and character of the request
+fn position(params: &Value) -> (usize, usize) {
+    let position = &params["position"];
+    let get = |key: &str| position[key].as_u64().unwrap_or_default() as usize;
+    (get("line"), get("character"))
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn range(line: usize, start: usize, end: usize) -> Value {
+    json!({
+        "start": { "line": line, "character": start },
+        "end": { "line": line, "character": end },
+    })
+}
+
+/// A variable declaration `name: type = ...` or a `catch` binding, lines and columns are
+/// 0-based
+struct Decl {
+    name: String,
+    line: usize,
+    col: usize,
+    datatype: DataType,
+}
+
+impl Decl {
+    fn range(&self) -> Value {
+        range(self.line, self.col, self.col + self.name.chars().count())
+    }
+}
+
+/// An open document, declarations are only collected when it parses
+struct Document {
+    lines: Vec<String>,
+    parse_err: Option<String>,
+    decls: Vec<Decl>,
+}
+
+impl Document {
+    fn new(text: &str) -> Self {
+        let lines = text.lines().map(String::from).collect::<Vec<_>>();
+        let mut doc = Self {
+            lines,
+            parse_err: None,
+            decls: Vec::new(),
+        };
+        match text.parse::<LangParser>() {
+            Ok(ast) => doc.decls = doc.declarations(&ast),
+            Err(e) => doc.parse_err = Some(e.to_string()),
+        }
+
+        doc
+    }
+
+    fn diagnostics(&self) -> Vec<Value> {
+        let text = self.lines.join("\n");
+        match &self.parse_err {
+            Some(message) => {
+                let line = error_line(&text) - 1;
+                let len = self.lines.get(line).map_or(0, |l| l.chars().count());
+                vec![json!({
+                    "range": range(line, 0, len),
+                    "severity": SEVERITY_ERROR,
+                    "source": "mybash",
+                    "message": message,
+                })]
+            }
+            None => lint(&text)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|lint| {
+                    let line = lint.line - 1;
+                    let len = self.lines.get(line).map_or(0, |l| l.chars().count());
+                    json!({
+                        "range": range(line, 0, len),
+                        "severity": SEVERITY_WARNING,
+                        "source": "mybash",
+                        "code": lint.rule.to_string(),
+                        "message": lint.message,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn declarations(&self, ast: &LangParser) -> Vec<Decl> {
+        let mut decls = Vec::new();
+        for (expr, line) in ast.experssions.iter().zip(&ast.lines) {
+            self.expr_declarations(expr, *line, &mut decls);
+        }
+
+        decls
+    }
+
+    fn expr_declarations(&self, expr: &Expression, line: usize, decls: &mut Vec<Decl>) {
+        match expr {
+            Expression::Var(Variable { name, value, .. }) => {
+                decls.push(self.decl_at(name, line, value.datatype()))
+            }
+            Expression::CallVar(CallVariable { name, datatype, .. }) => {
+                decls.push(self.decl_at(name, line, datatype.clone()))
+            }
+            Expression::Condition(con) => {
+                self.expr_declarations(&con.if_expr, line, decls);
+                if let Some(else_expr) = &con.else_expr {
+                    self.expr_declarations(else_expr, line, decls);
+                }
+            }
+            Expression::TryCatch(try_catch) => {
+                decls.extend(self.declarations(&try_catch.body));
+                let catch_line = self.catch_line(line);
+                decls.push(self.decl_at(&try_catch.err_name, catch_line, DataType::Map));
+                decls.extend(self.declarations(&try_catch.catch_body));
+            }
+            Expression::Trap(trap) => decls.extend(self.declarations(&trap.body)),
+            Expression::Defer(defer) => decls.extend(self.declarations(&defer.body)),
+            Expression::Readonly(readonly) => self.expr_declarations(&readonly.decl, line, decls),
+            Expression::Scoped(scoped) => self.expr_declarations(&scoped.decl, line, decls),
+            Expression::Function(function) => decls.extend(self.declarations(&function.body)),
+            Expression::Match(block) => {
+                for case in &block.cases {
+                    decls.extend(self.declarations(&case.body));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn decl_at(&self, name: &str, line: usize, datatype: DataType) -> Decl {
+        let (line, col) = self.locate(name, line);
+        Decl {
+            name: name.to_string(),
+            line,
+            col,
+            datatype,
+        }
+    }
+
+    /// 1-based line of the `catch` of the `try` at `try_line`
+    fn catch_line(&self, try_line: usize) -> usize {
+        let mut depth = 0;
+        for (idx, raw) in self.lines.iter().enumerate().skip(try_line) {
+            let code = remove_comments(raw);
+            if is_block_start(&code) {
+                depth += 1;
+            } else if is_block_end(&code) {
+                depth -= 1;
+            } else if depth == 0 && TryCatch::is_catch(&code) {
+                return idx + 1;
+            }
+        }
+
+        try_line
+    }
+
+    /// Position of `name` in the statement starting at the 1-based `line`. Statements inside
+    /// an `if` are on the following `do` lines
+    fn locate(&self, name: &str, line: usize) -> (usize, usize) {
+        for idx in line - 1..(line + 4).min(self.lines.len()) {
+            let code = remove_comments(&self.lines[idx]);
+            let offset = self.lines[idx].len() - self.lines[idx].trim_start().len();
+            if let Some(col) = find_word(&code, name) {
+                return (idx, self.lines[idx][..offset + col].chars().count());
+            }
+        }
+
+        (line - 1, 0)
+    }
+
+    /// The word under the cursor
+    fn word_at(&self, (line, character): (usize, usize)) -> Option<String> {
+        let chars = self.lines.get(line)?.chars().collect::<Vec<_>>();
+        let idx = character.min(chars.len());
+        let start = (0..idx)
+            .rev()
+            .take_while(|idx| is_word_char(chars[*idx]))
+            .last()
+            .unwrap_or(idx);
+        let end = (idx..chars.len())
+            .take_while(|idx| is_word_char(chars[*idx]))
+            .last()
+            .map_or(idx, |idx| idx + 1);
+
+        (start < end).then(|| chars[start..end].iter().collect())
+    }
+
+    /// The declaration of `name` visible at `line`, the first one if it's only declared later
+    fn decl(&self, name: &str, line: usize) -> Option<&Decl> {
+        let mut decls = self.decls.iter().filter(|decl| decl.name == name);
+        let first = decls.clone().next();
+        decls.rfind(|decl| decl.line <= line).or(first)
+    }
+
+    /// Position of the name in the `fn <name>` statement defining `name`
+    fn function(&self, name: &str) -> Option<(usize, usize)> {
+        self.lines.iter().enumerate().find_map(|(idx, raw)| {
+            let code = remove_comments(raw);
+            let caps = cached_regex!(RE_FN).captures(&code)?;
+            if &caps["name"] != name {
+                return None;
+            }
+            let offset = raw.len() - raw.trim_start().len();
+            let col = find_word(&code["fn".len()..], name)? + "fn".len();
+            Some((idx, raw[..offset + col].chars().count()))
+        })
+    }
+
+    fn hover(&self, position: (usize, usize)) -> Value {
+        let Some(word) = self.word_at(position) else {
+            return Value::Null;
+        };
+        let builtin = BUILTINS.iter().find(|(name, _)| *name == word);
+        let contents = match (self.decl(&word, position.0), builtin) {
+            (Some(decl), _) => format!(
+                "```mybash\n{}: {}\n```\nDeclared at line {}",
+                decl.name,
+                decl.datatype,
+                decl.line + 1
+            ),
+            (None, Some((_, signature))) => {
+                format!("```mybash\n{}\n```\nBuilt-in function", signature)
+            }
+            (None, None) => match self.function(&word) {
+                Some((line, _)) => format!(
+                    "```mybash\nfn {}\n```\nFunction defined at line {}",
+                    word,
+                    line + 1
+                ),
+                None => return Value::Null,
+            },
+        };
+
+        json!({ "contents": { "kind": "markdown", "value": contents } })
+    }
+
+    /// Variables jump to their declaration and functions to their `fn`, built-in functions
+    /// have no source to jump to
+    fn definition(&self, uri: &str, position: (usize, usize)) -> Value {
+        let Some(word) = self.word_at(position) else {
+            return Value::Null;
+        };
+        let range = match self.decl(&word, position.0) {
+            Some(decl) => decl.range(),
+            None if BUILTINS.iter().any(|(name, _)| *name == word) => return Value::Null,
+            None => match self.function(&word) {
+                Some((line, col)) => range(line, col, col + word.chars().count()),
+                None => return Value::Null,
+            },
+        };
+
+        json!({ "uri": uri, "range": range })
+    }
+
+    fn completion(&self, (line, _): (usize, usize)) -> Value {
+        let mut items = Vec::new();
+        let mut seen = Vec::new();
+        for decl in self.decls.iter().filter(|decl| decl.line < line) {
+            if seen.contains(&&decl.name) {
+                continue;
+            }
+            seen.push(&decl.name);
+            items.push(json!({
+                "label": decl.name,
+                "kind": COMPLETION_VARIABLE,
+                "detail": decl.datatype.to_string(),
+            }));
+        }
+        for (name, signature) in BUILTINS {
+            items.push(json!({ "label": name, "kind": COMPLETION_FUNCTION, "detail": signature }));
+        }
+        for keyword in KEYWORDS {
+            items.push(json!({ "label": keyword, "kind": COMPLETION_KEYWORD }));
+        }
+
+        Value::Array(items)
+    }
+
+    /// The first declaration of every variable, the functions and the traps
+    fn symbols(&self) -> Value {
+        let mut symbols = Vec::new();
+        let mut seen = Vec::new();
+        for decl in &self.decls {
+            if seen.contains(&&decl.name) {
+                continue;
+            }
+            seen.push(&decl.name);
+            symbols.push(json!({
+                "name": decl.name,
+                "detail": decl.datatype.to_string(),
+                "kind": SYMBOL_VARIABLE,
+                "range": decl.range(),
+                "selectionRange": decl.range(),
+            }));
+        }
+        for (idx, raw) in self.lines.iter().enumerate() {
+            let code = remove_comments(raw);
+            let kind = if Trap::is_trap(&code) {
+                SYMBOL_EVENT
+            } else if Function::is_function(&code) {
+                SYMBOL_FUNCTION
+            } else {
+                continue;
+            };
+            let start = raw.len() - raw.trim_start().len();
+            let range = range(idx, start, start + code.chars().count());
+            symbols.push(json!({
+                "name": code,
+                "kind": kind,
+                "range": range,
+                "selectionRange": range,
+            }));
+        }
+
+        Value::Array(symbols)
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Byte offset of `word` in `s` as a whole word
+fn find_word(s: &str, word: &str) -> Option<usize> {
+    s.match_indices(word).map(|(idx, _)| idx).find(|idx| {
+        let before = s[..*idx].chars().next_back();
+        let after = s[idx + word.len()..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    })
+}
+
+/// The 1-based line a parse error comes from, `ParseErr` doesn't carry it. Blocks are
+/// checked for their `catch` and `end` and every other statement is parsed on its own
+fn error_line(source: &str) -> usize {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(idx, l)| (idx + 1, remove_comments(l)))
+        .filter(|(_, l)| !l.is_empty())
+        .collect::<Vec<SourceLine>>();
+    let texts = lines.iter().map(|(_, l)| l.clone()).collect::<Vec<_>>();
+
+    let mut depth = 0;
+    let mut idx = 0;
+    while idx < lines.len() {
+        let (line, code) = &lines[idx];
+        if TryCatch::is_try(code) {
+            if TryCatch::from_lines(&lines, idx)
+                .is_err_and(|e| e.to_string().contains(&format!("at line {} ", line)))
+            {
+                return *line;
+            }
+            depth += 1;
+        } else if Match::is_match(code) {
+            if Match::from_lines(&lines, idx)
+                .is_err_and(|e| e.to_string().contains(&format!("at line {} ", line)))
+            {
+                return *line;
+            }
+            depth += 1;
+        } else if Match::is_case(code) {
+            if depth == 0 || Case::parse_header(code).is_err() {
+                return *line;
+            }
+        } else if Defer::is_defer(code) {
+            if find_block_end(&lines, idx).is_none() {
+                return *line;
+            }
+            depth += 1;
+        } else if Trap::is_trap(code) || Function::is_function(code) {
+            let header = format!("{}\nend", code).parse::<LangParser>();
+            if header.is_err() || find_block_end(&lines, idx).is_none() {
+                return *line;
+            }
+            depth += 1;
+        } else if Condition::is_if_statment(code) {
+            match Condition::from_lines(&texts, idx) {
+                Ok((expr, end_idx)) if expr.parse::<Condition>().is_ok() => idx = end_idx,
+                _ => return *line,
+            }
+        } else if is_block_end(code) {
+            if depth == 0 {
+                return *line;
+            }
+            depth -= 1;
+        } else if TryCatch::is_catch(code) {
+            if depth == 0 {
+                return *line;
+            }
+        } else if code.parse::<LangParser>().is_err() {
+            return *line;
+        }
+        idx += 1;
+    }
+
+    lines.first().map_or(1, |(line, _)| *line)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{error_line, serve};
+    use serde_json::{json, Value};
+    use std::io::Cursor;
+
+    /// Feed `requests` to the server like a client would and return its messages
+    fn session(requests: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for request in requests {
+            let body = request.to_string();
+            input.extend(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).bytes());
+        }
+        let mut output = Vec::new();
+        serve(Cursor::new(input), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        output
+            .split("Content-Length: ")
+            .skip(1)
+            .map(|msg| serde_json::from_str(msg.split_once("\r\n\r\n").unwrap().1).unwrap())
+            .collect()
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({
+            "textDocument": { "uri": "file:///deploy.mb" },
+            "position": { "line": line, "character": character },
+        })
+    }
+
+    #[test]
+    fn answer_requests_about_an_open_document() {
+        let text = "name: str = \"Jone\"\nage: int = 30\nif age > 20\ndo echo \"Hi $name\"\nendif\ntry\nthrow 'x'\ncatch e\necho e.message\nend\ntrap EXIT\necho age\nend";
+        let open = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///deploy.mb", "text": text } },
+        });
+        let symbols = json!({ "textDocument": { "uri": "file:///deploy.mb" } });
+        let messages = session(&[
+            request(1, "initialize", json!({})),
+            open,
+            request(2, "textDocument/hover", at(3, 13)),
+            request(3, "textDocument/definition", at(8, 5)),
+            request(4, "textDocument/completion", at(3, 0)),
+            request(5, "textDocument/documentSymbol", symbols),
+            request(6, "textDocument/hover", at(6, 3)),
+            request(7, "textDocument/formatting", json!({})),
+            request(8, "shutdown", Value::Null),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ]);
+
+        assert_eq!(messages[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(messages[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(messages[1]["params"]["diagnostics"], json!([]));
+        assert_eq!(
+            messages[2]["result"]["contents"]["value"],
+            "```mybash\nname: str\n```\nDeclared at line 1"
+        );
+        assert_eq!(
+            messages[3]["result"]["range"]["start"],
+            json!({ "line": 7, "character": 6 })
+        );
+
+        let labels = messages[4]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(labels[..3], ["name", "age", "input"]);
+        assert!(labels.contains(&"endif"));
+
+        let symbols = messages[5]["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|symbol| symbol["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(symbols, ["name", "age", "e", "trap EXIT"]);
+        assert_eq!(messages[6]["result"], Value::Null);
+        assert_eq!(messages[7]["error"]["code"], -32601);
+        assert_eq!(messages[8]["result"], Value::Null);
+        assert_eq!(messages.len(), 9);
+    }
+
+    #[test]
+    fn resolve_function_calls() {
+        let text = "fn greet\n  echo 'hi'\nend\ngreet()";
+        let open = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///deploy.mb", "text": text } },
+        });
+        let messages = session(&[
+            open,
+            request(1, "textDocument/definition", at(3, 2)),
+            request(2, "textDocument/hover", at(3, 0)),
+        ]);
+
+        assert_eq!(
+            messages[1]["result"]["range"],
+            json!({
+                "start": { "line": 0, "character": 3 },
+                "end": { "line": 0, "character": 8 },
+            })
+        );
+        assert_eq!(
+            messages[2]["result"]["contents"]["value"],
+            "```mybash\nfn greet\n```\nFunction defined at line 1"
+        );
+    }
+
+    #[test]
+    fn publish_parse_errors_and_lints() {
+        let change = |text: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": "file:///deploy.mb" },
+                    "contentChanges": [{ "text": text }],
+                },
+            })
+        };
+        let messages = session(&[
+            change("age: int = 30\necho age\nprint age"),
+            change("age: int = 30"),
+        ]);
+
+        let error = &messages[0]["params"]["diagnostics"][0];
+        assert_eq!(error["severity"], 1);
+        assert_eq!(error["range"]["start"]["line"], 2);
+        let warning = &messages[1]["params"]["diagnostics"][0];
+        assert_eq!(warning["severity"], 2);
+        assert_eq!(warning["code"], "unused-variable");
+    }
+
+    #[test]
+    fn keep_declarations_while_the_document_is_invalid() {
+        let change = |text: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": "file:///deploy.mb" },
+                    "contentChanges": [{ "text": text }],
+                },
+            })
+        };
+        let messages = session(&[
+            change("age: int = 30\necho age"),
+            change("age: int = 30\necho age\nif age >"),
+            request(1, "textDocument/hover", at(1, 6)),
+            request(2, "textDocument/definition", at(1, 6)),
+        ]);
+
+        assert_eq!(messages[1]["params"]["diagnostics"][0]["severity"], 1);
+        assert_eq!(
+            messages[2]["result"]["contents"]["value"],
+            "```mybash\nage: int\n```\nDeclared at line 1"
+        );
+        assert_eq!(
+            messages[3]["result"]["range"]["start"],
+            json!({ "line": 0, "character": 0 })
+        );
+    }
+
+    #[test]
+    fn locate_parse_errors() {
+        assert_eq!(error_line("echo a\n\ntry\necho b\nprint c\ncatch\nend"), 5);
+        assert_eq!(error_line("echo a\ntry\necho b\ncatch\n"), 2);
+        assert_eq!(error_line("trap NEVER\nend"), 1);
+        assert_eq!(error_line("if a > 1\ndo echo a\n"), 1);
+        assert_eq!(error_line("echo a\nend"), 2);
+        assert_eq!(error_line("match $1\ncase 'a'\necho a\ncase /(/\nend"), 4);
+        assert_eq!(error_line("match $1\necho a\nend"), 1);
+        assert_eq!(error_line("echo a\ncase _"), 2);
+    }
+}
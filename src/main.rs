@@ -1,47 +1,138 @@
-mod cmp;
-mod conditions;
-mod echo;
-mod eval;
-mod executor;
-mod lang_parser;
-mod regex;
-mod utils;
-mod variables;
-
-use executor::{ExeError, Executor};
-use lang_parser::{LangParser, ParseErr};
-use std::{env, fs, path::Path};
+mod cli;
+
+use cli::{Cli, CliErr, Command};
+use mybash::{
+    compiled::{self, CompiledScript},
+    dap,
+    debugger::{Console, Debugger},
+    formatter,
+    lint::{self, Lint},
+    lsp, testing, ExeError, Interpreter, InterpreterErr,
+};
+use serde::Serialize;
+use std::{env, fs, io, process};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 enum TopLevelErr {
-    #[error("Missing file path: `{0}`")]
-    MissingFilePath(String),
-    #[error("`{0}` not found")]
-    FileNotFound(String),
-    #[error("IO Error: `{0}`")]
-    IoError(#[from] std::io::Error),
-    #[error("Parse error: {0}")]
-    ParseErr(#[from] ParseErr),
-    #[error("Executor error: {0}")]
-    ExeError(#[from] ExeError),
+    #[error("{0}")]
+    CliErr(#[from] CliErr),
+    #[error("{0}")]
+    InterpreterErr(#[from] InterpreterErr),
 }
 
 fn main() -> Result<(), TopLevelErr> {
-    let file_path = env::args().nth(1).ok_or(TopLevelErr::MissingFilePath(
-        "example: mybash ./src/main.mb".into(),
-    ))?;
+    let cli = Cli::parse(env::args().skip(1))?;
+    match cli.command {
+        Command::Run => {}
+        Command::Compile { output } => {
+            CompiledScript::compile(&cli.file_path)?.write(output)?;
+            return Ok(());
+        }
+        Command::DumpAst => {
+            let ast = compiled::load(&cli.file_path)?;
+            let json = serde_json::to_string_pretty(&ast).expect("the AST is always serializable");
+            println!("{}", json);
+            return Ok(());
+        }
+        Command::Fmt { check, paths } => return fmt(&paths, check),
+        Command::Lint { json, paths } => return lint(&paths, json),
+        Command::Test { paths } => {
+            let files = testing::discover(&paths).map_err(InterpreterErr::from)?;
+            if !testing::run(&files, io::stdout().lock()).map_err(InterpreterErr::from)? {
+                process::exit(1);
+            }
+            return Ok(());
+        }
+        Command::Lsp => {
+            lsp::serve(io::stdin().lock(), io::stdout().lock()).map_err(InterpreterErr::from)?;
+            return Ok(());
+        }
+        Command::Debug { dap: true } => {
+            dap::serve(io::stdin().lock(), io::stdout().lock()).map_err(InterpreterErr::from)?;
+            return Ok(());
+        }
+        Command::Debug { dap: false } => {}
+    }
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_args(cli.args);
+    interpreter.set_limits(cli.limits);
+    interpreter.set_vm(cli.vm);
+    interpreter.set_trace(cli.trace);
+    if cli.command == (Command::Debug { dap: false }) {
+        let source = fs::read_to_string(&cli.file_path).map_err(InterpreterErr::from)?;
+        interpreter.set_debugger(Debugger::new(Console::stdio(&source)).stop_on_entry(true));
+    }
+    match interpreter.eval_file(&cli.file_path) {
+        Err(InterpreterErr::ExeError {
+            err: ExeError::Signal(event),
+            ..
+        }) => process::exit(event.exit_status()),
+        Err(InterpreterErr::ExeError {
+            err: ExeError::DebuggerQuit,
+            ..
+        }) => process::exit(1),
+        result => result?,
+    }
+
+    Ok(())
+}
+
+/// Format `paths` in place. With `check` nothing is written and the exit status is 1 if
+/// a script isn't formatted
+fn fmt(paths: &[String], check: bool) -> Result<(), TopLevelErr> {
+    let mut unformatted = 0;
+    for path in paths {
+        let source = fs::read_to_string(path).map_err(InterpreterErr::from)?;
+        let formatted = formatter::format(&source).map_err(InterpreterErr::from)?;
+        if formatted == source {
+            continue;
+        }
 
-    let path = Path::new(&file_path);
+        if check {
+            println!("{} is not formatted", path);
+            unformatted += 1;
+        } else {
+            fs::write(path, formatted).map_err(InterpreterErr::from)?;
+            println!("Formatted {}", path);
+        }
+    }
 
-    if !path.exists() {
-        return Err(TopLevelErr::FileNotFound(file_path));
+    if unformatted > 0 {
+        process::exit(1);
     }
+    Ok(())
+}
 
-    let content = fs::read_to_string(path)?;
-    let result = content.parse::<LangParser>()?;
-    let mut exe = Executor::new(&result.experssions);
-    exe.execute()?;
+#[derive(Serialize)]
+struct FileLint<'a> {
+    path: &'a str,
+    #[serde(flatten)]
+    lint: Lint,
+}
 
+/// Lint `paths`, as one JSON array with `json`. The exit status is 1 if there are lints
+fn lint(paths: &[String], json: bool) -> Result<(), TopLevelErr> {
+    let mut lints = Vec::new();
+    for path in paths {
+        let source = fs::read_to_string(path).map_err(InterpreterErr::from)?;
+        for lint in lint::lint(&source).map_err(InterpreterErr::from)? {
+            lints.push(FileLint { path, lint });
+        }
+    }
+
+    if json {
+        let json = serde_json::to_string_pretty(&lints).expect("lints are always serializable");
+        println!("{}", json);
+    } else {
+        for FileLint { path, lint } in &lints {
+            println!("{}:{}: {}", path, lint.line, lint);
+        }
+    }
+
+    if !lints.is_empty() {
+        process::exit(1);
+    }
     Ok(())
 }
@@ -0,0 +1,390 @@
+use crate::{
+    arg::Arg,
+    lang_parser::{find_block_end, is_block_end, is_block_start, LangParser, ParseErr, SourceLine},
+    regex::{cached_regex, RE_IDENT, RE_RANGE},
+    template::Template,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Display;
+
+/// A `match` block running the first `case` whose pattern matches the value
+///
+/// Example
+/// ```text
+/// match $1
+/// case "start"
+/// echo "Starting"
+/// case "stop" | "halt"
+/// echo "Stopping"
+/// case "*.conf" | /^v[0-9]+$/ | 1..10
+/// echo "A config file, a version or a small number"
+/// case arg if arg ~= "help"
+/// echo "Usage: service start|stop"
+/// case _
+/// throw "Unknown command: $1"
+/// end
+/// ```
+/// Nothing runs if no case matches
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Match {
+    /// The matched value as written `ex: $1`, `name`, `read_file("mode")`
+    pub value: Arg,
+    pub cases: Vec<Case>,
+}
+
+/// `case <pattern> | <pattern> ... [if <guard>]` and its body
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Case {
+    pub patterns: Vec<Pattern>,
+    /// A comparison evaluated after a pattern matched `ex: n > 10`
+    pub guard: Option<Arg>,
+    /// Source line of the `case`
+    pub line: usize,
+    pub body: LangParser,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum Pattern {
+    /// `"start"` or `42`, compared with `==` so strings holding `*`, `?` or `[...]` are globs
+    Literal(Template),
+    /// `/^v[0-9]+$/` matched against the value as a string
+    Regex(CaseRegex),
+    /// `1..10` an int, or a string holding one, within the bounds (both included)
+    Range(i32, i32),
+    /// `n` matches anything and binds the value to `n` in the case
+    Bind(String),
+    /// `_` matches anything
+    Wildcard,
+}
+
+impl Match {
+    pub fn is_match(s: &str) -> bool {
+        s.trim().starts_with("match ")
+    }
+
+    pub fn is_case(s: &str) -> bool {
+        let s = s.trim();
+        s == "case" || s.starts_with("case ")
+    }
+
+    /// Parse the block starting at `lines[start_idx]`, returns the block and the index of its `end`
+    pub fn from_lines(lines: &[SourceLine], start_idx: usize) -> Result<(Self, usize), ParseErr> {
+        let (match_line, header) = &lines[start_idx];
+        let value = header.trim()["match".len()..].trim();
+        let end_idx = find_block_end(lines, start_idx).ok_or_else(|| {
+            ParseErr::UnterminatedBlock(format!("`match` at line {} is missing `end`", match_line))
+        })?;
+
+        // Indexes of the `case` lines of this block, nested blocks are skipped
+        let mut case_idxs = Vec::new();
+        let mut depth = 0;
+        for (idx, (_, line)) in lines.iter().enumerate().take(end_idx).skip(start_idx + 1) {
+            if is_block_start(line) {
+                depth += 1;
+            } else if is_block_end(line) {
+                depth -= 1;
+            } else if depth == 0 && Match::is_case(line) {
+                case_idxs.push(idx);
+            }
+        }
+
+        if case_idxs.first() != Some(&(start_idx + 1)) {
+            return Err(ParseErr::InvalidExperssion(format!(
+                "`match` at line {} expects a `case` on the next line",
+                match_line
+            )));
+        }
+
+        let mut cases = Vec::new();
+        for (pos, case_idx) in case_idxs.iter().enumerate() {
+            let body_end = case_idxs.get(pos + 1).copied().unwrap_or(end_idx);
+            let (line, header) = &lines[*case_idx];
+            let (patterns, guard) = Case::parse_header(header)?;
+            cases.push(Case {
+                patterns,
+                guard,
+                line: *line,
+                body: LangParser::from_lines(&lines[case_idx + 1..body_end])?,
+            });
+        }
+
+        Ok((
+            Self {
+                value: value.into(),
+                cases,
+            },
+            end_idx,
+        ))
+    }
+}
+
+impl Case {
+    /// Parse `case <patterns> [if <guard>]`
+    pub fn parse_header(header: &str) -> Result<(Vec<Pattern>, Option<Arg>), ParseErr> {
+        // The trailing space lets a missing guard `ex: case n if` split too
+        let rest = format!("{} ", header.trim()["case".len()..].trim());
+        let (patterns, guard) = match split_outside_quotes(&rest, " if ").as_slice() {
+            [patterns] => (*patterns, None),
+            [patterns, guard] if !guard.trim().is_empty() => (*patterns, Some(guard.trim().into())),
+            _ => return Err(invalid_case(header)),
+        };
+
+        let patterns = split_outside_quotes(patterns, "|")
+            .into_iter()
+            .map(|pattern| Pattern::parse(pattern.trim(), header))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((patterns, guard))
+    }
+
+    /// Matches every value, `case _` or `case name` without a guard
+    pub fn is_default(&self) -> bool {
+        self.guard.is_none()
+            && self
+                .patterns
+                .iter()
+                .any(|pattern| matches!(pattern, Pattern::Wildcard | Pattern::Bind(_)))
+    }
+}
+
+impl Pattern {
+    /// Parse a pattern of the case `header`
+    fn parse(s: &str, header: &str) -> Result<Self, ParseErr> {
+        let is_quoted = s.len() >= 2
+            && (s.starts_with('"') && s.ends_with('"') || s.starts_with('\'') && s.ends_with('\''));
+
+        let pattern = if s.is_empty() || !is_quoted && s.contains(char::is_whitespace) {
+            return Err(invalid_case(header));
+        } else if s == "_" {
+            Self::Wildcard
+        } else if is_quoted {
+            Self::Literal(Template::parse(&s[1..s.len() - 1]))
+        } else if s.len() >= 2 && s.starts_with('/') && s.ends_with('/') {
+            let re = &s[1..s.len() - 1];
+            Self::Regex(CaseRegex::new(re).map_err(|e| {
+                ParseErr::InvalidExperssion(format!("`/{}/` is not a valid regex: {}", re, e))
+            })?)
+        } else if let Some(caps) = cached_regex!(RE_RANGE).captures(s) {
+            let bound = |name: &str| caps[name].parse().map_err(|_| invalid_case(header));
+            Self::Range(bound("start")?, bound("end")?)
+        } else if cached_regex!(RE_IDENT).is_match(s) && s != "true" && s != "false" {
+            Self::Bind(s.to_string())
+        } else {
+            Self::Literal(Template::parse(s))
+        };
+
+        Ok(pattern)
+    }
+}
+
+/// A `/regex/` pattern compiled when the script is parsed, compared and serialized as its
+/// source
+#[derive(Debug, Clone)]
+pub struct CaseRegex(pub ::regex::Regex);
+
+impl CaseRegex {
+    pub fn new(re: &str) -> Result<Self, ::regex::Error> {
+        ::regex::Regex::new(re).map(Self)
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        self.0.is_match(s)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for CaseRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for CaseRegex {}
+
+impl Serialize for CaseRegex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CaseRegex {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let re = String::deserialize(deserializer)?;
+        CaseRegex::new(&re).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Literal(template) if template.source().parse::<i32>().is_ok() => {
+                write!(f, "{}", template.source())
+            }
+            Pattern::Literal(template) => match template.source() {
+                "true" | "false" => write!(f, "{}", template.source()),
+                source if source.contains('"') => write!(f, "'{}'", source),
+                source => write!(f, "\"{}\"", source),
+            },
+            Pattern::Regex(re) => write!(f, "/{}/", re.as_str()),
+            Pattern::Range(start, end) => write!(f, "{}..{}", start, end),
+            Pattern::Bind(name) => write!(f, "{}", name),
+            Pattern::Wildcard => write!(f, "_"),
+        }
+    }
+}
+
+/// The header of the case `ex: case "stop" | "halt" if force == true`
+impl Display for Case {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect::<Vec<_>>();
+        write!(f, "case {}", patterns.join(" | "))?;
+        if let Some(guard) = &self.guard {
+            write!(f, " if {}", guard)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid_case(header: &str) -> ParseErr {
+    ParseErr::InvalidExperssion(format!(
+        "`{}` is not a valid case, expected `case <pattern> | <pattern> [if <condition>]`",
+        header.trim()
+    ))
+}
+
+/// Split `s` at every `sep` that isn't inside quotes or a `/regex/`
+fn split_outside_quotes<'a>(s: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (idx, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            // A `/` starts a regex only at the start of a pattern
+            (None, '/')
+                if s[start..idx].trim().is_empty() || s[..idx].trim_end().ends_with('|') =>
+            {
+                quote = Some('/')
+            }
+            (None, _) if s[idx..].starts_with(sep) => {
+                parts.push(&s[start..idx]);
+                start = idx + sep.len();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+
+    parts
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        echo::Echo,
+        lang_parser::{Expression, LangParser, ParseErr},
+    };
+
+    use super::{Case, CaseRegex, Match, Pattern};
+
+    #[test]
+    fn parse_match() {
+        let expr = "match $1\ncase \"start\" | 'stop'\necho 'go'\ncase /^v[0-9]+$/ | 1..10 | 42\ntry\necho 'nested'\ncatch\nend\ncase n if n > 10\ncase _\necho 'other'\nend";
+        let result = expr.parse::<LangParser>().unwrap();
+
+        assert_eq!(result.lines, vec![1]);
+        let Expression::Match(block) = &result.experssions[0] else {
+            panic!("expected a match block")
+        };
+        assert_eq!(block.value, "$1");
+        assert_eq!(
+            block.cases.iter().map(|case| case.line).collect::<Vec<_>>(),
+            vec![2, 4, 9, 10]
+        );
+        assert_eq!(
+            block.cases[0],
+            Case {
+                patterns: vec![
+                    Pattern::Literal("start".into()),
+                    Pattern::Literal("stop".into())
+                ],
+                guard: None,
+                line: 2,
+                body: LangParser {
+                    experssions: vec![Expression::Echo(Echo("go".into()))],
+                    lines: vec![3]
+                },
+            }
+        );
+        assert_eq!(
+            block.cases[1].patterns,
+            vec![
+                Pattern::Regex(CaseRegex::new("^v[0-9]+$").unwrap()),
+                Pattern::Range(1, 10),
+                Pattern::Literal("42".into())
+            ]
+        );
+        assert!(matches!(
+            block.cases[1].body.experssions[0],
+            Expression::TryCatch(_)
+        ));
+        assert_eq!(block.cases[2].patterns, vec![Pattern::Bind("n".into())]);
+        assert_eq!(block.cases[2].guard.as_ref().unwrap(), "n > 10");
+        assert!(block.cases[2].body.experssions.is_empty());
+        assert!(!block.cases[2].is_default() && block.cases[3].is_default());
+        assert_eq!(block.cases[1].to_string(), "case /^v[0-9]+$/ | 1..10 | 42");
+    }
+
+    #[test]
+    fn split_patterns_outside_quotes_and_regexes() {
+        let (patterns, guard) =
+            Case::parse_header("case 'a | b' | /x|y/ if n == \"c if d\"").unwrap();
+        assert_eq!(
+            patterns,
+            vec![
+                Pattern::Literal("a | b".into()),
+                Pattern::Regex(CaseRegex::new("x|y").unwrap())
+            ]
+        );
+        assert_eq!(guard.unwrap(), "n == \"c if d\"");
+    }
+
+    #[test]
+    fn parse_invalid_match() {
+        assert_eq!(
+            "match x\necho 'x'\ncase _\nend"
+                .parse::<LangParser>()
+                .err()
+                .unwrap(),
+            ParseErr::InvalidExperssion(
+                "`match` at line 1 expects a `case` on the next line".into()
+            )
+        );
+        assert_eq!(
+            "match x\ncase _".parse::<LangParser>().err().unwrap(),
+            ParseErr::UnterminatedBlock("`match` at line 1 is missing `end`".into())
+        );
+        for header in ["case", "case 'a' |", "case _ if", "case /(/"] {
+            let source = format!("match x\n{}\nend", header);
+            assert!(
+                matches!(
+                    source.parse::<LangParser>(),
+                    Err(ParseErr::InvalidExperssion(_))
+                ),
+                "`{}` should be invalid",
+                header
+            );
+        }
+        assert!(Match::is_case("case _") && !Match::is_case("cases"));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    lang_parser::{Expression, LangParser, ParseErr},
+    variables::{CallVariable, Variable},
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum ReadonlyKind {
+    /// `const`, the value is a literal
+    Const,
+    /// `readonly`, the value is computed when the declaration runs
+    Readonly,
+}
+
+impl Display for ReadonlyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadonlyKind::Const => write!(f, "const"),
+            ReadonlyKind::Readonly => write!(f, "readonly"),
+        }
+    }
+}
+
+/// A declaration that can't be reassigned
+///
+/// Example
+/// ```text
+/// const GREETING: str = "Hello"
+/// readonly config: str = read_file("config.txt")
+/// readonly user: str = "$USER"
+/// ```
+/// `const` values are literals, `readonly` ones can expand variables and call functions.
+/// Redeclaring one in the same script is a parse error, assigning it later `ex: from
+/// another evaluation` is a runtime error. In a function they are declared again by every call
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Readonly {
+    pub kind: ReadonlyKind,
+    /// An `Expression::Var` or, for `readonly`, an `Expression::CallVar`
+    pub decl: Box<Expression>,
+}
+
+impl Readonly {
+    pub fn is_readonly(s: &str) -> bool {
+        let s = s.trim();
+        s.starts_with("const ") || s.starts_with("readonly ")
+    }
+
+    /// Name of the declared variable
+    pub fn name(&self) -> &str {
+        match self.decl.as_ref() {
+            Expression::Var(Variable { name, .. })
+            | Expression::CallVar(CallVariable { name, .. }) => name,
+            _ => "",
+        }
+    }
+}
+
+impl FromStr for Readonly {
+    type Err = ParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, decl) = match s.split_once(char::is_whitespace) {
+            Some(("const", decl)) => (ReadonlyKind::Const, decl.trim()),
+            Some(("readonly", decl)) => (ReadonlyKind::Readonly, decl.trim()),
+            _ => return Err(ParseErr::InvalidExperssion(s.into())),
+        };
+
+        let decl = if CallVariable::is_call_var(decl) {
+            Expression::CallVar(decl.parse::<CallVariable>()?)
+        } else if Variable::is_var(decl) {
+            Expression::Var(decl.parse::<Variable>()?)
+        } else {
+            return Err(ParseErr::InvalidExperssion(format!(
+                "`{}` is not a valid declaration, expected `{} <name>: <type> = <value>`",
+                s, kind
+            )));
+        };
+
+        let is_literal = match &decl {
+            Expression::Var(Variable { template, .. }) => !template.has_vars(),
+            _ => false,
+        };
+        if kind == ReadonlyKind::Const && !is_literal {
+            return Err(ParseErr::InvalidExperssion(format!(
+                "`{}` const values must be literals, use `readonly` to compute them",
+                s
+            )));
+        }
+
+        Ok(Self {
+            kind,
+            decl: Box::new(decl),
+        })
+    }
+}
+
+/// Reject scripts declaring a `const` or `readonly` variable more than once, `catch`
+/// names, `match` bindings and the `err` of `trap ERR` count as declarations
+pub fn check_redeclarations(ast: &LangParser) -> Result<(), ParseErr> {
+    let decls = ast.declarations();
+    for (idx, (name, line, kind)) in decls.iter().enumerate() {
+        let Some(kind) = kind else {
+            continue;
+        };
+        if let Some((_, (_, other, _))) = decls
+            .iter()
+            .enumerate()
+            .find(|(other_idx, (other_name, ..))| other_name == name && *other_idx != idx)
+        {
+            return Err(ParseErr::Redeclared(format!(
+                "`{}` is a {} declared at line {}, it can't be redeclared at line {}",
+                name, kind, line, other
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        lang_parser::{Expression, LangParser, ParseErr},
+        variables::{VarValue, Variable},
+    };
+
+    use super::{Readonly, ReadonlyKind};
+
+    #[test]
+    fn parse_readonly_declarations() {
+        let result = "const NAME: str = \"Jone\"\nreadonly files: list = ls(\".\")"
+            .parse::<LangParser>()
+            .unwrap();
+
+        assert_eq!(
+            result.experssions[0],
+            Expression::Readonly(Readonly {
+                kind: ReadonlyKind::Const,
+                decl: Box::new(Expression::Var(Variable::new(
+                    "NAME",
+                    VarValue::Str("Jone".into())
+                ))),
+            })
+        );
+        let Expression::Readonly(readonly) = &result.experssions[1] else {
+            panic!("expected a readonly declaration")
+        };
+        assert_eq!(readonly.kind, ReadonlyKind::Readonly);
+        assert_eq!(readonly.name(), "files");
+        assert!(matches!(*readonly.decl, Expression::CallVar(_)));
+
+        for source in [
+            "const user: str = \"$USER\"",
+            "const files: list = ls()",
+            "const X",
+        ] {
+            assert!(
+                matches!(
+                    source.parse::<LangParser>(),
+                    Err(ParseErr::InvalidExperssion(_))
+                ),
+                "`{}` should be invalid",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn reject_redeclarations() {
+        let source = "const MAX: int = 3\necho MAX\ntry\nthrow 'x'\ncatch\nif 1 == 1\ndo MAX: int = 4\nendif\nend";
+        assert_eq!(
+            source.parse::<LangParser>().err().unwrap(),
+            ParseErr::Redeclared(
+                "`MAX` is a const declared at line 1, it can't be redeclared at line 6".into()
+            )
+        );
+
+        let source = "fn setup\ncount: int = 1\nend\nreadonly count: int = 2";
+        assert_eq!(
+            source.parse::<LangParser>().err().unwrap(),
+            ParseErr::Redeclared(
+                "`count` is a readonly declared at line 4, it can't be redeclared at line 2".into()
+            )
+        );
+
+        let source = "match $1\ncase MODE\necho MODE\nend\nconst MODE: str = \"fast\"";
+        assert!(matches!(
+            source.parse::<LangParser>(),
+            Err(ParseErr::Redeclared(_))
+        ));
+    }
+}
@@ -1,19 +1,57 @@
+/// Compile a regular expression once, the first time the expression is evaluated, and
+/// reuse it afterwards
+///
+/// Example
+/// ```text
+/// cached_regex!(RE_VAR).is_match(s)
+/// ```
+macro_rules! cached_regex {
+    ($pattern:expr) => {{
+        static RE: std::sync::OnceLock<::regex::Regex> = std::sync::OnceLock::new();
+        RE.get_or_init(|| ::regex::Regex::new($pattern).unwrap())
+    }};
+}
+pub(crate) use cached_regex;
+
 /// A regular expression to match variables declaration. [Interactive example](https://regex101.com/r/DUW4Rx/1)
 pub const RE_VAR: &str = r#"(?m)(?P<name>[^:\s]+)\s*:\s*(?P<type>[^:\s]+)\s*=\s*(?P<value>.+)"#;
 /// A regular expression to match echo statments. [Interactive example](https://regex101.com/r/b89BqY/1)
 pub const RE_ECHO: &str = r#"(?m)echo\s+('|")?(?P<expr>[^'"\n]+)('|")?"#;
+/// A regular expression to match throw statments `ex: throw "Missing config"`
+pub const RE_THROW: &str = r#"(?m)^throw\s+('|")?(?P<expr>[^'"\n]+)('|")?$"#;
+/// A regular expression to match the header of a trap block `ex: trap INT TERM`
+pub const RE_TRAP: &str = r#"(?m)^trap\s+(?P<events>[A-Z]+(\s+[A-Z]+)*)$"#;
 /// A regular expression to match `if else` statments. [Interactive example](https://regex101.com/r/cfub08/1)
 pub const RE_IF_ELSE: &str = r#"(?m)if\s+(?P<con>[^\n]+)\ndo\s+(?P<if_expr>[^\n]+)(\nelse\s*\ndo\s+(?P<else_expr>[^\n]+))?\nendif"#;
 /// A regular expression to evaluate comparison operations. [Interactive example](https://regex101.com/r/OjbUgO/1)
 pub const RE_CMP: &str =
-    r#"(?m)('|")?(?P<left>[^\s'"\n]+)('|")?\s+(?P<op>[^\s]+)\s+('|")?(?P<right>[^'"\n]+)('|")?"#;
+    r#"(?m)('|")?(?P<left>[^\s'"\n]+)('|")?\s+(?P<op>[^\s]+)\s+('|")?(?P<right>[^'"\n]*)('|")?"#;
+/// A regular expression to match unary file tests `ex: -f "notes.txt"`
+pub const RE_FILE_TEST: &str = r#"(?m)^(?P<op>-[efdrwxs])\s+('|")?(?P<right>[^'"\n]+)('|")?$"#;
 /// A regular expression to match commments `#`. [Interactive example](https://regex101.com/r/mAhoLQ/1)
 pub const RE_COMMENT: &str = r#"(?m)(^|[^\\])#(.*)"#;
 /// A regular expression to spot invalid math experssions. [Interactive example](https://regex101.com/r/kuMDUi/1)
 pub const RE_INVALID_MATH_EXPR: &str = r#"(?m)[a-zA-Z=]+"#;
-/// A regular expression to match variable expansions `ex: echo "Hello, ${name}"`. [Interactive example](https://regex101.com/r/5BLcW2/1)
+/// A regular expression to match variable expansions `ex: echo "Hello, ${name}"`, the template
+/// parser is tested against it. [Interactive example](https://regex101.com/r/5BLcW2/1)
+#[cfg(test)]
 pub const RE_VAR_EXPANSION: &str = r#"(?m)\$\{?(?P<var>[^\s"';,}]+)\}?"#;
-/// A regular expression to match match the `input("<text>")` function. [Interactive example](https://regex101.com/r/LPHoMk/1)
-pub const RE_INPUT_FUNC: &str = r#"(?m)input\(('|")?(?P<text>[^)'"]+)('|")?\)"#;
+/// A regular expression to match function calls `ex: read_file("notes.txt")`
+pub const RE_CALL: &str = r#"(?m)^(?P<name>[a-zA-Z_][a-zA-Z0-9_]*)\((?P<args>.*)\)$"#;
+/// A regular expression to match command style built-ins `ex: mkdir -p logs`
+pub const RE_COMMAND: &str = r#"(?m)^(?P<name>mkdir|rm|cp|mv|wait|jobs|kill)(\s+(?P<args>.+))?$"#;
+/// A regular expression to match external commands `ex: run cargo build &`
+pub const RE_RUN: &str = r#"(?m)^run\s+(?P<cmd>.+?)(?P<bg>\s*&)?$"#;
+/// A regular expression to match the header of a function `ex: fn test_greet`
+pub const RE_FN: &str = r#"^fn\s+(?P<name>[a-zA-Z_][a-zA-Z0-9_]*)$"#;
+/// A regular expression to match tracing toggles `ex: set trace on`
+pub const RE_SET_TRACE: &str = r#"^set\s+trace\s+(?P<state>on|off)$"#;
+/// A regular expression to match identifiers `ex: count`
+pub const RE_IDENT: &str = r#"^[a-zA-Z_][a-zA-Z0-9_]*$"#;
+/// A regular expression to match variable paths with an index `ex: cfg["servers"][0].host`,
+/// the quotes inside the brackets would be taken for string quotes otherwise
+pub const RE_VAR_PATH: &str = r#"^[a-zA-Z_][a-zA-Z0-9_]*(\.[a-zA-Z0-9_]+|\[([0-9]+|"[^"]*"|'[^']*')\])*\[([0-9]+|"[^"]*"|'[^']*')\](\.[a-zA-Z0-9_]+|\[([0-9]+|"[^"]*"|'[^']*')\])*$"#;
+/// A regular expression to match int ranges in `case` patterns `ex: 1..10`
+pub const RE_RANGE: &str = r#"^(?P<start>-?[0-9]+)\.\.(?P<end>-?[0-9]+)$"#;
 /// A regular expression for quote removal . [Interactive example](https://regex101.com/r/oJ9u9B/1)
 pub const RE_QUOTE_REMOVAL: &str = r#"(?m)('|")?(?P<value>[^'"\n]+)('|")?"#;
@@ -0,0 +1,106 @@
+use std::fmt::Display;
+
+/// Something a script may be denied to do
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Capability {
+    /// Run external commands and signal processes (`run`, `kill`)
+    Commands,
+    /// Create, modify or remove files (`write_file`, `append_file`, `mkdir`, `rm`, `cp`, `mv`)
+    FsWrite,
+    /// Network access. There are no network built-ins yet, new ones must check it
+    Network,
+    /// Read environment variables (`$HOME`, `${PATH}`)
+    Env,
+    /// Read from stdin (`input()`)
+    Stdin,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let capability = match self {
+            Capability::Commands => "commands",
+            Capability::FsWrite => "fs-write",
+            Capability::Network => "network",
+            Capability::Env => "env",
+            Capability::Stdin => "stdin",
+        };
+        write!(f, "{}", capability)
+    }
+}
+
+/// The capabilities granted to a script, everything is allowed by default
+///
+/// Example
+/// ```
+/// use mybash::{Interpreter, Sandbox};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_sandbox(Sandbox {
+///     env: true,
+///     ..Sandbox::deny_all()
+/// });
+/// assert!(interpreter.eval_str("run rm -rf /").is_err());
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Sandbox {
+    pub commands: bool,
+    pub fs_write: bool,
+    pub network: bool,
+    pub env: bool,
+    pub stdin: bool,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::allow_all()
+    }
+}
+
+impl Sandbox {
+    pub fn allow_all() -> Self {
+        Self {
+            commands: true,
+            fs_write: true,
+            network: true,
+            env: true,
+            stdin: true,
+        }
+    }
+
+    pub fn deny_all() -> Self {
+        Self {
+            commands: false,
+            fs_write: false,
+            network: false,
+            env: false,
+            stdin: false,
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Commands => self.commands,
+            Capability::FsWrite => self.fs_write,
+            Capability::Network => self.network,
+            Capability::Env => self.env,
+            Capability::Stdin => self.stdin,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Capability, Sandbox};
+
+    #[test]
+    fn allow_and_deny_capabilities() {
+        let sandbox = Sandbox {
+            stdin: true,
+            ..Sandbox::deny_all()
+        };
+        assert!(sandbox.allows(Capability::Stdin));
+        assert!(!sandbox.allows(Capability::FsWrite));
+        assert!(Sandbox::default().allows(Capability::Commands));
+        assert_eq!(Capability::FsWrite.to_string(), "fs-write");
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{
+    lang_parser::{Expression, ParseErr},
+    variables::{CallVariable, Variable},
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum ScopeKind {
+    /// `let`, the variable belongs to the enclosing block
+    Let,
+    /// `global`, the variable belongs to the script
+    Global,
+}
+
+impl Display for ScopeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScopeKind::Let => write!(f, "let"),
+            ScopeKind::Global => write!(f, "global"),
+        }
+    }
+}
+
+/// A declaration choosing the scope of its variable
+///
+/// Example
+/// ```text
+/// count: int = 1
+/// try
+/// let count: int = 2
+/// echo count
+/// global total: int = count
+/// end
+/// echo count
+/// ```
+/// `let` declares the variable in the enclosing block (a `try`, `catch`, `trap`, `fn`,
+/// `case` or `if` branch), it shadows a variable with the same name until the block ends.
+/// `global` assigns the script's variable even if a `let` shadows it. Without either,
+/// an assignment updates the innermost variable with that name, or declares a script one
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Scoped {
+    pub kind: ScopeKind,
+    /// An `Expression::Var` or an `Expression::CallVar`
+    pub decl: Box<Expression>,
+}
+
+impl Scoped {
+    pub fn is_scoped(s: &str) -> bool {
+        let s = s.trim();
+        s.starts_with("let ") || s.starts_with("global ")
+    }
+
+    /// Name of the declared variable
+    pub fn name(&self) -> &str {
+        match self.decl.as_ref() {
+            Expression::Var(Variable { name, .. })
+            | Expression::CallVar(CallVariable { name, .. }) => name,
+            _ => "",
+        }
+    }
+}
+
+impl FromStr for Scoped {
+    type Err = ParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, decl) = match s.split_once(char::is_whitespace) {
+            Some(("let", decl)) => (ScopeKind::Let, decl.trim()),
+            Some(("global", decl)) => (ScopeKind::Global, decl.trim()),
+            _ => return Err(ParseErr::InvalidExperssion(s.into())),
+        };
+
+        let decl = if CallVariable::is_call_var(decl) {
+            Expression::CallVar(decl.parse::<CallVariable>()?)
+        } else if Variable::is_var(decl) {
+            Expression::Var(decl.parse::<Variable>()?)
+        } else {
+            return Err(ParseErr::InvalidExperssion(format!(
+                "`{}` is not a valid declaration, expected `{} <name>: <type> = <value>`",
+                s, kind
+            )));
+        };
+
+        Ok(Self {
+            kind,
+            decl: Box::new(decl),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        lang_parser::{Expression, LangParser, ParseErr},
+        variables::{VarValue, Variable},
+    };
+
+    use super::{ScopeKind, Scoped};
+
+    #[test]
+    fn parse_scoped_declarations() {
+        let result = "try\nlet count: int = 2\nglobal files: list = ls(\".\")\ncatch\nend"
+            .parse::<LangParser>()
+            .unwrap();
+        let Expression::TryCatch(try_catch) = &result.experssions[0] else {
+            panic!("expected a try block")
+        };
+
+        assert_eq!(
+            try_catch.body.experssions[0],
+            Expression::Scoped(Scoped {
+                kind: ScopeKind::Let,
+                decl: Box::new(Expression::Var(Variable::new("count", VarValue::Int(2)))),
+            })
+        );
+        let Expression::Scoped(scoped) = &try_catch.body.experssions[1] else {
+            panic!("expected a global declaration")
+        };
+        assert_eq!(scoped.kind, ScopeKind::Global);
+        assert_eq!(scoped.name(), "files");
+        assert!(matches!(*scoped.decl, Expression::CallVar(_)));
+
+        for source in ["let count", "global echo 'x'", "let"] {
+            assert!(
+                matches!(
+                    source.parse::<LangParser>(),
+                    Err(ParseErr::InvalidExperssion(_))
+                ),
+                "`{}` should be invalid",
+                source
+            );
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    cell::RefCell,
+    io::{self, BufRead, BufReader, Write},
+    rc::Rc,
+};
+
+/// The standard streams used by a script
+pub struct Streams {
+    pub stdout: Box<dyn Write>,
+    pub stderr: Box<dyn Write>,
+    pub stdin: Box<dyn BufRead>,
+    /// `false` once any stream is redirected, external commands then get their output captured
+    /// and forwarded instead of inheriting the process' streams
+    pub inherited: bool,
+}
+
+impl Default for Streams {
+    fn default() -> Self {
+        Self {
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            stdin: Box::new(BufReader::new(io::stdin())),
+            inherited: true,
+        }
+    }
+}
+
+/// An in-memory sink that can be handed to the interpreter while keeping access to what
+/// was written
+///
+/// Example
+/// ```
+/// use mybash::{Interpreter, OutputBuffer};
+///
+/// let out = OutputBuffer::default();
+/// let mut interpreter = Interpreter::new();
+/// interpreter.set_stdout(out.clone());
+/// interpreter.eval_str("echo 'Hello'").unwrap();
+/// assert_eq!(out.contents(), "Hello\n");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct OutputBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).to_string()
+    }
+
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear()
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// A piece of a template
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum Segment {
+    Lit(String),
+    /// A variable expansion, holds the variable name without `$`, `{` and `}`
+    Var(String),
+}
+
+/// A string with variable expansions, split into literal and variable segments once so it
+/// can be evaluated without scanning the string again
+///
+/// Example
+/// ```text
+/// "Hello, ${name}!"  =>  [Lit("Hello, "), Var("name"), Lit("!")]
+/// ```
+/// Expansions follow `RE_VAR_EXPANSION`: a `$`, an optional `{`, the name up to a
+/// whitespace, quote, `;`, `,` or `}` and an optional `}`
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct Template {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse<T: Into<String>>(source: T) -> Self {
+        let source = source.into();
+        let mut segments = Vec::new();
+        let mut lit = String::new();
+        let mut chars = source.char_indices().peekable();
+
+        while let Some((_, c)) = chars.next() {
+            if c != '$' {
+                lit.push(c);
+                continue;
+            }
+
+            let mut rest = chars.clone();
+            // The `{` is only a delimiter if a name follows it, otherwise it is the name
+            if let Some((_, '{')) = rest.peek() {
+                let mut after = rest.clone();
+                after.next();
+                if after.peek().is_some_and(|(_, c)| is_name_char(*c)) {
+                    rest = after;
+                }
+            }
+
+            let mut name = String::new();
+            while let Some((_, c)) = rest.peek().filter(|(_, c)| is_name_char(*c)) {
+                name.push(*c);
+                rest.next();
+            }
+            if name.is_empty() {
+                lit.push(c);
+                continue;
+            }
+            if let Some((_, '}')) = rest.peek() {
+                rest.next();
+            }
+
+            if !lit.is_empty() {
+                segments.push(Segment::Lit(std::mem::take(&mut lit)));
+            }
+            segments.push(Segment::Var(name));
+            chars = rest;
+        }
+        if !lit.is_empty() {
+            segments.push(Segment::Lit(lit));
+        }
+
+        Self { source, segments }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn has_vars(&self) -> bool {
+        self.segments
+            .iter()
+            .any(|segment| matches!(segment, Segment::Var(_)))
+    }
+
+    /// The variable name if the whole template is a single expansion `ex: ${name}`
+    pub fn single_var(&self) -> Option<&str> {
+        match self.segments.as_slice() {
+            [Segment::Var(name)] => Some(name),
+            _ => None,
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    !(c.is_whitespace() || matches!(c, '"' | '\'' | ';' | ',' | '}'))
+}
+
+impl From<&str> for Template {
+    fn from(s: &str) -> Self {
+        Template::parse(s)
+    }
+}
+
+impl From<String> for Template {
+    fn from(s: String) -> Self {
+        Template::parse(s)
+    }
+}
+
+impl Display for Template {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Segment, Template};
+    use crate::regex::{cached_regex, RE_VAR_EXPANSION};
+
+    fn lit(s: &str) -> Segment {
+        Segment::Lit(s.into())
+    }
+
+    fn var(s: &str) -> Segment {
+        Segment::Var(s.into())
+    }
+
+    #[test]
+    fn split_literals_and_expansions() {
+        let template = Template::parse("Hello, ${name}! You are $age, $1");
+        assert_eq!(
+            template.segments(),
+            [
+                lit("Hello, "),
+                var("name"),
+                lit("! You are "),
+                var("age"),
+                lit(", "),
+                var("1")
+            ]
+        );
+        assert_eq!(template.to_string(), "Hello, ${name}! You are $age, $1");
+        assert!(template.has_vars());
+
+        assert_eq!(Template::parse("costs 5 $").segments(), [lit("costs 5 $")]);
+        assert_eq!(
+            Template::parse("${err.kind}").single_var(),
+            Some("err.kind")
+        );
+        assert_eq!(Template::parse("$?").single_var(), Some("?"));
+        assert!(!Template::parse("plain").has_vars());
+    }
+
+    #[test]
+    fn match_the_expansion_regex() {
+        let re = cached_regex!(RE_VAR_EXPANSION);
+        for s in [
+            "Hello, ${name}",
+            "$a$b",
+            "${}",
+            "$name} and ${x",
+            "$'quoted'",
+            "a $1,$2;$3",
+            "${dir}/logs/$file.txt",
+        ] {
+            let expected = re
+                .captures_iter(s)
+                .map(|caps| caps["var"].to_string())
+                .collect::<Vec<_>>();
+            let found = Template::parse(s)
+                .segments()
+                .iter()
+                .filter_map(|segment| match segment {
+                    Segment::Var(name) => Some(name.clone()),
+                    Segment::Lit(_) => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected, "{}", s);
+        }
+    }
+}
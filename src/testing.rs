@@ -0,0 +1,311 @@
+use crate::{
+    call::Call,
+    compiled,
+    executor::Executor,
+    interpreter::InterpreterErr,
+    lang_parser::Expression,
+    streams::{OutputBuffer, Streams},
+    trace::traced,
+    variables::VarValue,
+};
+use std::{
+    fs, io,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Files holding tests end with this suffix
+const TEST_SUFFIX: &str = "_test.mb";
+
+/// The outcome of a `test_*` function
+#[derive(Debug, PartialEq, Eq)]
+pub struct TestResult {
+    pub name: String,
+    /// Line of the failing statement, or of the `fn` statement if the test passed
+    pub line: usize,
+    /// The error that ended the test, if it failed
+    pub failure: Option<String>,
+    /// What the test wrote to stdout and stderr
+    pub output: String,
+}
+
+/// The `*_test.mb` files in `paths`, directories are searched recursively skipping hidden
+/// ones and `target`
+pub fn discover<P: AsRef<Path>>(paths: &[P]) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        if path.is_dir() {
+            find_tests(path, &mut files)?;
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    Ok(files)
+}
+
+fn find_tests(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for path in entries {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                find_tests(&path, files)?;
+            }
+        } else if name.ends_with(TEST_SUFFIX) {
+            files.push(path.strip_prefix(".").unwrap_or(&path).to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the `test_*` functions of the script at `path` in source order. Every test gets its
+/// own executor, which runs the script's top level statements before calling the test
+///
+/// Example
+/// ```text
+/// fn test_greeting
+/// greeting: str = "Hello, Jone"
+/// assert_eq(greeting, "Hello, Jone")
+/// assert_contains(greeting, "Jone")
+/// end
+/// ```
+/// `EXIT` traps registered by the script or the test run after the test
+pub fn run_file<P: AsRef<Path>>(path: P) -> Result<Vec<TestResult>, InterpreterErr> {
+    let path = path.as_ref();
+    let ast = compiled::load(path)?;
+
+    let tests = ast
+        .experssions
+        .iter()
+        .zip(&ast.lines)
+        .filter_map(|(expr, line)| match expr {
+            Expression::Function(function) if function.name.starts_with("test_") => {
+                Some((function.name.clone(), *line))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = Vec::new();
+    for (name, line) in tests {
+        let output = OutputBuffer::default();
+        let mut exe = Executor::new();
+        exe.script_name = path.display().to_string();
+        exe.streams = Streams {
+            stdout: Box::new(output.clone()),
+            stderr: Box::new(output.clone()),
+            stdin: Box::new(io::empty()),
+            inherited: false,
+        };
+
+        let mut program = ast.clone();
+        program
+            .experssions
+            .push(Expression::Call(Call::new(&name, vec![])));
+        program.lines.push(line);
+        let failure = exe.execute(&program).err();
+
+        results.push(TestResult {
+            name,
+            line: if failure.is_some() { exe.line() } else { line },
+            failure: failure.map(|err| err.to_string()),
+            output: output.contents(),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Run the tests in `files` and write a report to `out`, returns `true` if every test
+/// passed. A file that can't be parsed counts as a failed test
+pub fn run<W: Write>(files: &[PathBuf], mut out: W) -> io::Result<bool> {
+    let mut passed = 0;
+    let mut failures = Vec::new();
+    for file in files {
+        let results = match run_file(file) {
+            Ok(results) => results,
+            Err(err) => {
+                writeln!(out, "error: {}: {}", file.display(), err)?;
+                failures.push(format!("---- {} ----\n{}", file.display(), err));
+                continue;
+            }
+        };
+
+        writeln!(
+            out,
+            "running {} test(s) from {}",
+            results.len(),
+            file.display()
+        )?;
+        for result in results {
+            match result.failure {
+                None => {
+                    writeln!(out, "test {} ... ok", result.name)?;
+                    passed += 1;
+                }
+                Some(failure) => {
+                    writeln!(out, "test {} ... FAILED", result.name)?;
+                    let mut report = format!(
+                        "---- {}::{} at line {} ----\n{}\n",
+                        file.display(),
+                        result.name,
+                        result.line,
+                        failure
+                    );
+                    if !result.output.is_empty() {
+                        report.push_str(&format!("output:\n{}", result.output));
+                    }
+                    failures.push(report);
+                }
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        writeln!(out, "\nfailures:")?;
+        for report in &failures {
+            write!(out, "\n{}", report.trim_end())?;
+            writeln!(out)?;
+        }
+    }
+    let status = if failures.is_empty() { "ok" } else { "FAILED" };
+    writeln!(
+        out,
+        "\ntest result: {}. {} passed; {} failed",
+        status,
+        passed,
+        failures.len()
+    )?;
+
+    Ok(failures.is_empty())
+}
+
+/// A line diff of the two values, `-` lines are only in `left` and `+` lines only in
+/// `right`. Single line values are shown the way the trace shows them
+pub(crate) fn diff_values(left: &VarValue, right: &VarValue) -> String {
+    let (left, right) = (left.to_string(), right.to_string());
+    if !left.contains('\n') && !right.contains('\n') {
+        let (left, right) = (traced(&VarValue::Str(left)), traced(&VarValue::Str(right)));
+        return format!("- {}\n+ {}", left, right);
+    }
+
+    diff(&left, &right)
+}
+
+/// A line diff based on the longest common subsequence, `-` lines are only in `left`, `+`
+/// lines only in `right`
+pub fn diff(left: &str, right: &str) -> String {
+    let left = left.lines().collect::<Vec<_>>();
+    let right = right.lines().collect::<Vec<_>>();
+
+    // lcs[i][j] is the length of the longest common subsequence of left[i..] and right[j..]
+    let mut lcs = vec![vec![0; right.len() + 1]; left.len() + 1];
+    for i in (0..left.len()).rev() {
+        for j in (0..right.len()).rev() {
+            lcs[i][j] = if left[i] == right[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < left.len() || j < right.len() {
+        if i < left.len() && j < right.len() && left[i] == right[j] {
+            lines.push(format!("  {}", left[i]));
+            i += 1;
+            j += 1;
+        } else if j == right.len() || i < left.len() && lcs[i + 1][j] >= lcs[i][j + 1] {
+            lines.push(format!("- {}", left[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", right[j]));
+            j += 1;
+        }
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff, discover, run};
+    use crate::streams::OutputBuffer;
+    use std::fs;
+
+    #[test]
+    fn diff_lines() {
+        assert_eq!(diff("a\nb\nc", "a\nx\nc\nd"), "  a\n- b\n+ x\n  c\n+ d");
+    }
+
+    #[test]
+    fn run_test_functions_in_isolation() {
+        let dir = std::env::temp_dir().join(format!("mybash-testing-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(
+            dir.join("lib/greet_test.mb"),
+            "name: str = \"Jone\"
+count: int = 0
+
+fn test_greeting
+greeting: str = \"Hello, $name\"
+assert_eq(greeting, \"Hello, $name\")
+assert_contains(greeting, \"Jone\")
+assert(name == \"Jone\")
+count: int = 1
+end
+
+fn test_isolated
+assert(count == 0, \"count leaked from another test\")
+end
+
+fn test_failing
+echo \"checking\"
+assert_eq(name, \"Jane\")
+end",
+        )
+        .unwrap();
+        fs::write(dir.join("lib/helpers.mb"), "echo 'not a test'").unwrap();
+
+        let files = discover(&[&dir]).unwrap();
+        assert_eq!(files, vec![dir.join("lib/greet_test.mb")]);
+
+        let out = OutputBuffer::default();
+        let passed = run(&files, out.clone()).unwrap();
+        let report = out.contents();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!passed);
+        let file = files[0].display();
+        assert_eq!(
+            report,
+            format!(
+                "running 3 test(s) from {file}
+test test_greeting ... ok
+test test_isolated ... ok
+test test_failing ... FAILED
+
+failures:
+
+---- {file}::test_failing at line 18 ----
+Assertion failed: `name` != `\"Jane\"`
+- \"Jone\"
++ \"Jane\"
+output:
+checking
+
+test result: FAILED. 2 passed; 1 failed
+"
+            )
+        );
+    }
+}
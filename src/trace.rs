@@ -0,0 +1,74 @@
+use crate::{
+    lang_parser::ParseErr,
+    regex::{cached_regex, RE_SET_TRACE},
+    variables::VarValue,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Turn execution tracing on or off, like `set -x`
+///
+/// Example
+/// ```text
+/// set trace on
+/// name: str = "Jone"
+/// set trace off
+/// ```
+/// While tracing, every statement is printed to stderr with its variables expanded
+/// before it runs `ex: + deploy.mb:2: name = "Jone"`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SetTrace(pub bool);
+
+impl SetTrace {
+    pub fn is_set_trace(s: &str) -> bool {
+        s.trim().starts_with("set ")
+    }
+}
+
+impl FromStr for SetTrace {
+    type Err = ParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let re = cached_regex!(RE_SET_TRACE);
+
+        match re.captures(s.trim()) {
+            Some(caps) => Ok(Self(&caps["state"] == "on")),
+            None => Err(ParseErr::InvalidExperssion(format!(
+                "`{}` is not a valid set statement, expected `set trace on` or `set trace off`",
+                s.trim()
+            ))),
+        }
+    }
+}
+
+/// A value the way the trace shows it, strings are quoted so whitespace is visible
+pub(crate) fn traced(value: &VarValue) -> String {
+    match value {
+        VarValue::Str(val) => format!("{:?}", val),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SetTrace;
+    use crate::lang_parser::{Expression, LangParser, ParseErr};
+
+    #[test]
+    fn parse_set_trace() {
+        let result = "set trace on\nset  trace  off"
+            .parse::<LangParser>()
+            .unwrap();
+        assert_eq!(
+            result.experssions,
+            vec![
+                Expression::SetTrace(SetTrace(true)),
+                Expression::SetTrace(SetTrace(false))
+            ]
+        );
+        assert!(matches!(
+            "set trace maybe".parse::<LangParser>(),
+            Err(ParseErr::InvalidExperssion(_))
+        ));
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{
+    lang_parser::{find_block_end, Expression, LangParser, ParseErr, SourceLine},
+    regex::{cached_regex, RE_TRAP},
+};
+use serde::{Deserialize, Serialize};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    flag, low_level, SigId,
+};
+use std::{
+    fmt::Display,
+    io,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// The events a trap can be registered for
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum TrapEvent {
+    /// The script finished, successfully or not
+    Exit,
+    /// The script is about to stop because of an uncaught error
+    Err,
+    /// SIGINT
+    Int,
+    /// SIGTERM
+    Term,
+}
+
+impl FromStr for TrapEvent {
+    type Err = ParseErr;
+
+    fn from_str(s: &str) -> Result<Self, ParseErr> {
+        let event = match s.trim() {
+            "EXIT" => Self::Exit,
+            "ERR" => Self::Err,
+            "INT" | "SIGINT" => Self::Int,
+            "TERM" | "SIGTERM" => Self::Term,
+            _ => {
+                return Err(ParseErr::InvalidExperssion(format!(
+                    "`{}` is not a valid trap event\nEvents: [EXIT, ERR, INT, TERM]",
+                    s
+                )))
+            }
+        };
+
+        Ok(event)
+    }
+}
+
+impl Display for TrapEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let event = match self {
+            TrapEvent::Exit => "EXIT",
+            TrapEvent::Err => "ERR",
+            TrapEvent::Int => "INT",
+            TrapEvent::Term => "TERM",
+        };
+        write!(f, "{}", event)
+    }
+}
+
+impl TrapEvent {
+    /// Exit status of a script stopped by this signal (128 + signal number)
+    pub fn exit_status(&self) -> i32 {
+        match self {
+            TrapEvent::Int => 128 + SIGINT,
+            TrapEvent::Term => 128 + SIGTERM,
+            _ => 1,
+        }
+    }
+}
+
+/// A cleanup handler
+///
+/// Example
+/// ```text
+/// trap EXIT
+/// rm -r "tmp"
+/// end
+///
+/// trap INT TERM
+/// echo "Interrupted"
+/// end
+/// ```
+/// Traps are registered when executed and run in reverse registration order.
+/// `ERR` handlers can read the uncaught error through `err`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Trap {
+    pub events: Vec<TrapEvent>,
+    pub body: LangParser,
+}
+
+impl Trap {
+    pub fn is_trap(s: &str) -> bool {
+        let re = cached_regex!(RE_TRAP);
+        re.is_match(s.trim())
+    }
+
+    /// Parse the block starting at `lines[start_idx]`, returns the block and the index of its `end`
+    pub fn from_lines(lines: &[SourceLine], start_idx: usize) -> Result<(Self, usize), ParseErr> {
+        let (trap_line, header) = &lines[start_idx];
+        let re = cached_regex!(RE_TRAP);
+        let caps = re
+            .captures(header.trim())
+            .ok_or_else(|| ParseErr::InvalidExperssion(header.into()))?;
+        let events = caps["events"]
+            .split_whitespace()
+            .map(|event| event.parse::<TrapEvent>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let end_idx = find_block_end(lines, start_idx).ok_or_else(|| {
+            ParseErr::UnterminatedBlock(format!("`trap` at line {} is missing `end`", trap_line))
+        })?;
+
+        Ok((
+            Self {
+                events,
+                body: LangParser::from_lines(&lines[start_idx + 1..end_idx])?,
+            },
+            end_idx,
+        ))
+    }
+}
+
+/// A cleanup block run when the enclosing function returns
+///
+/// Example
+/// ```text
+/// fn build
+/// mkdir "tmp"
+/// defer
+/// rm -r "tmp"
+/// end
+/// run make
+/// end
+/// ```
+/// Deferred blocks run in reverse registration order when the function returns, even if it
+/// failed. An error raised by one is only returned if the function itself succeeded.
+/// `defer` is only valid inside a function
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Defer {
+    pub body: LangParser,
+}
+
+impl Defer {
+    pub fn is_defer(s: &str) -> bool {
+        s.trim() == "defer"
+    }
+
+    /// Parse the block starting at `lines[start_idx]`, returns the block and the index of its `end`
+    pub fn from_lines(lines: &[SourceLine], start_idx: usize) -> Result<(Self, usize), ParseErr> {
+        let (defer_line, _) = &lines[start_idx];
+        let end_idx = find_block_end(lines, start_idx).ok_or_else(|| {
+            ParseErr::UnterminatedBlock(format!("`defer` at line {} is missing `end`", defer_line))
+        })?;
+
+        Ok((
+            Self {
+                body: LangParser::from_lines(&lines[start_idx + 1..end_idx])?,
+            },
+            end_idx,
+        ))
+    }
+}
+
+/// Reject `defer` blocks outside a function, trap bodies run once the functions returned
+pub fn check_defers(ast: &LangParser) -> Result<(), ParseErr> {
+    check_block_defers(ast, false)
+}
+
+fn check_block_defers(block: &LangParser, in_function: bool) -> Result<(), ParseErr> {
+    for (expr, line) in block.experssions.iter().zip(&block.lines) {
+        match expr {
+            Expression::Defer(_) if !in_function => {
+                return Err(ParseErr::InvalidExperssion(format!(
+                    "`defer` at line {} is outside a function",
+                    line
+                )))
+            }
+            Expression::Defer(defer) => check_block_defers(&defer.body, in_function)?,
+            Expression::Function(function) => check_block_defers(&function.body, true)?,
+            Expression::Trap(trap) => check_block_defers(&trap.body, false)?,
+            Expression::TryCatch(try_catch) => {
+                check_block_defers(&try_catch.body, in_function)?;
+                check_block_defers(&try_catch.catch_body, in_function)?;
+            }
+            Expression::Match(block) => {
+                for case in &block.cases {
+                    check_block_defers(&case.body, in_function)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Records SIGINT and SIGTERM so the executor can run the matching traps between statements.
+/// A second signal terminates the process right away, even if the first one was not handled
+/// yet (`ex: while blocked on input()`)
+pub struct Signals {
+    pending: Arc<AtomicUsize>,
+    ids: Vec<SigId>,
+}
+
+impl Signals {
+    pub fn register() -> io::Result<Self> {
+        let pending = Arc::new(AtomicUsize::new(0));
+        let handling = Arc::new(AtomicBool::new(false));
+        let mut ids = Vec::new();
+        for signal in [SIGINT, SIGTERM] {
+            ids.push(flag::register_conditional_shutdown(
+                signal,
+                128 + signal,
+                Arc::clone(&handling),
+            )?);
+            ids.push(flag::register(signal, Arc::clone(&handling))?);
+            ids.push(flag::register_usize(
+                signal,
+                Arc::clone(&pending),
+                signal as usize,
+            )?);
+        }
+
+        Ok(Self { pending, ids })
+    }
+
+    /// Record `event` as if the signal was received
+    #[cfg(test)]
+    pub fn send(&self, event: TrapEvent) {
+        let signal = match event {
+            TrapEvent::Int => SIGINT,
+            TrapEvent::Term => SIGTERM,
+            _ => return,
+        };
+        self.pending.store(signal as usize, Ordering::SeqCst);
+    }
+
+    /// Returns the signal received since the last call, if any
+    pub fn take(&self) -> Option<TrapEvent> {
+        match self.pending.swap(0, Ordering::SeqCst) as i32 {
+            SIGINT => Some(TrapEvent::Int),
+            SIGTERM => Some(TrapEvent::Term),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for Signals {
+    fn drop(&mut self) {
+        for id in self.ids.drain(..) {
+            low_level::unregister(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        echo::Echo,
+        lang_parser::{Expression, LangParser, ParseErr},
+    };
+
+    use super::{Defer, Trap, TrapEvent};
+
+    #[test]
+    fn parse_trap() {
+        let expr = "trap INT TERM\necho 'bye'\nend";
+        let result = expr.parse::<LangParser>().unwrap();
+
+        assert_eq!(
+            result.experssions,
+            vec![Expression::Trap(Box::new(Trap {
+                events: vec![TrapEvent::Int, TrapEvent::Term],
+                body: LangParser {
+                    experssions: vec![Expression::Echo(Echo("bye".into()))],
+                    lines: vec![2]
+                }
+            }))]
+        );
+    }
+
+    #[test]
+    fn parse_defer() {
+        let expr = "fn build\ndefer\necho 'bye'\nend\nend";
+        let result = expr.parse::<LangParser>().unwrap();
+        let Expression::Function(function) = &result.experssions[0] else {
+            panic!("expected a function")
+        };
+
+        assert_eq!(
+            function.body.experssions,
+            vec![Expression::Defer(Box::new(Defer {
+                body: LangParser {
+                    experssions: vec![Expression::Echo(Echo("bye".into()))],
+                    lines: vec![3]
+                }
+            }))]
+        );
+
+        for (source, message) in [
+            (
+                "defer\necho 'bye'\nend",
+                "`defer` at line 1 is outside a function",
+            ),
+            (
+                "fn build\ntrap EXIT\ndefer\nend\nend\nend",
+                "`defer` at line 3 is outside a function",
+            ),
+        ] {
+            assert_eq!(
+                source.parse::<LangParser>().err().unwrap(),
+                ParseErr::InvalidExperssion(message.into())
+            );
+        }
+        assert_eq!(
+            "fn build\ndefer\nend".parse::<LangParser>().err().unwrap(),
+            ParseErr::UnterminatedBlock("`fn` at line 1 is missing `end`".into())
+        );
+    }
+
+    #[test]
+    fn parse_invalid_trap() {
+        let expr = "trap HUP\necho 'bye'\nend";
+        assert!(matches!(
+            expr.parse::<LangParser>(),
+            Err(ParseErr::InvalidExperssion(_))
+        ));
+
+        let expr = "trap EXIT\necho 'bye'";
+        assert_eq!(
+            expr.parse::<LangParser>().err().unwrap(),
+            ParseErr::UnterminatedBlock("`trap` at line 1 is missing `end`".into())
+        );
+    }
+}
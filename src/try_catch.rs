@@ -0,0 +1,161 @@
+use crate::{
+    lang_parser::{is_block_end, is_block_start, LangParser, ParseErr, SourceLine},
+    regex::{cached_regex, RE_THROW},
+    template::Template,
+};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A representation of `try catch` blocks
+///
+/// Example
+/// ```text
+/// try
+/// content: str = read_file("config.txt")
+/// catch err
+/// echo "Failed at line ${err.line}: ${err.message}"
+/// end
+/// ```
+/// The caught error is a map with `kind`, `message` and `line`. The name after
+/// `catch` is optional and defaults to `err`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TryCatch {
+    pub body: LangParser,
+    pub err_name: String,
+    pub catch_body: LangParser,
+}
+
+impl TryCatch {
+    pub fn is_try(s: &str) -> bool {
+        s.trim() == "try"
+    }
+
+    pub fn is_catch(s: &str) -> bool {
+        let s = s.trim();
+        s == "catch" || s.starts_with("catch ")
+    }
+
+    /// Parse the block starting at `lines[start_idx]`, returns the block and the index of its `end`
+    pub fn from_lines(lines: &[SourceLine], start_idx: usize) -> Result<(Self, usize), ParseErr> {
+        let (try_line, _) = &lines[start_idx];
+        let mut catch_idx = None;
+        let mut end_idx = None;
+        let mut depth = 0;
+
+        for (idx, (_, line)) in lines.iter().enumerate().skip(start_idx + 1) {
+            if is_block_start(line) {
+                depth += 1;
+            } else if is_block_end(line) {
+                if depth == 0 {
+                    end_idx = Some(idx);
+                    break;
+                }
+                depth -= 1;
+            } else if depth == 0 && TryCatch::is_catch(line) && catch_idx.is_none() {
+                catch_idx = Some(idx);
+            }
+        }
+
+        let end_idx = end_idx.ok_or_else(|| {
+            ParseErr::UnterminatedBlock(format!("`try` at line {} is missing `end`", try_line))
+        })?;
+        let catch_idx = catch_idx.ok_or_else(|| {
+            ParseErr::InvalidExperssion(format!("`try` at line {} is missing `catch`", try_line))
+        })?;
+
+        let err_name = lines[catch_idx].1.trim()["catch".len()..].trim();
+        let err_name = if err_name.is_empty() { "err" } else { err_name };
+
+        Ok((
+            Self {
+                body: LangParser::from_lines(&lines[start_idx + 1..catch_idx])?,
+                err_name: err_name.to_string(),
+                catch_body: LangParser::from_lines(&lines[catch_idx + 1..end_idx])?,
+            },
+            end_idx,
+        ))
+    }
+}
+
+/// `throw "<message>"` raises an error of kind `throw`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Throw(pub Template);
+
+impl Throw {
+    pub fn is_throw(s: &str) -> bool {
+        let re = cached_regex!(RE_THROW);
+        re.is_match(s.trim())
+    }
+}
+
+impl FromStr for Throw {
+    type Err = ParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let re = cached_regex!(RE_THROW);
+
+        if let Some(caps) = re.captures(s.trim()) {
+            Ok(Self(Template::parse(&caps["expr"])))
+        } else {
+            Err(ParseErr::InvalidExperssion(s.into()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        echo::Echo,
+        lang_parser::{Expression, LangParser, ParseErr},
+    };
+
+    use super::{Throw, TryCatch};
+
+    #[test]
+    fn parse_try_catch() {
+        let expr = "echo 'start'\ntry\nthrow \"boom\"\ncatch e\necho $e.message\nend";
+        let result = expr.parse::<LangParser>().unwrap();
+
+        assert_eq!(result.lines, vec![1, 2]);
+        assert_eq!(
+            result.experssions[1],
+            Expression::TryCatch(Box::new(TryCatch {
+                body: LangParser {
+                    experssions: vec![Expression::Throw(Throw("boom".into()))],
+                    lines: vec![3]
+                },
+                err_name: "e".into(),
+                catch_body: LangParser {
+                    experssions: vec![Expression::Echo(Echo("$e.message".into()))],
+                    lines: vec![5]
+                },
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_nested_try_catch() {
+        let expr = "try\ntry\nthrow 'inner'\ncatch\necho 'inner'\nend\ncatch\necho 'outer'\nend";
+        let result = expr.parse::<LangParser>().unwrap();
+
+        assert_eq!(result.experssions.len(), 1);
+        let Expression::TryCatch(outer) = &result.experssions[0] else {
+            panic!("expected a try block")
+        };
+        assert_eq!(outer.err_name, "err");
+        assert!(matches!(outer.body.experssions[0], Expression::TryCatch(_)));
+        assert_eq!(
+            outer.catch_body.experssions,
+            vec![Expression::Echo(Echo("outer".into()))]
+        );
+    }
+
+    #[test]
+    fn parse_try_without_end() {
+        let expr = "try\nthrow 'x'\ncatch err";
+        assert_eq!(
+            expr.parse::<LangParser>().err().unwrap(),
+            ParseErr::UnterminatedBlock("`try` at line 1 is missing `end`".into())
+        );
+    }
+}
@@ -1,9 +1,7 @@
-use regex::Regex;
+use crate::regex::{cached_regex, RE_COMMENT};
 
-use crate::regex::{RE_COMMENT, RE_INPUT_FUNC};
-
-pub fn remove_comments<'a>(s: &'a str) -> String {
-    let re = Regex::new(RE_COMMENT).unwrap();
+pub fn remove_comments(s: &str) -> String {
+    let re = cached_regex!(RE_COMMENT);
 
     if let Some(caps) = re.captures(s) {
         s.replace(&caps[0], "")
@@ -15,14 +13,54 @@ pub fn remove_comments<'a>(s: &'a str) -> String {
     }
 }
 
-pub fn is_input_fn(s: &str) -> bool {
-    let re = Regex::new(RE_INPUT_FUNC).unwrap();
-    re.is_match(s.trim())
+/// The comment `remove_comments` strips from `s`, without the `#`
+pub fn comment(s: &str) -> Option<String> {
+    let re = cached_regex!(RE_COMMENT);
+    re.captures(s).map(|caps| caps[2].trim_end().to_string())
+}
+
+/// Split `s` on `sep` (or on whitespace when `sep` is `None`). Separators inside quotes
+/// or parentheses are ignored and every part is trimmed
+pub fn split_args(s: &str, sep: Option<char>) -> Vec<String> {
+    let is_sep = |c: char| match sep {
+        Some(sep) => c == sep,
+        None => c.is_whitespace(),
+    };
+
+    let mut args = Vec::new();
+    let mut curr = String::new();
+    let mut quote: Option<char> = None;
+    let mut depth = 0;
+
+    for c in s.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, c) if depth == 0 && is_sep(c) => {
+                if sep.is_some() || !curr.trim().is_empty() {
+                    args.push(curr.trim().to_string());
+                }
+                curr.clear();
+                continue;
+            }
+            _ => {}
+        }
+        curr.push(c);
+    }
+
+    if !curr.trim().is_empty() || (sep.is_some() && !args.is_empty()) {
+        args.push(curr.trim().to_string());
+    }
+
+    args
 }
 
 #[cfg(test)]
 mod test {
-    use super::remove_comments;
+    use super::{comment, remove_comments, split_args};
 
     #[test]
     fn should_ignore_line_comment() {
@@ -44,4 +82,30 @@ mod test {
             "name: string = \"#hash#\"".to_string()
         );
     }
+
+    #[test]
+    fn should_keep_the_removed_comment() {
+        assert_eq!(
+            comment("echo \"Hello\" # greet the user"),
+            Some(" greet the user".into())
+        );
+        assert_eq!(
+            comment("#!/usr/bin/env mybash"),
+            Some("!/usr/bin/env mybash".into())
+        );
+        assert_eq!(comment(r#"name: str = "\#hash""#), None);
+    }
+
+    #[test]
+    fn should_split_args_ignoring_quoted_separators() {
+        assert_eq!(
+            split_args(r#"'a, b', c , "d""#, Some(',')),
+            vec!["'a, b'", "c", "\"d\""]
+        );
+        assert_eq!(
+            split_args("-p  'my dir'   other", None),
+            vec!["-p", "'my dir'", "other"]
+        );
+        assert!(split_args("  ", Some(',')).is_empty());
+    }
 }
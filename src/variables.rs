@@ -1,44 +1,226 @@
-use crate::{eval::eval, regex::*, utils::is_input_fn};
-use regex::Regex;
-use std::{fmt::Display, str::FromStr};
+use crate::{call::Call, eval::eval, regex::*, template::Template};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
 use thiserror::Error;
 
-#[derive(Debug, Error)]
-pub enum VarValueErr {
-    #[error("`{0}` is not a valid variable value")]
-    InvalidVarValue(String),
-}
-
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum VarValue {
     Int(i32),
     Str(String),
     Bool(bool),
+    List(Vec<VarValue>),
+    Map(BTreeMap<String, VarValue>),
+    /// A JSON `null`
+    Null,
+    /// A JSON number that isn't an `int`, kept as written
+    Number(String),
 }
 
-impl FromStr for VarValue {
-    type Err = VarValueErr;
+impl Display for VarValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VarValue::Int(val) => write!(f, "{}", val),
+            VarValue::Str(val) => write!(f, "{}", val),
+            VarValue::Bool(val) => write!(f, "{}", val),
+            VarValue::Null => Ok(()),
+            VarValue::Number(val) => write!(f, "{}", val),
+            VarValue::List(items) => {
+                let items = items.iter().map(|i| i.to_string()).collect::<Vec<_>>();
+                write!(f, "[{}]", items.join(", "))
+            }
+            VarValue::Map(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect::<Vec<_>>();
+                write!(f, "{{{}}}", fields.join(", "))
+            }
+        }
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let res = match s.parse::<i32>() {
+impl VarValue {
+    /// The value of a literal, ints and booleans are parsed and anything else is a string
+    ///
+    /// Example
+    /// ```text
+    /// 42  =>  Int(42)
+    /// true  =>  Bool(true)
+    /// Jone  =>  Str("Jone")
+    /// ```
+    pub fn from_literal(s: &str) -> Self {
+        match s.parse::<i32>() {
             Ok(num) => Self::Int(num),
             Err(_) => match s.parse::<bool>() {
                 Ok(res) => Self::Bool(res),
                 Err(_) => Self::Str(s.to_string()),
             },
+        }
+    }
+
+    /// Approximate number of bytes held by the value, used for the memory limit
+    pub fn size(&self) -> usize {
+        match self {
+            VarValue::Int(_) => std::mem::size_of::<i32>(),
+            VarValue::Bool(_) => std::mem::size_of::<bool>(),
+            VarValue::Str(val) | VarValue::Number(val) => val.len(),
+            VarValue::Null => 0,
+            VarValue::List(items) => items.iter().map(VarValue::size).sum(),
+            VarValue::Map(fields) => fields
+                .iter()
+                .map(|(key, value)| key.len() + value.size())
+                .sum(),
+        }
+    }
+
+    /// The value at `path`, map fields by key and list items by index
+    pub fn get_path(&self, path: &[String]) -> Option<&VarValue> {
+        let mut value = self;
+        for key in path {
+            value = match value {
+                VarValue::Map(fields) => fields.get(key)?,
+                VarValue::List(items) => items.get(key.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+
+        Some(value)
+    }
+
+    pub fn datatype(&self) -> DataType {
+        match self {
+            VarValue::Int(_) => DataType::Int,
+            // JSON values without a type of their own behave like strings
+            VarValue::Str(_) | VarValue::Null | VarValue::Number(_) => DataType::Str,
+            VarValue::Bool(_) => DataType::Bool,
+            VarValue::List(_) => DataType::List,
+            VarValue::Map(_) => DataType::Map,
+        }
+    }
+
+    /// Convert the value into the declared `datatype`. Strings are parsed into ints and
+    /// booleans, any value can become a string
+    pub fn coerce(self, datatype: &DataType) -> Result<VarValue, VarErr> {
+        match (datatype, self) {
+            (DataType::Str, VarValue::Str(val)) => Ok(VarValue::Str(val)),
+            (DataType::Str, other) => Ok(VarValue::Str(other.to_string())),
+            (DataType::Int, VarValue::Int(val)) => Ok(VarValue::Int(val)),
+            (DataType::Int, VarValue::Str(val)) => val
+                .trim()
+                .parse::<i32>()
+                .map(VarValue::Int)
+                .map_err(|_| VarErr::InvalidInt(format!("`{}` is not a valid int", val))),
+            (DataType::Bool, VarValue::Bool(val)) => Ok(VarValue::Bool(val)),
+            (DataType::Bool, VarValue::Str(val)) => val
+                .trim()
+                .parse::<bool>()
+                .map(VarValue::Bool)
+                .map_err(|_| VarErr::InvalidBool(format!("`{}` is not a valid boolean", val))),
+            (DataType::List, VarValue::List(items)) => Ok(VarValue::List(items)),
+            (DataType::Map, VarValue::Map(fields)) => Ok(VarValue::Map(fields)),
+            (DataType::Int, other) => Err(VarErr::InvalidInt(format!(
+                "`{}` is not a valid int",
+                other
+            ))),
+            (DataType::Bool, other) => Err(VarErr::InvalidBool(format!(
+                "`{}` is not a valid boolean",
+                other
+            ))),
+            (DataType::List, other) => Err(VarErr::InvalidList(format!(
+                "`{}` is not a valid list",
+                other
+            ))),
+            (DataType::Map, other) => Err(VarErr::InvalidMap(format!(
+                "`{}` is not a valid map",
+                other
+            ))),
+        }
+    }
+}
+
+/// Split a reference into its variable and the path into the variable's value
+///
+/// Example
+/// ```text
+/// err.message                  =>  ("err", ["message"])
+/// cfg["servers"][0]["host"]    =>  ("cfg", ["servers", "0", "host"])
+/// cfg.servers[0].host          =>  ("cfg", ["servers", "0", "host"])
+/// ```
+/// Returns `None` if the path is malformed `ex: cfg[0`
+pub fn split_path(s: &str) -> Option<(&str, Vec<String>)> {
+    let end = s.find(['.', '[']).unwrap_or(s.len());
+    let (base, mut rest) = s.split_at(end);
+    if base.is_empty() {
+        return None;
+    }
+
+    let mut path = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return None;
+            }
+            path.push(after[..end].to_string());
+            rest = &after[end..];
+        } else {
+            let after = rest.strip_prefix('[')?;
+            let (key, after) = match after.chars().next()? {
+                quote @ ('"' | '\'') => {
+                    let end = after[1..].find(quote)? + 1;
+                    (&after[1..end], after[end + 1..].strip_prefix(']')?)
+                }
+                _ => {
+                    let (index, after) = after.split_once(']')?;
+                    index.parse::<usize>().ok()?;
+                    (index, after)
+                }
+            };
+            path.push(key.to_string());
+            rest = after;
+        }
+    }
+
+    Some((base, path))
+}
+
+/// The declared type of a variable `ex: name: str = "Jone"`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum DataType {
+    Int,
+    Str,
+    Bool,
+    List,
+    Map,
+}
+
+impl FromStr for DataType {
+    type Err = VarErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let datatype = match s.trim() {
+            "str" | "string" => Self::Str,
+            "int" => Self::Int,
+            "bool" => Self::Bool,
+            "list" => Self::List,
+            "map" => Self::Map,
+            _ => return Err(VarErr::InvalidDataType(s.to_string())),
         };
 
-        Ok(res)
+        Ok(datatype)
     }
 }
 
-impl Display for VarValue {
+impl Display for DataType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            VarValue::Int(val) => write!(f, "{}", val),
-            VarValue::Str(val) => write!(f, "{}", val),
-            VarValue::Bool(val) => write!(f, "{}", val),
-        }
+        let datatype = match self {
+            DataType::Int => "int",
+            DataType::Str => "str",
+            DataType::Bool => "bool",
+            DataType::List => "list",
+            DataType::Map => "map",
+        };
+        write!(f, "{}", datatype)
     }
 }
 
@@ -48,37 +230,42 @@ pub enum VarErr {
     InvalidInt(String),
     #[error("`{0}` is not a valid variable declaration")]
     InvlaidVarDeclaration(String),
-    #[error("`{0}` is not valid datatypes\nDatatype: [Int, Str, String]")]
+    #[error("`{0}` is not valid datatypes\nDatatype: [int, str, string, bool, list, map]")]
     InvalidDataType(String),
     #[error("`{0}` is not valid boolean")]
     InvalidBool(String),
+    #[error("`{0}` is not a valid list")]
+    InvalidList(String),
+    #[error("`{0}` is not a valid map")]
+    InvalidMap(String),
+    #[error("Invalid function call: {0}")]
+    InvalidCall(String),
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Variable {
     pub name: String,
     pub value: VarValue,
+    /// The value as a template, parsed once when the variable is declared
+    pub template: Template,
 }
 
 impl Variable {
     pub fn new<T: Into<String> + Display>(name: T, value: VarValue) -> Self {
         Self {
             name: name.to_string(),
+            template: Template::parse(value.to_string()),
             value,
         }
     }
 
     pub fn is_var(s: &str) -> bool {
-        let re = Regex::new(RE_VAR).unwrap();
+        let re = cached_regex!(RE_VAR);
         re.is_match(s)
     }
 
     pub fn is_int(&self) -> bool {
-        if let VarValue::Int(_) = self.value {
-            true
-        } else {
-            false
-        }
+        matches!(self.value, VarValue::Int(_))
     }
 }
 
@@ -86,16 +273,15 @@ impl FromStr for Variable {
     type Err = VarErr;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let re = Regex::new(RE_VAR).unwrap();
+        let re = cached_regex!(RE_VAR);
 
         if let Some(caps) = re.captures(s) {
             let name = caps["name"].trim().to_string();
             let mut value = caps["value"].trim().to_string();
-            if !is_input_fn(&value.trim()) {
-                let re = Regex::new(RE_QUOTE_REMOVAL).unwrap();
-                if let Some(caps) = re.captures(&value) {
-                    value = caps["value"].to_string()
-                }
+            let re = cached_regex!(RE_QUOTE_REMOVAL);
+            let is_path = cached_regex!(RE_VAR_PATH).is_match(&value);
+            if let Some(caps) = re.captures(&value).filter(|_| !is_path) {
+                value = caps["value"].to_string()
             }
             let data = match &caps["type"] {
                 "str" | "string" => VarValue::Str(value),
@@ -120,7 +306,53 @@ impl FromStr for Variable {
                 _ => return Err(VarErr::InvalidDataType(caps["type"].to_string())),
             };
 
-            Ok(Variable { name, value: data })
+            Ok(Variable::new(name, data))
+        } else {
+            Err(VarErr::InvlaidVarDeclaration(s.to_string()))
+        }
+    }
+}
+
+/// A variable declaration whose value is computed at runtime by a function call
+///
+/// Example
+/// ```text
+/// content: str = read_file("notes.txt")
+/// files: list = ls(".")
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct CallVariable {
+    pub name: String,
+    pub datatype: DataType,
+    pub call: Call,
+}
+
+impl CallVariable {
+    pub fn is_call_var(s: &str) -> bool {
+        let re = cached_regex!(RE_VAR);
+        match re.captures(s) {
+            Some(caps) => Call::is_call(&caps["value"]),
+            None => false,
+        }
+    }
+}
+
+impl FromStr for CallVariable {
+    type Err = VarErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let re = cached_regex!(RE_VAR);
+
+        if let Some(caps) = re.captures(s) {
+            let call = caps["value"]
+                .parse::<Call>()
+                .map_err(|e| VarErr::InvalidCall(e.to_string()))?;
+
+            Ok(Self {
+                name: caps["name"].trim().to_string(),
+                datatype: caps["type"].parse::<DataType>()?,
+                call,
+            })
         } else {
             Err(VarErr::InvlaidVarDeclaration(s.to_string()))
         }
@@ -131,6 +363,17 @@ impl FromStr for Variable {
 mod test {
     use super::*;
 
+    #[test]
+    fn parse_literals() {
+        assert_eq!(VarValue::from_literal("42"), VarValue::Int(42));
+        assert_eq!(VarValue::from_literal("false"), VarValue::Bool(false));
+        assert_eq!(
+            VarValue::from_literal("9999999999"),
+            VarValue::Str("9999999999".into())
+        );
+        assert_eq!(VarValue::from_literal(""), VarValue::Str("".into()));
+    }
+
     #[test]
     fn create_new_str_var() {
         let var_name = "name".to_string();
@@ -156,13 +399,7 @@ mod test {
         let expr = "name: str = \"Jone\"";
         let var = expr.parse::<Variable>().unwrap();
 
-        assert_eq!(
-            var,
-            Variable {
-                name: "name".into(),
-                value: VarValue::Str("Jone".into())
-            }
-        )
+        assert_eq!(var, Variable::new("name", VarValue::Str("Jone".into())))
     }
 
     #[test]
@@ -172,10 +409,7 @@ mod test {
 
         assert_eq!(
             var,
-            Variable {
-                name: "email".into(),
-                value: VarValue::Str("something@whatmatter.com".into())
-            }
+            Variable::new("email", VarValue::Str("something@whatmatter.com".into()))
         )
     }
 
@@ -184,13 +418,7 @@ mod test {
         let expr = "age: int = 31";
         let var = expr.parse::<Variable>().unwrap();
 
-        assert_eq!(
-            var,
-            Variable {
-                name: "age".into(),
-                value: VarValue::Int(31)
-            }
-        )
+        assert_eq!(var, Variable::new("age", VarValue::Int(31)))
     }
 
     #[test]
@@ -198,12 +426,66 @@ mod test {
         let expr = "is_married: bool = false";
         let var = expr.parse::<Variable>().unwrap();
 
+        assert_eq!(var, Variable::new("is_married", VarValue::Bool(false)))
+    }
+
+    #[test]
+    fn new_call_var() {
+        let expr = "files: list = ls(\".\")";
+        assert!(CallVariable::is_call_var(expr));
+        assert!(!CallVariable::is_call_var("name: str = \"Jone\""));
+
+        let var = expr.parse::<CallVariable>().unwrap();
         assert_eq!(
             var,
-            Variable {
-                name: "is_married".into(),
-                value: VarValue::Bool(false)
+            CallVariable {
+                name: "files".into(),
+                datatype: DataType::List,
+                call: Call::new("ls", vec!["\".\"".into()])
             }
         )
     }
+
+    #[test]
+    fn coerce_values_to_datatype() {
+        let value = VarValue::Str("42".into());
+        assert_eq!(value.clone().coerce(&DataType::Int), Ok(VarValue::Int(42)));
+        assert_eq!(value.clone().coerce(&DataType::Str), Ok(value.clone()));
+        assert!(value.coerce(&DataType::Bool).is_err());
+
+        let list = VarValue::List(vec![VarValue::Int(1), VarValue::Str("a".into())]);
+        assert_eq!(list.to_string(), "[1, a]");
+        assert_eq!(
+            list.clone().coerce(&DataType::Str),
+            Ok(VarValue::Str("[1, a]".into()))
+        );
+        assert!(VarValue::Int(1).coerce(&DataType::List).is_err());
+    }
+
+    #[test]
+    fn split_and_follow_paths() {
+        let path = |s: &str| split_path(s).map(|(base, path)| (base.to_string(), path));
+        let expected = Some((
+            "cfg".to_string(),
+            vec!["servers".into(), "0".into(), "host".into()],
+        ));
+        assert_eq!(path("cfg[\"servers\"][0]['host']"), expected);
+        assert_eq!(path("cfg.servers[0].host"), expected);
+        assert_eq!(path("name"), Some(("name".into(), vec![])));
+        for invalid in ["cfg[0", "cfg[x]", "cfg.", "cfg[\"a]", ".a"] {
+            assert_eq!(split_path(invalid), None, "`{}` should be invalid", invalid);
+        }
+
+        let cfg = VarValue::Map(BTreeMap::from([(
+            "servers".to_string(),
+            VarValue::List(vec![VarValue::Str("a.local".into())]),
+        )]));
+        let path = |path: &[&str]| path.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            cfg.get_path(&path(&["servers", "0"])),
+            Some(&VarValue::Str("a.local".into()))
+        );
+        assert_eq!(cfg.get_path(&path(&["servers", "1"])), None);
+        assert_eq!(cfg.get_path(&path(&["servers", "host"])), None);
+    }
 }
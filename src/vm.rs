@@ -0,0 +1,407 @@
+use crate::{
+    bytecode::{Name, Op, Part, Program},
+    cmp::CompareExpr,
+    executor::{parse_operand, ExeError, Executor},
+    variables::{split_path, VarValue},
+};
+use std::collections::HashMap;
+
+/// An active `try` block
+struct Handler {
+    catch: usize,
+    slot: usize,
+    stack_len: usize,
+    depth: usize,
+}
+
+/// A stack machine running compiled programs. The executor provides the script's
+/// environment (arguments, streams, sandbox and limits) and keeps the variables between runs
+pub struct Vm<'a> {
+    exe: &'a mut Executor,
+    program: &'a Program,
+    slots: Vec<Option<VarValue>>,
+    /// Slot of every variable name, only used to resolve names built at runtime
+    names: HashMap<&'a str, usize>,
+    stack: Vec<VarValue>,
+    handlers: Vec<Handler>,
+    depth: usize,
+    /// Bytes held by the slots
+    used_bytes: usize,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(exe: &'a mut Executor, program: &'a Program) -> Self {
+        let slots = program
+            .names
+            .iter()
+            .map(|name| exe.vars.get(name).cloned())
+            .collect::<Vec<_>>();
+        let used_bytes = slots.iter().flatten().map(VarValue::size).sum();
+        let names = program
+            .names
+            .iter()
+            .enumerate()
+            .map(|(slot, name)| (name.as_str(), slot))
+            .collect();
+
+        Self {
+            exe,
+            program,
+            slots,
+            names,
+            stack: Vec::new(),
+            handlers: Vec::new(),
+            depth: 0,
+            used_bytes,
+        }
+    }
+
+    /// Run the program, the variables are written back to the executor even if it fails
+    pub fn run(mut self) -> Result<(), ExeError> {
+        self.exe.begin();
+        let result = self.eval();
+        while self.depth > 0 {
+            self.leave();
+        }
+
+        for (name, value) in self.program.names.iter().zip(self.slots) {
+            if let Some(value) = value {
+                self.exe.insert_var(name.clone(), value);
+            }
+        }
+
+        result
+    }
+
+    fn eval(&mut self) -> Result<(), ExeError> {
+        let mut pc = 0;
+        while let Some(op) = self.program.ops.get(pc) {
+            pc += 1;
+            if let Err(err) = self.eval_op(op, &mut pc) {
+                match self.handlers.pop() {
+                    Some(handler) if err.is_catchable() => {
+                        self.stack.truncate(handler.stack_len);
+                        while self.depth > handler.depth {
+                            self.leave();
+                        }
+                        let err = self.exe.err_value(&err);
+                        self.store(handler.slot, err)?;
+                        pc = handler.catch;
+                    }
+                    _ => return Err(err),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn eval_op(&mut self, op: &Op, pc: &mut usize) -> Result<(), ExeError> {
+        match op {
+            Op::Line(line) => {
+                self.exe.line = *line;
+                self.exe.step()?;
+            }
+            Op::Push(value) => self.stack.push(value.clone()),
+            Op::Load(name, fallback) => {
+                let value = self.load(name)?.unwrap_or_else(|| fallback.clone());
+                self.stack.push(value);
+            }
+            Op::Expand(parts) => {
+                let mut s = String::new();
+                for part in parts {
+                    match part {
+                        Part::Lit(lit) => s.push_str(lit),
+                        Part::Var(name, raw) => {
+                            let value = match self.load(name)? {
+                                Some(value) => value,
+                                None => self.exe.special_var(raw)?,
+                            };
+                            s.push_str(&value.to_string());
+                        }
+                    }
+                }
+                self.stack.push(VarValue::Str(s));
+            }
+            Op::Resolve => {
+                let s = self.pop().to_string();
+                let value = self.resolve(&s)?;
+                self.stack.push(value);
+            }
+            Op::ParseOperand => {
+                let s = self.pop().to_string();
+                self.stack.push(parse_operand(s));
+            }
+            Op::Store(slot) => {
+                let value = self.pop();
+                self.store(*slot, value)?;
+            }
+            Op::Echo => {
+                let value = self.pop();
+                self.exe.write_stdout(format!("{}\n", value).as_bytes())?;
+            }
+            Op::Compare(operator) => {
+                let right = self.pop();
+                let left = self.pop();
+                let is_true = CompareExpr::cmp(&left, &right, operator)?;
+                self.stack.push(VarValue::Bool(is_true));
+            }
+            Op::JumpIfFalse(to) => {
+                if self.pop() == VarValue::Bool(false) {
+                    *pc = *to;
+                }
+            }
+            Op::Jump(to) => *pc = *to,
+            Op::Throw => return Err(ExeError::Thrown(self.pop().to_string())),
+            Op::TryStart { catch, slot } => self.handlers.push(Handler {
+                catch: *catch,
+                slot: *slot,
+                stack_len: self.stack.len(),
+                depth: self.depth,
+            }),
+            Op::TryEnd => {
+                self.handlers.pop();
+            }
+            Op::Enter => {
+                self.exe.enter()?;
+                self.depth += 1;
+            }
+            Op::Leave => self.leave(),
+        }
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> VarValue {
+        self.stack
+            .pop()
+            .expect("the compiler keeps the stack balanced")
+    }
+
+    fn leave(&mut self) {
+        self.exe.leave();
+        self.depth -= 1;
+    }
+
+    /// Assign a slot within the memory limit
+    fn store(&mut self, slot: usize, value: VarValue) -> Result<(), ExeError> {
+        self.exe.check_writable(&self.program.names[slot])?;
+        let total =
+            self.used_bytes - self.slots[slot].as_ref().map_or(0, VarValue::size) + value.size();
+        if let Some(max_memory) = self.exe.limits.max_memory {
+            if total > max_memory {
+                return Err(ExeError::MemoryLimit(max_memory));
+            }
+        }
+        self.used_bytes = total;
+        self.slots[slot] = Some(value);
+
+        Ok(())
+    }
+
+    /// Look up a variable the script reads, every slot that isn't set yet belongs to a
+    /// declaration that didn't run
+    fn load(&self, name: &Name) -> Result<Option<VarValue>, ExeError> {
+        match name {
+            Name::Slot(slot) | Name::Field(slot, _) if self.slots[*slot].is_none() => {
+                Err(ExeError::Undeclared(self.program.names[*slot].clone()))
+            }
+            name => self.lookup(name),
+        }
+    }
+
+    fn lookup(&self, name: &Name) -> Result<Option<VarValue>, ExeError> {
+        let value = match name {
+            Name::Slot(slot) => self.slots[*slot].clone(),
+            Name::Field(slot, path) => self.slots[*slot]
+                .as_ref()
+                .and_then(|value| value.get_path(path))
+                .cloned(),
+            Name::Special(var) => Some(self.exe.special_var(var)?),
+        };
+
+        Ok(value)
+    }
+
+    /// The variable named by a string built at runtime, or the string parsed as a value
+    fn resolve(&self, s: &str) -> Result<VarValue, ExeError> {
+        let name = match self.names.get(s) {
+            Some(slot) => Some(Name::Slot(*slot)),
+            None => split_path(s)
+                .filter(|(_, path)| !path.is_empty())
+                .and_then(|(base, path)| Some(Name::Field(*self.names.get(base)?, path))),
+        };
+        if let Some(value) = name.map(|name| self.lookup(&name)).transpose()?.flatten() {
+            return Ok(value);
+        }
+
+        match s.strip_prefix('$') {
+            Some(var) => self.exe.special_var(var),
+            None => Ok(VarValue::from_literal(s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        interpreter::{Interpreter, InterpreterErr},
+        limits::Limits,
+        streams::OutputBuffer,
+        variables::VarValue,
+    };
+    use std::fs;
+
+    /// Everything observable about a run: the output, the variables and the error
+    #[derive(Debug, PartialEq)]
+    struct Outcome {
+        stdout: String,
+        vars: Vec<(String, Option<VarValue>)>,
+        err: Option<String>,
+    }
+
+    fn run(source: &str, vm: bool, limits: Limits) -> Result<Outcome, InterpreterErr> {
+        let out = OutputBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_vm(vm);
+        interpreter.set_limits(limits);
+        interpreter.set_stdout(out.clone());
+        interpreter.set_args(["foo", "bar"]);
+        interpreter.set_var("preset", VarValue::Int(7));
+
+        let err = match interpreter.eval_str(source) {
+            Ok(()) => None,
+            Err(err @ InterpreterErr::ExeError { .. }) => Some(err.to_string()),
+            Err(err) => return Err(err),
+        };
+        let names = ["preset", "name", "age", "res", "greeting", "err", "e", "x"];
+        Ok(Outcome {
+            stdout: out.contents(),
+            vars: names
+                .iter()
+                .map(|name| (name.to_string(), interpreter.get_var(name).cloned()))
+                .collect(),
+            err,
+        })
+    }
+
+    /// Run `source` on the tree-walker and the VM and expect the same outcome
+    fn differential(source: &str, limits: Limits) {
+        let expected = run(source, false, limits).unwrap();
+        let found = run(source, true, limits).unwrap();
+        assert_eq!(found, expected, "VM and tree-walker differ on:\n{}", source);
+    }
+
+    const SCRIPTS: &[&str] = &[
+        "age: int = 30\necho age\nif age > 40\ndo echo \"I am old\"\nelse\ndo echo \"I am still young\"\nendif",
+        "name: str = Jone\ngreeting: str = \"Hello, ${name} $1 $2 [$3]\"\necho greeting",
+        "echo $0\necho $1\nif $1 == 'foo'\ndo echo \"got foo\"\nendif",
+        "res: int = (12 + 12) / 4\necho res\nx: str = preset\necho \"preset is $preset\"",
+        "name: str = \"server.log\"\nif name == \"*.log\"\ndo echo \"log\"\nendif\nif name ~= \"SERVER.LOG\"\ndo echo \"same\"\nendif",
+        "if \"apple\" < \"banana\"\ndo echo \"apple first\"\nelse\ndo echo \"banana first\"\nendif",
+        "if 3 > 4\ndo echo 'never'\nendif\nif true == true\ndo x: int = 1\nendif",
+        "try\nthrow \"bad $1\"\necho 'skipped'\ncatch e\necho e.message\necho \"line ${e.line}\"\nend",
+        "try\ntry\nthrow 'inner'\ncatch\nthrow \"outer: $err.message\"\nend\ncatch\necho err.message\nend",
+        "age: int = 3\nif age > \"abc\"\ndo echo 'never'\nendif",
+        "name: str = Jone\nthrow \"failed for $name\"",
+        "x: str = \"$name\"\nname: str = Jone",
+        "echo name\nname: str = Jone",
+        "if $1 == 'bar'\ndo x: int = 1\nendif\necho x",
+        "echo \"$e.kind\"\ntry\nthrow 'x'\ncatch e\nres: str = \"${e.kind}\"\nend",
+    ];
+
+    #[test]
+    fn vm_matches_tree_walker() {
+        for script in SCRIPTS {
+            differential(script, Limits::default());
+        }
+    }
+
+    #[test]
+    fn vm_matches_tree_walker_on_limits() {
+        let script = SCRIPTS[7];
+        for limits in [
+            Limits {
+                max_steps: Some(3),
+                ..Limits::default()
+            },
+            Limits {
+                max_depth: Some(1),
+                ..Limits::default()
+            },
+            Limits {
+                max_output: Some(10),
+                ..Limits::default()
+            },
+            Limits {
+                max_memory: Some(12),
+                ..Limits::default()
+            },
+        ] {
+            differential(script, limits);
+        }
+    }
+
+    #[test]
+    fn vm_matches_tree_walker_on_examples() {
+        let mut compiled = Vec::new();
+        for entry in fs::read_dir("lang").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "mb") {
+                continue;
+            }
+            let source = fs::read_to_string(&path).unwrap();
+            let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+            match run(&source, true, Limits::default()) {
+                Ok(_) => {
+                    differential(&source, Limits::default());
+                    compiled.push(name);
+                }
+                Err(InterpreterErr::CompileErr(_)) => {}
+                Err(err) => panic!("{}: {}", name, err),
+            }
+        }
+
+        // The others use statements the VM doesn't support yet
+        compiled.sort();
+        assert_eq!(
+            compiled,
+            [
+                "script_1",
+                "script_11",
+                "script_2",
+                "script_3",
+                "script_4",
+                "script_5",
+                "script_6",
+                "script_7",
+                "script_8",
+                "script_9"
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_readonly_stores() {
+        for decl in ["const X: int = 1", "readonly X: int = 1"] {
+            let mut interpreter = Interpreter::new();
+            interpreter.eval_str(decl).unwrap();
+            interpreter.set_vm(true);
+            let err = interpreter.eval_str("X: int = 2").err().unwrap();
+
+            assert!(err.to_string().contains("`X`"), "{}", err);
+            assert_eq!(interpreter.get_var("X"), Some(&VarValue::Int(1)));
+        }
+    }
+
+    #[test]
+    fn report_unsupported_statements() {
+        let err = run("echo 'ok'\nname: str = input()", true, Limits::default())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Compile error: `input()` at line 2 is not supported by the VM yet"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Golden output tests for the `lang/` example scripts
+//!
+//! Every `lang/<name>.mb` script runs in-process with the inputs of its sibling files and
+//! its stdout, stderr and exit code are compared with `<name>.expected`
+//!
+//! ```text
+//! <name>.args      one argument per line
+//! <name>.stdin     fed to `input()`
+//! <name>.env       `KEY=VALUE` lines, set while the script runs
+//! <name>.expected  the snapshot
+//! ```
+//! Scripts run from an empty temporary directory holding a copy of the script, so `$0` is
+//! the file name and files they create don't end up in the repository.
+//! `cargo test --test golden -- --bless` rewrites the snapshots, other arguments filter
+//! the scripts by name
+use mybash::{testing::diff, ExeError, Interpreter, InterpreterErr, OutputBuffer};
+use std::{
+    env, fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    process,
+};
+
+const LANG_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/lang");
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let bless = args.iter().any(|arg| arg == "--bless");
+    let filters = args
+        .iter()
+        .filter(|arg| !arg.starts_with('-'))
+        .collect::<Vec<_>>();
+
+    let mut scripts = fs::read_dir(LANG_DIR)
+        .expect("lang/ is readable")
+        .map(|entry| entry.expect("lang/ is readable").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "mb"))
+        .filter(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy();
+            filters.is_empty() || filters.iter().any(|filter| name.contains(filter.as_str()))
+        })
+        .collect::<Vec<_>>();
+    scripts.sort();
+
+    let mut failed = 0;
+    for script in &scripts {
+        let name = script.file_stem().unwrap().to_string_lossy();
+        let actual = run(script);
+        let expected_path = script.with_extension("expected");
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+
+        if actual == expected {
+            println!("golden {} ... ok", name);
+        } else if bless {
+            fs::write(&expected_path, &actual).expect("the snapshot is writable");
+            println!("golden {} ... blessed", name);
+        } else {
+            println!("golden {} ... FAILED\n{}\n", name, diff(&expected, &actual));
+            failed += 1;
+        }
+    }
+
+    println!(
+        "\ngolden result: {} passed; {} failed",
+        scripts.len() - failed,
+        failed
+    );
+    if failed > 0 {
+        println!("run `cargo test --test golden -- --bless` to update the snapshots");
+        process::exit(1);
+    }
+}
+
+/// Run `script` with the inputs of its sibling files and render the snapshot
+fn run(script: &Path) -> String {
+    let args = read_sibling(script, "args");
+    let stdin = read_sibling(script, "stdin");
+    let vars = read_sibling(script, "env")
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+        .collect::<Vec<_>>();
+
+    let file_name = script.file_name().unwrap();
+    let dir = env::temp_dir().join(format!(
+        "mybash-golden-{}-{}",
+        process::id(),
+        file_name.to_string_lossy()
+    ));
+    fs::create_dir_all(&dir).expect("the temporary directory is writable");
+    fs::copy(script, dir.join(file_name)).expect("the script is readable");
+    let cwd = env::current_dir().expect("the current directory exists");
+    env::set_current_dir(&dir).expect("the temporary directory exists");
+    let saved = vars
+        .iter()
+        .map(|(key, value)| {
+            let saved = env::var(key).ok();
+            env::set_var(key, value);
+            (key, saved)
+        })
+        .collect::<Vec<_>>();
+
+    let stdout = OutputBuffer::default();
+    let stderr = OutputBuffer::default();
+    let mut interpreter = Interpreter::new();
+    interpreter.set_args(args.lines());
+    interpreter.set_stdin(Cursor::new(stdin.into_bytes()));
+    interpreter.set_stdout(stdout.clone());
+    interpreter.set_stderr(stderr.clone());
+    let result = interpreter.eval_file(file_name);
+    drop(interpreter);
+
+    for (key, saved) in saved {
+        match saved {
+            Some(value) => env::set_var(key, value),
+            None => env::remove_var(key),
+        }
+    }
+    env::set_current_dir(cwd).expect("the previous directory exists");
+    fs::remove_dir_all(&dir).expect("the temporary directory is removable");
+
+    let mut stderr = stderr.contents();
+    let exit_code = match result {
+        Ok(()) => 0,
+        Err(InterpreterErr::ExeError {
+            err: ExeError::Signal(event),
+            ..
+        }) => event.exit_status(),
+        Err(err) => {
+            stderr.push_str(&format!("Error: {}\n", err));
+            1
+        }
+    };
+
+    format!(
+        "--- stdout\n{}--- stderr\n{}--- exit code\n{}\n",
+        with_newline(stdout.contents()),
+        with_newline(stderr),
+        exit_code
+    )
+}
+
+fn read_sibling(script: &Path, extension: &str) -> String {
+    let path: PathBuf = script.with_extension(extension);
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+/// Sections always end with a newline so they stay on their own lines
+fn with_newline(mut s: String) -> String {
+    if !s.is_empty() && !s.ends_with('\n') {
+        s.push('\n');
+    }
+    s
+}